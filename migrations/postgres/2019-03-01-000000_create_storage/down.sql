@@ -0,0 +1,3 @@
+DROP TABLE poller_cursors;
+DROP TABLE transactions;
+DROP TABLE blocks;
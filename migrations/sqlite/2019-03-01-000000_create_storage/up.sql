@@ -0,0 +1,26 @@
+CREATE TABLE blocks (
+    currency TEXT NOT NULL,
+    height BIGINT NOT NULL,
+    hash TEXT NOT NULL,
+    block_time TIMESTAMP,
+    scanned_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (currency, height)
+);
+
+CREATE TABLE transactions (
+    currency TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    block_number BIGINT,
+    block_hash TEXT,
+    payload TEXT NOT NULL,
+    published_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (currency, hash)
+);
+
+CREATE INDEX transactions_currency_block_hash_idx ON transactions (currency, block_hash);
+
+CREATE TABLE poller_cursors (
+    currency TEXT PRIMARY KEY,
+    block_number BIGINT NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
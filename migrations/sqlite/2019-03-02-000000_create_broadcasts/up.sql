@@ -0,0 +1,10 @@
+CREATE TABLE broadcasts (
+    currency TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    status TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (currency, hash)
+);
+
+CREATE INDEX broadcasts_status_updated_at_idx ON broadcasts (status, updated_at);
@@ -0,0 +1,300 @@
+//! Authentication of API clients, checked before routing.
+//!
+//! Clients authenticate either with a static token in `Authorization: Bearer <token>` header,
+//! or by signing requests. Signed requests carry `X-Client-Id`, `X-Timestamp` (unix seconds),
+//! `X-Nonce` and `X-Signature` headers, see `sign` for the signed payload.
+
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+
+use chrono::Utc;
+use failure::Fail;
+use hyper::header::{HeaderMap, HeaderValue};
+use hyper::{Method, Uri};
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+
+use super::error::*;
+use client::bytes_to_hex;
+use config::{Auth, AuthClient, Scope};
+
+pub const CLIENT_ID_HEADER: &str = "X-Client-Id";
+pub const TIMESTAMP_HEADER: &str = "X-Timestamp";
+pub const NONCE_HEADER: &str = "X-Nonce";
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+
+pub struct Authenticator {
+    clients: Vec<AuthClient>,
+    signature_max_age_secs: i64,
+    // nonces of signed requests that are not expired yet, to reject replayed requests
+    nonces: Mutex<HashMap<(String, String), i64>>,
+}
+
+impl Authenticator {
+    /// Fails if environment variable with token or secret of a client is not set
+    pub fn new(config: &Auth) -> Result<Self, Error> {
+        let clients = config.clients.iter().map(resolve_credentials).collect::<Result<_, _>>()?;
+        Ok(Self {
+            clients,
+            signature_max_age_secs: config.signature_max_age_secs as i64,
+            nonces: Default::default(),
+        })
+    }
+
+    /// Returns id of authenticated client, or `None` if route doesn't require authentication
+    pub fn authenticate(&self, method: &Method, uri: &Uri, headers: &HeaderMap<HeaderValue>, body: &[u8]) -> Result<Option<String>, Error> {
+        self.authenticate_at(method, uri, headers, body, Utc::now().timestamp())
+    }
+
+    fn authenticate_at(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap<HeaderValue>,
+        body: &[u8],
+        now: i64,
+    ) -> Result<Option<String>, Error> {
+        let scope = match required_scope(method, uri.path()) {
+            Some(scope) => scope,
+            None => return Ok(None),
+        };
+        let client = match header(headers, SIGNATURE_HEADER) {
+            Some(signature) => self.verify_signature(signature, method, uri, headers, body, now)?,
+            None => self.verify_token(headers)?,
+        };
+        if !client.scopes.contains(&scope) {
+            return Err(ectx!(err ErrorContext::AuthScope, ErrorKind::Forbidden => client.id, scope));
+        }
+        Ok(Some(client.id.clone()))
+    }
+
+    fn verify_token(&self, headers: &HeaderMap<HeaderValue>) -> Result<&AuthClient, Error> {
+        let token = header(headers, "Authorization")
+            .and_then(|value| {
+                if value.starts_with("Bearer ") {
+                    Some(value["Bearer ".len()..].trim())
+                } else {
+                    None
+                }
+            })
+            .ok_or_else(|| ectx!(try err ErrorContext::AuthCredentials, ErrorKind::Unauthorized))?;
+        self.clients
+            .iter()
+            .find(|client| client.token.as_ref().map(|expected| secure_eq(expected, token)).unwrap_or(false))
+            .ok_or_else(|| ectx!(err ErrorContext::AuthCredentials, ErrorKind::Unauthorized))
+    }
+
+    fn verify_signature(
+        &self,
+        signature: &str,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap<HeaderValue>,
+        body: &[u8],
+        now: i64,
+    ) -> Result<&AuthClient, Error> {
+        let client_id = header(headers, CLIENT_ID_HEADER).unwrap_or_default();
+        let client = self
+            .clients
+            .iter()
+            .find(|client| client.id == client_id && client.secret.is_some())
+            .ok_or_else(|| ectx!(try err ErrorContext::AuthCredentials, ErrorKind::Unauthorized => client_id))?;
+        let secret = client.secret.as_ref().map(String::as_str).unwrap_or_default();
+        let timestamp = header(headers, TIMESTAMP_HEADER).and_then(|value| value.parse::<i64>().ok());
+        let nonce = header(headers, NONCE_HEADER).unwrap_or_default();
+        let timestamp = match timestamp {
+            Some(timestamp) if (now - timestamp).abs() <= self.signature_max_age_secs && !nonce.is_empty() => timestamp,
+            _ => return Err(ectx!(err ErrorContext::AuthSignature, ErrorKind::Unauthorized => client_id, timestamp, now, nonce)),
+        };
+        let expected = sign(secret, method, uri, timestamp, nonce, body)?;
+        if !secure_eq(&expected, signature) {
+            return Err(ectx!(err ErrorContext::AuthSignature, ErrorKind::Unauthorized => client_id));
+        }
+        // nonces are checked only after signature, so that forged requests can't fill the cache
+        let mut nonces = self.nonces.lock().unwrap();
+        let max_age = self.signature_max_age_secs;
+        nonces.retain(|_, seen_at| now - *seen_at <= max_age);
+        if nonces.insert((client.id.clone(), nonce.to_string()), timestamp).is_some() {
+            return Err(ectx!(err ErrorContext::AuthSignature, ErrorKind::Unauthorized => client_id, nonce));
+        }
+        Ok(client)
+    }
+}
+
+/// Scope required by route, `None` for probes and metrics that are served without authentication
+pub fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    if !path.starts_with("/v1/") {
+        None
+    } else if path.starts_with("/v1/admin/") {
+        Some(Scope::Admin)
+    } else if *method == Method::POST && path.ends_with("/transactions/raw") {
+        Some(Scope::Broadcast)
+    } else if *method != Method::GET && path.ends_with("/nonces") {
+        // reservations are used by signers together with posting transactions
+        Some(Scope::Broadcast)
+    } else {
+        Some(Scope::Read)
+    }
+}
+
+/// Signature of request - hex encoded HMAC-SHA256 of `{method}\n{path and query}\n{timestamp}\n{nonce}\n{body}`
+pub fn sign(secret: &str, method: &Method, uri: &Uri, timestamp: i64, nonce: &str, body: &[u8]) -> Result<String, Error> {
+    let path_and_query = uri.path_and_query().map(|value| value.as_str()).unwrap_or_else(|| uri.path());
+    let mut payload = format!("{}\n{}\n{}\n{}\n", method, path_and_query, timestamp, nonce).into_bytes();
+    payload.extend_from_slice(body);
+    PKey::hmac(secret.as_bytes())
+        .and_then(|key| {
+            let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+            signer.update(&payload)?;
+            signer.sign_to_vec()
+        })
+        .map(|signature| bytes_to_hex(&signature))
+        .map_err(ectx!(ErrorContext::AuthSignature, ErrorKind::Internal))
+}
+
+fn header<'a>(headers: &'a HeaderMap<HeaderValue>, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Comparison in constant time, so that secrets can't be guessed by response timings
+fn secure_eq(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len() && memcmp::eq(expected.as_bytes(), actual.as_bytes())
+}
+
+/// Client with token and secret read from environment variables, if they are configured this way
+fn resolve_credentials(client: &AuthClient) -> Result<AuthClient, Error> {
+    let from_env = |value: &Option<String>, name: &Option<String>| -> Result<Option<String>, Error> {
+        match name {
+            Some(name) => env::var(name)
+                .map(Some)
+                .map_err(ectx!(ErrorContext::Config, ErrorKind::Internal => client.id, name)),
+            None => Ok(value.clone()),
+        }
+    };
+    Ok(AuthClient {
+        token: from_env(&client.token, &client.token_env)?,
+        secret: from_env(&client.secret, &client.secret_env)?,
+        ..client.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authenticator() -> Authenticator {
+        Authenticator::new(&Auth {
+            disabled: false,
+            signature_max_age_secs: 300,
+            clients: vec![
+                AuthClient {
+                    id: "reader".to_string(),
+                    token: Some("read-token".to_string()),
+                    token_env: None,
+                    secret: None,
+                    secret_env: None,
+                    scopes: vec![Scope::Read],
+                },
+                AuthClient {
+                    id: "wallet".to_string(),
+                    token: None,
+                    token_env: None,
+                    secret: Some("secret".to_string()),
+                    secret_env: None,
+                    scopes: vec![Scope::Read, Scope::Broadcast],
+                },
+            ],
+        })
+        .unwrap()
+    }
+
+    fn error_kind(res: Result<Option<String>, Error>) -> String {
+        format!("{:?}", res.unwrap_err().kind())
+    }
+
+    fn signed_headers(uri: &Uri, timestamp: i64, nonce: &str, body: &[u8]) -> HeaderMap<HeaderValue> {
+        let signature = sign("secret", &Method::POST, uri, timestamp, nonce, body).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(CLIENT_ID_HEADER, HeaderValue::from_static("wallet"));
+        headers.insert(TIMESTAMP_HEADER, HeaderValue::from_str(&timestamp.to_string()).unwrap());
+        headers.insert(NONCE_HEADER, HeaderValue::from_str(nonce).unwrap());
+        headers.insert(SIGNATURE_HEADER, HeaderValue::from_str(&signature).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_credentials_from_env() {
+        let client = AuthClient {
+            id: "payments".to_string(),
+            token: None,
+            token_env: Some("BLOCKCHAIN_GATEWAY_TEST_AUTH_TOKEN".to_string()),
+            secret: None,
+            secret_env: None,
+            scopes: vec![Scope::Read],
+        };
+        let auth = Auth {
+            disabled: false,
+            signature_max_age_secs: 300,
+            clients: vec![client],
+        };
+        assert!(Authenticator::new(&auth).is_err(), "variable is not set");
+
+        env::set_var("BLOCKCHAIN_GATEWAY_TEST_AUTH_TOKEN", "env-token");
+        let auth = Authenticator::new(&auth).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("Authorization", HeaderValue::from_static("Bearer env-token"));
+        let res = auth.authenticate_at(&Method::GET, &"/v1/ethereum/aa/balance".parse().unwrap(), &headers, &[], 0);
+        assert_eq!(res.unwrap(), Some("payments".to_string()));
+    }
+
+    #[test]
+    fn test_token() {
+        let auth = authenticator();
+        let balance: Uri = "/v1/ethereum/aa/balance".parse().unwrap();
+        let broadcast: Uri = "/v1/ethereum/transactions/raw".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        let res = auth.authenticate_at(&Method::GET, &balance, &headers, &[], 0);
+        assert_eq!(error_kind(res), "Unauthorized");
+        let res = auth.authenticate_at(&Method::GET, &"/healthz".parse().unwrap(), &headers, &[], 0);
+        assert_eq!(res.unwrap(), None);
+
+        headers.insert("Authorization", HeaderValue::from_static("Bearer read-tokem"));
+        let res = auth.authenticate_at(&Method::GET, &balance, &headers, &[], 0);
+        assert_eq!(error_kind(res), "Unauthorized");
+        headers.insert("Authorization", HeaderValue::from_static("Bearer read-token"));
+        let res = auth.authenticate_at(&Method::GET, &balance, &headers, &[], 0);
+        assert_eq!(res.unwrap(), Some("reader".to_string()));
+        let res = auth.authenticate_at(&Method::POST, &broadcast, &headers, &[], 0);
+        assert_eq!(error_kind(res), "Forbidden");
+        let nonces: Uri = "/v1/ethereum/aa/nonces".parse().unwrap();
+        let res = auth.authenticate_at(&Method::GET, &nonces, &headers, &[], 0);
+        assert_eq!(res.unwrap(), Some("reader".to_string()));
+        let res = auth.authenticate_at(&Method::POST, &nonces, &headers, &[], 0);
+        assert_eq!(error_kind(res), "Forbidden");
+    }
+
+    #[test]
+    fn test_signature() {
+        let auth = authenticator();
+        let uri: Uri = "/v1/ethereum/transactions/raw".parse().unwrap();
+        let body = br#"{"raw": "f86b"}"#;
+        let now = 1_550_000_000;
+
+        let headers = signed_headers(&uri, now - 10, "1", body);
+        let res = auth.authenticate_at(&Method::POST, &uri, &headers, body, now);
+        assert_eq!(res.unwrap(), Some("wallet".to_string()));
+        let res = auth.authenticate_at(&Method::POST, &uri, &headers, body, now);
+        assert_eq!(error_kind(res), "Unauthorized", "replayed request");
+
+        let headers = signed_headers(&uri, now, "2", body);
+        let res = auth.authenticate_at(&Method::POST, &uri, &headers, br#"{"raw": "f86c"}"#, now);
+        assert_eq!(error_kind(res), "Unauthorized", "tampered body");
+
+        let headers = signed_headers(&uri, now - 301, "3", body);
+        let res = auth.authenticate_at(&Method::POST, &uri, &headers, body, now);
+        assert_eq!(error_kind(res), "Unauthorized", "expired timestamp");
+    }
+}
@@ -0,0 +1,46 @@
+use super::super::requests::PostBackfillRequest;
+use super::super::utils::{parse_body, response_with_model};
+use super::Context;
+use super::ControllerFuture;
+use prelude::*;
+
+/// Starts backfill in background, responds with its initial state
+pub fn post_backfills(ctx: &Context) -> ControllerFuture {
+    let backfill_service = ctx.backfill_service.clone();
+    let body = ctx.body.clone();
+    Box::new(parse_body::<PostBackfillRequest>(ctx.body.clone()).and_then(move |input| {
+        backfill_service
+            .start_backfill(input.currency, input.from_height, input.to_height)
+            .map_err(ectx!(convert => body))
+            .and_then(|backfill| response_with_model(&backfill))
+    }))
+}
+
+pub fn get_backfills(ctx: &Context) -> ControllerFuture {
+    Box::new(
+        ctx.backfill_service
+            .get_backfills()
+            .map_err(ectx!(convert))
+            .and_then(|backfills| response_with_model(&backfills)),
+    )
+}
+
+pub fn get_backfill(ctx: &Context, id: String) -> ControllerFuture {
+    let id_clone = id.clone();
+    Box::new(
+        ctx.backfill_service
+            .get_backfill(id)
+            .map_err(ectx!(convert => id_clone))
+            .and_then(|backfill| response_with_model(&backfill)),
+    )
+}
+
+pub fn delete_backfill(ctx: &Context, id: String) -> ControllerFuture {
+    let id_clone = id.clone();
+    Box::new(
+        ctx.backfill_service
+            .cancel_backfill(id)
+            .map_err(ectx!(convert => id_clone))
+            .and_then(|backfill| response_with_model(&backfill)),
+    )
+}
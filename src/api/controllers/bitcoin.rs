@@ -1,40 +1,89 @@
 use super::super::requests::*;
-use super::super::responses::{BalanceResponse, TxHashResponse};
-use super::super::utils::{parse_body, response_with_model};
+use super::super::responses::{BalanceResponse, PostBitcoinTransactionResponse};
+use super::super::utils::{parse_body, parse_tx_hash, response_with_model};
 use super::Context;
 use super::ControllerFuture;
 use models::*;
 use prelude::*;
 
 pub fn get_utxos(ctx: &Context, address: BitcoinAddress) -> ControllerFuture {
+    let bitcoin_service = ctx.bitcoin_service.clone();
     let address_clone = address.clone();
-    Box::new(
-        ctx.bitcoin_service
+    Box::new(ctx.amount_format().into_future().and_then(move |format| {
+        bitcoin_service
             .get_utxos(address)
             .map_err(ectx!(convert => address_clone))
-            .and_then(|utxos| response_with_model(&utxos)),
-    )
+            .and_then(move |utxos| {
+                let utxos: Vec<_> = utxos.into_iter().map(|utxo| format.utxo(utxo)).collect();
+                response_with_model(&utxos)
+            })
+    }))
 }
 
 pub fn get_btc_balance(ctx: &Context, address: BitcoinAddress) -> ControllerFuture {
+    let bitcoin_service = ctx.bitcoin_service.clone();
     let address_clone = address.clone();
-    Box::new(
-        ctx.bitcoin_service
+    Box::new(ctx.amount_format().into_future().and_then(move |format| {
+        bitcoin_service
             .get_balance(address)
             .map_err(ectx!(convert => address_clone))
-            .and_then(|balance| response_with_model(&BalanceResponse { balance })),
+            .and_then(move |balance| {
+                let resp = BalanceResponse {
+                    balance: format.amount(balance, Currency::Btc),
+                };
+                response_with_model(&resp)
+            })
+    }))
+}
+
+/// Balances of many addresses, with errors of addresses that couldn't be queried
+pub fn post_btc_balances(ctx: &Context) -> ControllerFuture {
+    let bitcoin_service = ctx.bitcoin_service.clone();
+    let body = ctx.body.clone();
+    Box::new(
+        ctx.amount_format()
+            .into_future()
+            .and_then(move |format| parse_body::<PostBitcoinBalancesRequest>(body.clone()).map(move |input| (format, input, body)))
+            .and_then(move |(format, input, body)| {
+                bitcoin_service
+                    .get_balances(input.addresses)
+                    .map_err(ectx!(convert => body))
+                    .and_then(move |balances| response_with_model(&format.balances(balances, Currency::Btc)))
+            }),
     )
 }
 
 pub fn post_bitcoin_transactions(ctx: &Context) -> ControllerFuture {
     let bitcoin_service = ctx.bitcoin_service.clone();
+    let broadcast_service = ctx.broadcast_service.clone();
     let body = ctx.body.clone();
     Box::new(
         parse_body::<PostBitcoinTransactionRequest>(ctx.body.clone())
-            .and_then(move |input| bitcoin_service.send_raw_tx(input.raw).map_err(ectx!(convert => body)))
-            .and_then(|hash| {
-                let resp = TxHashResponse { tx_hash: hash };
-                response_with_model(&resp)
-            }),
+            .and_then(move |input| {
+                let raw = input.raw;
+                bitcoin_service
+                    .validate_raw_tx(raw.clone())
+                    .and_then(move |transaction| {
+                        bitcoin_service.send_raw_tx(raw.clone()).and_then(move |tx_hash| {
+                            broadcast_service
+                                .track(Broadcast::bitcoin(raw, &transaction))
+                                .map(move |_| PostBitcoinTransactionResponse { tx_hash, transaction })
+                        })
+                    })
+                    .map_err(ectx!(convert => body))
+            })
+            .and_then(|resp| response_with_model(&resp)),
     )
 }
+
+pub fn get_bitcoin_transaction(ctx: &Context, hash: String) -> ControllerFuture {
+    let bitcoin_service = ctx.bitcoin_service.clone();
+    let input = parse_tx_hash(&hash).and_then(|hash| ctx.amount_format().map(|format| (hash, format)));
+    Box::new(input.into_future().and_then(move |(hash, format)| {
+        let hash_clone = hash.clone();
+        bitcoin_service
+            .get_transaction(hash)
+            .map_err(ectx!(convert => hash_clone))
+            .and_then(move |tx| response_with_model(&format.transaction(tx)))
+    }))
+}
@@ -0,0 +1,38 @@
+use super::super::responses::BlockResponse;
+use super::super::utils::response_with_model;
+use super::Context;
+use super::ControllerFuture;
+use models::*;
+use prelude::*;
+
+pub fn get_btc_block(ctx: &Context, height: u64) -> ControllerFuture {
+    get_block(ctx, Currency::Btc, height)
+}
+
+pub fn get_eth_block(ctx: &Context, height: u64) -> ControllerFuture {
+    get_block(ctx, Currency::Eth, height)
+}
+
+pub fn get_stq_block(ctx: &Context, height: u64) -> ControllerFuture {
+    get_block(ctx, Currency::Stq, height)
+}
+
+/// Block scanned by poller with transactions published from it, read from database
+fn get_block(ctx: &Context, currency: Currency, height: u64) -> ControllerFuture {
+    let block_service = ctx.block_service.clone();
+    Box::new(ctx.amount_format().into_future().and_then(move |format| {
+        block_service
+            .get_block(currency, height)
+            .map_err(ectx!(convert => currency, height))
+            .and_then(move |block| {
+                let resp = BlockResponse {
+                    currency: block.currency,
+                    height: block.height,
+                    hash: block.hash,
+                    time: block.time,
+                    transactions: block.transactions.into_iter().map(|tx| format.transaction(tx)).collect(),
+                };
+                response_with_model(&resp)
+            })
+    }))
+}
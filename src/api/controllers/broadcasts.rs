@@ -0,0 +1,15 @@
+use super::super::utils::{parse_tx_hash, response_with_model};
+use super::Context;
+use super::ControllerFuture;
+use prelude::*;
+
+pub fn get_broadcast(ctx: &Context, hash: String) -> ControllerFuture {
+    let broadcast_service = ctx.broadcast_service.clone();
+    Box::new(parse_tx_hash(&hash).into_future().and_then(move |hash| {
+        let hash_clone = hash.clone();
+        broadcast_service
+            .get_broadcast(hash)
+            .map_err(ectx!(convert => hash_clone))
+            .and_then(|broadcast| response_with_model(&broadcast))
+    }))
+}
@@ -1,59 +1,211 @@
 use super::super::requests::*;
 use super::super::responses::*;
-use super::super::utils::{parse_body, response_with_model};
+use super::super::utils::{parse_body, parse_query, parse_tx_hash, response_with_model};
 use super::Context;
 use super::ControllerFuture;
+use futures::future::{self, Either};
 use models::*;
 use prelude::*;
 
 pub fn get_nonce(ctx: &Context, address: EthereumAddress) -> ControllerFuture {
+    let ethereum_service = ctx.ethereum_service.clone();
     let address_clone = address.clone();
-    Box::new(
-        ctx.ethereum_service
-            .get_nonce(address)
+    Box::new(parse_query::<NonceQuery>(&ctx.uri).into_future().and_then(move |query| {
+        ethereum_service
+            .get_nonce(address, query.block)
             .map_err(ectx!(convert => address_clone))
             .and_then(|nonce| {
                 let resp = NonceResponse { nonce };
                 response_with_model(&resp)
-            }),
+            })
+    }))
+}
+
+pub fn get_nonces(ctx: &Context, address: EthereumAddress) -> ControllerFuture {
+    let address_clone = address.clone();
+    Box::new(
+        ctx.nonce_service
+            .get_nonces(address)
+            .map_err(ectx!(convert => address_clone))
+            .and_then(|state| response_with_model(&state)),
     )
 }
 
-pub fn get_eth_balance(ctx: &Context, address: EthereumAddress) -> ControllerFuture {
+/// Reserves the next nonce for a transaction of address
+pub fn post_nonces(ctx: &Context, address: EthereumAddress) -> ControllerFuture {
     let address_clone = address.clone();
     Box::new(
-        ctx.ethereum_service
-            .get_eth_balance(address)
+        ctx.nonce_service
+            .reserve_nonce(address)
             .map_err(ectx!(convert => address_clone))
-            .and_then(|balance| {
-                let resp = BalanceResponse { balance };
+            .and_then(|nonce| {
+                let resp = NonceResponse { nonce };
                 response_with_model(&resp)
             }),
     )
 }
 
-pub fn get_stq_balance(ctx: &Context, address: EthereumAddress) -> ControllerFuture {
+/// Resets nonces of address to chain state
+pub fn delete_nonces(ctx: &Context, address: EthereumAddress) -> ControllerFuture {
     let address_clone = address.clone();
     Box::new(
-        ctx.ethereum_service
-            .get_stq_balance(address)
+        ctx.nonce_service
+            .reset_nonces(address)
             .map_err(ectx!(convert => address_clone))
-            .and_then(|balance| {
-                let resp = BalanceResponse { balance };
-                response_with_model(&resp)
+            .and_then(|state| response_with_model(&state)),
+    )
+}
+
+pub fn get_eth_balance(ctx: &Context, address: EthereumAddress) -> ControllerFuture {
+    get_balance(ctx, address, Currency::Eth)
+}
+
+pub fn get_stq_balance(ctx: &Context, address: EthereumAddress) -> ControllerFuture {
+    get_balance(ctx, address, Currency::Stq)
+}
+
+fn get_balance(ctx: &Context, address: EthereumAddress, currency: Currency) -> ControllerFuture {
+    let ethereum_service = ctx.ethereum_service.clone();
+    let address_clone = address.clone();
+    let query = ctx
+        .amount_format()
+        .and_then(|format| parse_query::<BalanceQuery>(&ctx.uri).map(|query| (format, query)));
+    Box::new(query.into_future().and_then(move |(format, query)| {
+        let balance = match currency {
+            Currency::Stq => ethereum_service.get_stq_balance(address, query.block),
+            _ => ethereum_service.get_eth_balance(address, query.block),
+        };
+        balance.map_err(ectx!(convert => address_clone)).and_then(move |balance| {
+            let resp = BalanceResponse {
+                balance: format.amount(balance, currency),
+            };
+            response_with_model(&resp)
+        })
+    }))
+}
+
+/// Balances of many addresses at a single block, with errors of addresses that couldn't be queried
+pub fn post_eth_balances(ctx: &Context) -> ControllerFuture {
+    post_balances(ctx, Currency::Eth)
+}
+
+pub fn post_stq_balances(ctx: &Context) -> ControllerFuture {
+    post_balances(ctx, Currency::Stq)
+}
+
+fn post_balances(ctx: &Context, currency: Currency) -> ControllerFuture {
+    let ethereum_service = ctx.ethereum_service.clone();
+    let body = ctx.body.clone();
+    Box::new(
+        ctx.amount_format()
+            .into_future()
+            .and_then(move |format| parse_body::<PostBalancesRequest>(body.clone()).map(move |input| (format, input, body)))
+            .and_then(move |(format, input, body)| {
+                ethereum_service
+                    .get_balances(currency, input.addresses, input.block)
+                    .map_err(ectx!(convert => body))
+                    .and_then(move |balances| response_with_model(&format.balances(balances, currency)))
             }),
     )
 }
 
 pub fn post_ethereum_transactions(ctx: &Context) -> ControllerFuture {
     let ethereum_service = ctx.ethereum_service.clone();
+    let broadcast_service = ctx.broadcast_service.clone();
+    let nonce_service = ctx.nonce_service.clone();
     let body = ctx.body.clone();
     Box::new(
         parse_body::<PostEthereumTransactionRequest>(ctx.body.clone())
-            .and_then(move |input| ethereum_service.send_raw_tx(input.raw).map_err(ectx!(convert => body)))
-            .and_then(|hash| {
-                let resp = TxHashResponse { tx_hash: hash };
-                response_with_model(&resp)
-            }),
+            .and_then(move |input| {
+                let raw = input.raw;
+                ethereum_service
+                    .validate_raw_tx(raw.clone())
+                    .and_then(move |transaction| {
+                        ethereum_service.send_raw_tx(raw.clone()).and_then(move |tx_hash| {
+                            nonce_service.record_posted(transaction.from.clone(), transaction.nonce);
+                            broadcast_service
+                                .track(Broadcast::ethereum(raw, &transaction))
+                                .map(move |_| PostEthereumTransactionResponse { tx_hash, transaction })
+                        })
+                    })
+                    .map_err(ectx!(convert => body))
+            })
+            .and_then(|resp| response_with_model(&resp)),
+    )
+}
+
+pub fn post_estimate_gas(ctx: &Context) -> ControllerFuture {
+    let ethereum_service = ctx.ethereum_service.clone();
+    let body = ctx.body.clone();
+    Box::new(
+        parse_body::<EthereumCall>(ctx.body.clone())
+            .and_then(move |call| ethereum_service.estimate_gas(call).map_err(ectx!(convert => body)))
+            .and_then(|gas| response_with_model(&EstimateGasResponse { gas })),
+    )
+}
+
+pub fn post_estimate_erc20_transfer_gas(ctx: &Context) -> ControllerFuture {
+    let ethereum_service = ctx.ethereum_service.clone();
+    let body = ctx.body.clone();
+    Box::new(
+        parse_body::<PostErc20TransferEstimateRequest>(ctx.body.clone())
+            .and_then(move |input| {
+                ethereum_service
+                    .estimate_erc20_transfer_gas(input.token, input.from, input.to, input.value)
+                    .map_err(ectx!(convert => body))
+            })
+            .and_then(|estimate| response_with_model(&estimate)),
+    )
+}
+
+/// Read-only contract call
+pub fn post_call(ctx: &Context) -> ControllerFuture {
+    let ethereum_service = ctx.ethereum_service.clone();
+    let body = ctx.body.clone();
+    Box::new(
+        parse_body::<PostCallRequest>(ctx.body.clone())
+            .and_then(move |input| {
+                let call = EthereumCall {
+                    from: input.from,
+                    to: Some(input.to),
+                    value: input.value,
+                    data: input.data,
+                };
+                ethereum_service.call(call, input.block).map_err(ectx!(convert => body))
+            })
+            .and_then(|result| response_with_model(&CallResponse { result })),
     )
 }
+
+pub fn get_ethereum_transaction(ctx: &Context, hash: String) -> ControllerFuture {
+    let ethereum_service = ctx.ethereum_service.clone();
+    let input = parse_tx_hash(&hash).and_then(|hash| ctx.amount_format().map(|format| (hash, format)));
+    Box::new(input.into_future().and_then(move |(hash, format)| {
+        let hash_clone = hash.clone();
+        ethereum_service
+            .get_transaction_status(hash.clone())
+            .map_err(ectx!(convert => hash_clone))
+            .and_then(move |status| match status {
+                TransactionStatus::Pending => Either::A(future::ok(EthereumTransactionResponse {
+                    transaction: None,
+                    erc20_transfers: vec![],
+                    status,
+                })),
+                _ => {
+                    let hash_clone = hash.clone();
+                    Either::B(
+                        ethereum_service
+                            .get_transaction(hash.clone())
+                            .join(ethereum_service.get_erc20_transfers(hash))
+                            .map_err(ectx!(convert => hash_clone))
+                            .map(move |(transaction, erc20_transfers)| EthereumTransactionResponse {
+                                transaction: Some(format.transaction(transaction)),
+                                erc20_transfers: erc20_transfers.into_iter().map(|tx| format.transaction(tx)).collect(),
+                                status,
+                            }),
+                    )
+                }
+            })
+            .and_then(|resp| response_with_model(&resp))
+    }))
+}
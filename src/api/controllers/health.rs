@@ -0,0 +1,17 @@
+use super::super::utils::{response_with_model, response_with_status};
+use super::Context;
+use super::ControllerFuture;
+use prelude::*;
+
+/// Liveness probe, answers as long as the server is able to process requests
+pub fn get_healthz(_ctx: &Context) -> ControllerFuture {
+    response_with_model(&json!({ "status": "ok" }))
+}
+
+/// Readiness probe, checks upstream dependencies and responds with 503 if any critical one is down
+pub fn get_readyz(ctx: &Context) -> ControllerFuture {
+    Box::new(ctx.health_service.readiness().map_err(ectx!(convert)).and_then(|readiness| {
+        let status = if readiness.ready { 200 } else { 503 };
+        response_with_status(&readiness, status)
+    }))
+}
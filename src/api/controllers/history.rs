@@ -0,0 +1,40 @@
+use super::super::requests::HistoryQuery;
+use super::super::responses::TransactionsResponse;
+use super::super::utils::{parse_query, response_with_model};
+use super::Context;
+use super::ControllerFuture;
+use models::*;
+use prelude::*;
+
+pub fn get_btc_transactions(ctx: &Context, address: BitcoinAddress) -> ControllerFuture {
+    get_transactions(ctx, Currency::Btc, address.to_string())
+}
+
+pub fn get_eth_transactions(ctx: &Context, address: EthereumAddress) -> ControllerFuture {
+    get_transactions(ctx, Currency::Eth, address.into_inner())
+}
+
+pub fn get_stq_transactions(ctx: &Context, address: EthereumAddress) -> ControllerFuture {
+    get_transactions(ctx, Currency::Stq, address.into_inner())
+}
+
+/// Page of transactions of address from history index
+fn get_transactions(ctx: &Context, currency: Currency, address: String) -> ControllerFuture {
+    let history_service = ctx.history_service.clone();
+    let address_clone = address.clone();
+    let query = ctx
+        .amount_format()
+        .and_then(|format| parse_query::<HistoryQuery>(&ctx.uri).map(|query| (format, query)));
+    Box::new(query.into_future().and_then(move |(format, query)| {
+        history_service
+            .get_transactions(currency, address, query.cursor, query.limit)
+            .map_err(ectx!(convert => address_clone))
+            .and_then(move |page| {
+                let resp = TransactionsResponse {
+                    transactions: page.transactions.into_iter().map(|tx| format.transaction(tx)).collect(),
+                    next_cursor: page.next_cursor,
+                };
+                response_with_model(&resp)
+            })
+    }))
+}
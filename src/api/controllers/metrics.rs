@@ -0,0 +1,16 @@
+use super::Context;
+use super::ControllerFuture;
+use futures::prelude::*;
+use hyper::{Body, Response};
+use metrics;
+
+pub fn get_metrics(_ctx: &Context) -> ControllerFuture {
+    Box::new(
+        Ok(Response::builder()
+            .status(200)
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(Body::from(metrics::render()))
+            .unwrap())
+        .into_future(),
+    )
+}
@@ -5,15 +5,33 @@ use futures::prelude::*;
 use hyper::{header::HeaderValue, Body, HeaderMap, Method, Response, Uri};
 
 use super::error::*;
-use services::{BitcoinService, EthereumService};
+use super::requests::UnitsQuery;
+use super::responses::AmountFormat;
+use super::utils::parse_query;
+use models::CurrencyDecimals;
+use services::{
+    BackfillService, BitcoinService, BlockService, BroadcastService, EthereumService, HealthService, HistoryService, NonceService,
+};
 
+mod backfills;
 mod bitcoin;
+mod blocks;
+mod broadcasts;
 mod ethereum;
 mod fallback;
+mod health;
+mod history;
+mod metrics;
 
+pub use self::backfills::*;
 pub use self::bitcoin::*;
+pub use self::blocks::*;
+pub use self::broadcasts::*;
 pub use self::ethereum::*;
 pub use self::fallback::*;
+pub use self::health::*;
+pub use self::history::*;
+pub use self::metrics::*;
 
 pub type ControllerFuture = Box<Future<Item = Response<Body>, Error = Error> + Send>;
 
@@ -25,6 +43,23 @@ pub struct Context {
     pub headers: HeaderMap<HeaderValue>,
     pub bitcoin_service: Arc<BitcoinService>,
     pub ethereum_service: Arc<EthereumService>,
+    pub broadcast_service: Arc<BroadcastService>,
+    pub nonce_service: Arc<NonceService>,
+    pub history_service: Arc<HistoryService>,
+    pub backfill_service: Arc<BackfillService>,
+    pub block_service: Arc<BlockService>,
+    pub health_service: Arc<HealthService>,
+    pub decimals: CurrencyDecimals,
+}
+
+impl Context {
+    /// Format of amounts in response, requested with `units` query param
+    pub fn amount_format(&self) -> Result<AmountFormat, Error> {
+        parse_query::<UnitsQuery>(&self.uri).map(|query| AmountFormat {
+            units: query.units,
+            decimals: self.decimals,
+        })
+    }
 }
 
 impl Display for Context {
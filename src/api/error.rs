@@ -14,6 +14,11 @@ pub struct Error {
 pub enum ErrorKind {
     #[fail(display = "controller error - unauthorized")]
     Unauthorized,
+    #[fail(display = "controller error - forbidden")]
+    Forbidden,
+    /// Carries number of seconds after which request can be retried
+    #[fail(display = "controller error - too many requests")]
+    TooManyRequests(u64),
     #[fail(display = "controller error - bad request")]
     BadRequest,
     #[fail(display = "controller error - not found")]
@@ -44,8 +49,18 @@ pub enum ErrorContext {
     RequestMissingQuery,
     #[fail(display = "controller context - failed to extract query params")]
     RequestQueryParams,
+    #[fail(display = "controller context - invalid path params")]
+    RequestPathParams,
     #[fail(display = "controller context - error converting json data from request")]
     ResponseJson,
+    #[fail(display = "controller context - missing or invalid credentials")]
+    AuthCredentials,
+    #[fail(display = "controller context - invalid or expired request signature")]
+    AuthSignature,
+    #[fail(display = "controller context - client has no scope required by route")]
+    AuthScope,
+    #[fail(display = "controller context - rate limit of client is exceeded")]
+    RateLimit,
 }
 
 derive_error_impls!();
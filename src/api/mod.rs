@@ -1,28 +1,36 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use failure::{Compat, Fail};
+use futures::future;
 use futures::prelude::*;
 use hyper;
+use hyper::server::conn::AddrStream;
 use hyper::Server;
-use hyper::{service::Service, Body, Request, Response};
-use log::Level;
+use hyper::{service::make_service_fn, service::Service, Body, Request, Response};
 
-use super::config::Config;
+use super::config::{Config, Scope};
 use super::utils::{log_and_capture_error, log_error, log_warn};
-use client::HttpClientImpl;
-use client::{BitcoinClientImpl, EthereumClientImpl};
-use services::{BitcoinServiceImpl, EthereumServiceImpl};
+use metrics::{self, API_RATE_LIMITED_REQUESTS, API_REQUEST_DURATION};
+use services::{
+    BackfillService, BitcoinService, BlockService, BroadcastService, EthereumService, HealthService, HistoryService, NonceService,
+};
 use utils::read_body;
 
+mod auth;
 mod controllers;
 mod error;
+mod rate_limit;
 mod requests;
 mod responses;
 mod utils;
 
+use self::auth::{required_scope, Authenticator};
 use self::controllers::*;
 use self::error::*;
+use self::rate_limit::{scope_label, RateLimiter};
+use self::utils::route_label;
 use models::*;
 use serde_json;
 
@@ -30,10 +38,32 @@ use serde_json;
 pub struct ApiService {
     server_address: SocketAddr,
     config: Config,
+    bitcoin_service: Arc<BitcoinService>,
+    ethereum_service: Arc<EthereumService>,
+    broadcast_service: Arc<BroadcastService>,
+    nonce_service: Arc<NonceService>,
+    history_service: Arc<HistoryService>,
+    backfill_service: Arc<BackfillService>,
+    block_service: Arc<BlockService>,
+    health_service: Arc<HealthService>,
+    authenticator: Option<Arc<Authenticator>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    // address of the connection, set for each connection when server accepts it
+    remote_addr: Option<SocketAddr>,
 }
 
 impl ApiService {
-    fn from_config(config: &Config) -> Result<Self, Error> {
+    fn from_config(
+        config: &Config,
+        bitcoin_service: Arc<BitcoinService>,
+        ethereum_service: Arc<EthereumService>,
+        broadcast_service: Arc<BroadcastService>,
+        nonce_service: Arc<NonceService>,
+        history_service: Arc<HistoryService>,
+        backfill_service: Arc<BackfillService>,
+        block_service: Arc<BlockService>,
+        health_service: Arc<HealthService>,
+    ) -> Result<Self, Error> {
         let server_address = format!("{}:{}", config.server.host, config.server.port)
             .parse::<SocketAddr>()
             .map_err(ectx!(
@@ -43,9 +73,36 @@ impl ApiService {
                 config.server.host,
                 config.server.port
             ))?;
+        let authenticator = match config.auth {
+            Some(ref auth) if auth.disabled => {
+                warn!("Authentication is disabled, all API requests are allowed");
+                None
+            }
+            Some(ref auth) => Some(Arc::new(Authenticator::new(auth)?)),
+            // missing section is more likely a mistake than intent to open the API
+            None => {
+                return Err(ectx!(
+                    err format_err!("`auth` section is missing, set `auth.disabled = true` to allow unauthenticated requests"),
+                    ErrorContext::Config,
+                    ErrorKind::Internal
+                ))
+            }
+        };
+        let rate_limiter = config.rate_limit.as_ref().map(|rate_limit| Arc::new(RateLimiter::new(rate_limit)));
         Ok(ApiService {
             config: config.clone(),
             server_address,
+            bitcoin_service,
+            ethereum_service,
+            broadcast_service,
+            nonce_service,
+            history_service,
+            backfill_service,
+            block_service,
+            health_service,
+            authenticator,
+            rate_limiter,
+            remote_addr: None,
         })
     }
 }
@@ -58,43 +115,91 @@ impl Service for ApiService {
 
     fn call(&mut self, req: Request<Body>) -> Self::Future {
         let (parts, http_body) = req.into_parts();
-        let config = self.config.clone();
+        let started_at = Instant::now();
+        let route = route_label(parts.uri.path());
+        let method = parts.method.to_string();
+        let decimals = CurrencyDecimals::new(self.config.client.stq_decimals);
+        let bitcoin_service = self.bitcoin_service.clone();
+        let ethereum_service = self.ethereum_service.clone();
+        let broadcast_service = self.broadcast_service.clone();
+        let nonce_service = self.nonce_service.clone();
+        let history_service = self.history_service.clone();
+        let backfill_service = self.backfill_service.clone();
+        let block_service = self.block_service.clone();
+        let health_service = self.health_service.clone();
+        let authenticator = self.authenticator.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let remote_addr = self.remote_addr;
+        let scope = required_scope(&parts.method, parts.uri.path());
+        let ip_key = rate_limiter
+            .as_ref()
+            .map(|rate_limiter| format!("ip:{}", rate_limiter.client_ip(&parts.headers, remote_addr)));
+        // source is checked before its body is read. Requests of authenticated clients are limited per client,
+        // so source IP is charged only for failed authentication, unless authentication is disabled
+        let source_checked = match (&rate_limiter, &ip_key, scope) {
+            (Some(ref rate_limiter), Some(ref ip_key), Some(scope)) if authenticator.is_some() => rate_limiter
+                .check_available(ip_key, scope)
+                .map_err(|retry_after| rate_limit_error(ip_key, "ip", scope, retry_after)),
+            (Some(ref rate_limiter), Some(ref ip_key), Some(scope)) => check_rate_limit(rate_limiter, ip_key.clone(), "ip", scope),
+            _ => Ok(()),
+        };
         Box::new(
-            read_body(http_body)
-                .map_err(ectx!(ErrorSource::Hyper, ErrorKind::Internal))
+            future::result(source_checked)
+                .and_then(move |_| read_body(http_body).map_err(ectx!(ErrorSource::Hyper, ErrorKind::Internal)))
                 .and_then(move |body| {
+                    let client_id = match authenticator {
+                        Some(ref authenticator) => authenticator
+                            .authenticate(&parts.method, &parts.uri, &parts.headers, &body)
+                            .map_err(|e| {
+                                if let (Some(ref rate_limiter), Some(ref ip_key), Some(scope)) = (&rate_limiter, &ip_key, scope) {
+                                    let _ = rate_limiter.check(ip_key, scope);
+                                }
+                                e
+                            })?,
+                        None => None,
+                    };
+                    if let (Some(ref rate_limiter), Some(scope), Some(id)) = (&rate_limiter, scope, client_id) {
+                        check_rate_limit(rate_limiter, format!("client:{}", id), &id, scope)?;
+                    }
+                    Ok((parts, body))
+                })
+                .and_then(move |(parts, body)| {
                     let router = router! {
+                        GET /healthz => get_healthz,
+                        GET /readyz => get_readyz,
+                        GET /metrics => get_metrics,
                         GET /v1/bitcoin/{address: BitcoinAddress}/utxos => get_utxos,
                         GET /v1/bitcoin/{address: BitcoinAddress}/balance => get_btc_balance,
+                        GET /v1/bitcoin/{address: BitcoinAddress}/transactions => get_btc_transactions,
+                        POST /v1/bitcoin/balances => post_btc_balances,
                         POST /v1/bitcoin/transactions/raw => post_bitcoin_transactions,
+                        GET /v1/bitcoin/transactions/{hash: String} => get_bitcoin_transaction,
+                        GET /v1/bitcoin/blocks/{height: u64} => get_btc_block,
                         GET /v1/ethereum/{address: EthereumAddress}/nonce => get_nonce,
+                        GET /v1/ethereum/{address: EthereumAddress}/nonces => get_nonces,
+                        POST /v1/ethereum/{address: EthereumAddress}/nonces => post_nonces,
+                        DELETE /v1/ethereum/{address: EthereumAddress}/nonces => delete_nonces,
                         GET /v1/ethereum/{address: EthereumAddress}/balance => get_eth_balance,
                         GET /v1/storiqa/{address: EthereumAddress}/balance => get_stq_balance,
+                        GET /v1/ethereum/{address: EthereumAddress}/transactions => get_eth_transactions,
+                        GET /v1/storiqa/{address: EthereumAddress}/transactions => get_stq_transactions,
+                        POST /v1/ethereum/balances => post_eth_balances,
+                        POST /v1/storiqa/balances => post_stq_balances,
                         POST /v1/ethereum/transactions/raw => post_ethereum_transactions,
+                        POST /v1/ethereum/estimate_gas => post_estimate_gas,
+                        POST /v1/ethereum/estimate_gas/erc20_transfer => post_estimate_erc20_transfer_gas,
+                        POST /v1/ethereum/call => post_call,
+                        GET /v1/ethereum/transactions/{hash: String} => get_ethereum_transaction,
+                        GET /v1/ethereum/blocks/{height: u64} => get_eth_block,
+                        GET /v1/storiqa/blocks/{height: u64} => get_stq_block,
+                        GET /v1/broadcasts/{hash: String} => get_broadcast,
+                        POST /v1/admin/backfills => post_backfills,
+                        GET /v1/admin/backfills => get_backfills,
+                        GET /v1/admin/backfills/{id: String} => get_backfill,
+                        DELETE /v1/admin/backfills/{id: String} => delete_backfill,
                         _ => not_found,
                     };
 
-                    let http_client = Arc::new(HttpClientImpl::new(&config, Level::Debug));
-                    let bitcoin_client = Arc::new(BitcoinClientImpl::new(
-                        http_client.clone(),
-                        config.mode.clone(),
-                        config.client.bitcoin_rpc_url.clone(),
-                        config.client.bitcoin_rpc_user.clone(),
-                        config.client.bitcoin_rpc_password.clone(),
-                    ));
-                    let ethereum_client = Arc::new(EthereumClientImpl::new(
-                        http_client.clone(),
-                        config.mode.clone(),
-                        config.client.infura_key.clone(),
-                        config.client.stq_contract_address.clone(),
-                        config.client.stq_transfer_topic.clone(),
-                        config.client.stq_approval_topic.clone(),
-                        config.client.stq_balance_method.clone(),
-                    ));
-
-                    let bitcoin_service = Arc::new(BitcoinServiceImpl::new(bitcoin_client));
-                    let ethereum_service = Arc::new(EthereumServiceImpl::new(ethereum_client));
-
                     let ctx = Context {
                         body,
                         method: parts.method.clone(),
@@ -102,6 +207,13 @@ impl Service for ApiService {
                         headers: parts.headers,
                         bitcoin_service,
                         ethereum_service,
+                        broadcast_service,
+                        nonce_service,
+                        history_service,
+                        backfill_service,
+                        block_service,
+                        health_service,
+                        decimals,
                     };
 
                     debug!("Received request {}", ctx);
@@ -140,6 +252,23 @@ impl Service for ApiService {
                             .body(Body::from(r#"{"description": "Unauthorized"}"#))
                             .unwrap())
                     }
+                    ErrorKind::Forbidden => {
+                        log_warn(&e);
+                        Ok(Response::builder()
+                            .status(403)
+                            .header("Content-Type", "application/json")
+                            .body(Body::from(r#"{"description": "Forbidden"}"#))
+                            .unwrap())
+                    }
+                    ErrorKind::TooManyRequests(retry_after_secs) => {
+                        log_warn(&e);
+                        Ok(Response::builder()
+                            .status(429)
+                            .header("Content-Type", "application/json")
+                            .header("Retry-After", retry_after_secs.to_string().as_str())
+                            .body(Body::from(r#"{"description": "Too many requests"}"#))
+                            .unwrap())
+                    }
                     ErrorKind::NotFound => {
                         log_error(&e);
                         Ok(Response::builder()
@@ -167,29 +296,73 @@ impl Service for ApiService {
                             .body(Body::from(r#"{"description": "Internal server error"}"#))
                             .unwrap())
                     }
+                })
+                .map(move |resp| {
+                    let status = resp.status().as_u16().to_string();
+                    API_REQUEST_DURATION
+                        .with_label_values(&[&route, &method, &status])
+                        .observe(metrics::duration_secs(started_at));
+                    resp
                 }),
         )
     }
 }
 
-pub fn start_server(config: Config) -> Box<Future<Item = (), Error = ()> + Send> {
-    let fut = ApiService::from_config(&config)
-        .into_future()
-        .and_then(move |api| {
-            let api_clone = api.clone();
-            let new_service = move || {
-                let res: Result<_, hyper::Error> = Ok(api_clone.clone());
-                res
-            };
-            let addr = api.server_address.clone();
-            let server = Server::bind(&api.server_address)
-                .serve(new_service)
-                .map_err(ectx!(ErrorSource::Hyper, ErrorKind::Internal => addr));
-            info!("Listening on http://{}", addr);
-            server
-        })
-        .map_err(|e: Error| log_error(&e))
-        .map(|_| ());
+/// Takes a token from the bucket of `key`, `client_label` is the label of rejected requests metric
+fn check_rate_limit(rate_limiter: &RateLimiter, key: String, client_label: &str, scope: Scope) -> Result<(), Error> {
+    rate_limiter
+        .check(&key, scope)
+        .map_err(|retry_after| rate_limit_error(&key, client_label, scope, retry_after))
+}
+
+fn rate_limit_error(key: &str, client_label: &str, scope: Scope, retry_after: Duration) -> Error {
+    API_RATE_LIMITED_REQUESTS
+        .with_label_values(&[client_label, scope_label(scope)])
+        .inc();
+    let retry_after_secs = retry_after.as_secs() + if retry_after.subsec_nanos() > 0 { 1 } else { 0 };
+    ectx!(err ErrorContext::RateLimit, ErrorKind::TooManyRequests(retry_after_secs) => key)
+}
+
+pub fn start_server(
+    config: Config,
+    bitcoin_service: Arc<BitcoinService>,
+    ethereum_service: Arc<EthereumService>,
+    broadcast_service: Arc<BroadcastService>,
+    nonce_service: Arc<NonceService>,
+    history_service: Arc<HistoryService>,
+    backfill_service: Arc<BackfillService>,
+    block_service: Arc<BlockService>,
+    health_service: Arc<HealthService>,
+) -> Box<Future<Item = (), Error = ()> + Send> {
+    let fut = ApiService::from_config(
+        &config,
+        bitcoin_service,
+        ethereum_service,
+        broadcast_service,
+        nonce_service,
+        history_service,
+        backfill_service,
+        block_service,
+        health_service,
+    )
+    .into_future()
+    .and_then(move |api| {
+        let api_clone = api.clone();
+        let new_service = make_service_fn(move |conn: &AddrStream| {
+            let mut api = api_clone.clone();
+            api.remote_addr = Some(conn.remote_addr());
+            let res: Result<_, hyper::Error> = Ok(api);
+            res
+        });
+        let addr = api.server_address.clone();
+        let server = Server::bind(&api.server_address)
+            .serve(new_service)
+            .map_err(ectx!(ErrorSource::Hyper, ErrorKind::Internal => addr));
+        info!("Listening on http://{}", addr);
+        server
+    })
+    .map_err(|e: Error| log_error(&e))
+    .map(|_| ());
 
     Box::new(fut)
 }
@@ -0,0 +1,217 @@
+//! Token bucket rate limiting of API requests. Authenticated clients have buckets of their own, while source IP
+//! is charged only for requests that fail authentication, so that guessing credentials is limited, but clients
+//! behind the same proxy or NAT don't share a quota. Without authentication all requests are limited per source IP
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use hyper::header::{HeaderMap, HeaderValue};
+
+use config::{Limit, RateLimit, Scope};
+
+/// Buckets are purged only when there are more of them, so that the check stays cheap
+const PURGE_THRESHOLD: usize = 1024;
+
+pub struct RateLimiter {
+    read: Limit,
+    broadcast: Limit,
+    client_ip_header: Option<String>,
+    buckets: Mutex<HashMap<(String, Scope), TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimit) -> Self {
+        Self {
+            read: config.read,
+            broadcast: config.broadcast,
+            client_ip_header: config.client_ip_header.clone(),
+            buckets: Default::default(),
+        }
+    }
+
+    /// Address of client, taken from header of trusted proxy if it's configured and valid
+    pub fn client_ip(&self, headers: &HeaderMap<HeaderValue>, remote_addr: Option<SocketAddr>) -> String {
+        let forwarded = self.client_ip_header.as_ref().and_then(|name| {
+            // proxy appends address it's connected from, the preceding ones are sent by client and can be forged
+            let value = headers.get_all(name.as_str()).iter().last()?.to_str().ok()?;
+            value.rsplit(',').next()?.trim().parse::<IpAddr>().ok()
+        });
+        forwarded
+            .or(remote_addr.map(|addr| addr.ip()))
+            .map(|ip| ip.to_string())
+            .unwrap_or("unknown".to_string())
+    }
+
+    /// Takes a token from the bucket of `key`. If the bucket is empty returns time until the next token
+    pub fn check(&self, key: &str, scope: Scope) -> Result<(), Duration> {
+        self.check_at(key, scope, Instant::now())
+    }
+
+    /// Checks that the bucket of `key` has a token without taking it, so that exhausted sources are rejected early
+    pub fn check_available(&self, key: &str, scope: Scope) -> Result<(), Duration> {
+        self.check_available_at(key, scope, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, scope: Scope, now: Instant) -> Result<(), Duration> {
+        let limit = self.limit(scope);
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > PURGE_THRESHOLD {
+            let (read, broadcast) = (self.read, self.broadcast);
+            buckets.retain(|(_, scope), bucket| {
+                let limit = if *scope == Scope::Read { read } else { broadcast };
+                !bucket.is_full(limit, now)
+            });
+        }
+        buckets
+            .entry((key.to_string(), scope))
+            .or_insert_with(|| TokenBucket::new(limit, now))
+            .take(limit, now)
+    }
+
+    fn check_available_at(&self, key: &str, scope: Scope, now: Instant) -> Result<(), Duration> {
+        let limit = self.limit(scope);
+        match self.buckets.lock().unwrap().get(&(key.to_string(), scope)) {
+            Some(bucket) => {
+                let mut bucket = *bucket;
+                bucket.take(limit, now)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn limit(&self, scope: Scope) -> Limit {
+        match scope {
+            Scope::Read => self.read,
+            // admin requests are rare, they share the stricter limit
+            Scope::Broadcast | Scope::Admin => self.broadcast,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: Limit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, limit: Limit, now: Instant) {
+        // `now` is taken before locking buckets, so it can be behind the last update by another request
+        if now <= self.updated_at {
+            return;
+        }
+        let elapsed = now.duration_since(self.updated_at);
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+        self.tokens = (self.tokens + elapsed * limit.requests_per_sec).min(limit.burst as f64);
+        self.updated_at = now;
+    }
+
+    fn take(&mut self, limit: Limit, now: Instant) -> Result<(), Duration> {
+        self.refill(limit, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else if limit.requests_per_sec > 0.0 {
+            let secs = (1.0 - self.tokens) / limit.requests_per_sec;
+            Err(Duration::new(secs as u64, (secs.fract() * 1e9) as u32))
+        } else {
+            // zero rate forbids the scope entirely
+            Err(Duration::from_secs(u64::from(u32::max_value())))
+        }
+    }
+
+    fn is_full(&self, limit: Limit, now: Instant) -> bool {
+        let mut bucket = *self;
+        bucket.refill(limit, now);
+        bucket.tokens >= limit.burst as f64
+    }
+}
+
+/// Value of `scope` label of rate limiting metrics
+pub fn scope_label(scope: Scope) -> &'static str {
+    match scope {
+        Scope::Read => "read",
+        Scope::Broadcast => "broadcast",
+        Scope::Admin => "admin",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(&RateLimit {
+            read: Limit {
+                requests_per_sec: 10.0,
+                burst: 2,
+            },
+            broadcast: Limit {
+                requests_per_sec: 0.5,
+                burst: 1,
+            },
+            client_ip_header: None,
+        });
+        let now = Instant::now();
+        assert!(limiter.check_at("a", Scope::Read, now).is_ok());
+        assert!(limiter.check_at("a", Scope::Read, now).is_ok());
+        assert_eq!(limiter.check_at("a", Scope::Read, now), Err(Duration::from_millis(100)));
+        assert!(limiter.check_at("b", Scope::Read, now).is_ok(), "buckets are per key");
+        assert!(limiter.check_at("a", Scope::Read, now + Duration::from_millis(100)).is_ok());
+
+        assert!(limiter.check_at("a", Scope::Broadcast, now).is_ok(), "buckets are per scope");
+        assert_eq!(limiter.check_at("a", Scope::Broadcast, now), Err(Duration::from_secs(2)));
+        assert_eq!(
+            limiter.check_at("a", Scope::Broadcast, now + Duration::from_secs(1)),
+            Err(Duration::from_secs(1))
+        );
+        assert!(limiter.check_at("a", Scope::Broadcast, now + Duration::from_secs(3)).is_ok());
+
+        assert!(limiter.check_available_at("c", Scope::Broadcast, now).is_ok());
+        assert!(limiter.check_at("c", Scope::Broadcast, now).is_ok());
+        assert_eq!(limiter.check_available_at("c", Scope::Broadcast, now), Err(Duration::from_secs(2)));
+        assert_eq!(
+            limiter.check_available_at("c", Scope::Broadcast, now + Duration::from_secs(2)),
+            Ok(()),
+            "checking availability doesn't take tokens"
+        );
+        assert!(limiter.check_at("c", Scope::Broadcast, now + Duration::from_secs(2)).is_ok());
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let limit = Limit {
+            requests_per_sec: 1.0,
+            burst: 1,
+        };
+        let config = RateLimit {
+            read: limit,
+            broadcast: limit,
+            client_ip_header: None,
+        };
+        let remote_addr = Some("10.0.0.1:5000".parse().unwrap());
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Forwarded-For", HeaderValue::from_static("1.1.1.1, 2.2.2.2"));
+        let limiter = RateLimiter::new(&config);
+        assert_eq!(limiter.client_ip(&headers, remote_addr), "10.0.0.1", "header is not trusted");
+
+        let limiter = RateLimiter::new(&RateLimit {
+            client_ip_header: Some("X-Forwarded-For".to_string()),
+            ..config
+        });
+        assert_eq!(limiter.client_ip(&headers, remote_addr), "2.2.2.2", "address appended by proxy");
+        headers.insert("X-Forwarded-For", HeaderValue::from_static("garbage"));
+        assert_eq!(limiter.client_ip(&headers, remote_addr), "10.0.0.1");
+        assert_eq!(limiter.client_ip(&HeaderMap::new(), None), "unknown");
+    }
+}
@@ -11,3 +11,93 @@ pub struct PostBitcoinTransactionRequest {
 pub struct PostEthereumTransactionRequest {
     pub raw: RawEthereumTransaction,
 }
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PostErc20TransferEstimateRequest {
+    /// Address of token contract
+    pub token: EthereumAddress,
+    pub from: EthereumAddress,
+    pub to: EthereumAddress,
+    pub value: Amount,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PostCallRequest {
+    pub from: Option<EthereumAddress>,
+    pub to: EthereumAddress,
+    pub value: Option<Amount>,
+    pub data: Option<String>,
+    #[serde(default)]
+    pub block: BlockTag,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PostBitcoinBalancesRequest {
+    pub addresses: Vec<BitcoinAddress>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PostBalancesRequest {
+    pub addresses: Vec<EthereumAddress>,
+    #[serde(default)]
+    pub block: BlockTag,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceQuery {
+    /// Block number for historical balances
+    #[serde(default)]
+    pub block: BlockTag,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryQuery {
+    /// `nextCursor` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct NonceQuery {
+    /// With `pending` block nonce counts transactions in node's mempool
+    #[serde(default)]
+    pub block: BlockTag,
+}
+
+/// Units of amounts in responses
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    /// Integer number of minimal units, i.e. satoshis or wei
+    Base,
+    /// Decimal string of coins, i.e. `"0.00012345"` BTC
+    Decimal,
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Units::Base
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UnitsQuery {
+    #[serde(default)]
+    pub units: Units,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PostBackfillRequest {
+    pub currency: Currency,
+    pub from_height: u64,
+    pub to_height: u64,
+}
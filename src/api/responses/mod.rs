@@ -1,3 +1,8 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+
+use super::requests::Units;
 use models::*;
 
 #[derive(Debug, Serialize, Clone)]
@@ -6,14 +11,189 @@ pub struct NonceResponse {
     pub nonce: u64,
 }
 
+/// Amount rendered in units requested with `units` query param
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum AmountResponse {
+    Base(Amount),
+    Decimal(String),
+}
+
+/// Renders amounts of responses in requested units
+#[derive(Debug, Clone, Copy)]
+pub struct AmountFormat {
+    pub units: Units,
+    pub decimals: CurrencyDecimals,
+}
+
+impl AmountFormat {
+    pub fn amount(&self, amount: Amount, currency: Currency) -> AmountResponse {
+        match self.units {
+            Units::Base => AmountResponse::Base(amount),
+            Units::Decimal => AmountResponse::Decimal(amount.to_decimal_string(self.decimals.get(currency))),
+        }
+    }
+
+    pub fn balances(&self, balances: Balances, currency: Currency) -> BalancesResponse {
+        let mut resp = BalancesResponse {
+            block_number: balances.block_number,
+            balances: BTreeMap::new(),
+            errors: BTreeMap::new(),
+        };
+        for entry in balances.balances {
+            match entry.balance {
+                Ok(balance) => {
+                    resp.balances.insert(entry.address, self.amount(balance, currency));
+                }
+                Err(message) => {
+                    resp.errors.insert(entry.address, message);
+                }
+            }
+        }
+        resp
+    }
+
+    pub fn utxo(&self, utxo: Utxo) -> UtxoResponse {
+        UtxoResponse {
+            tx_hash: utxo.tx_hash,
+            index: utxo.index,
+            value: self.amount(utxo.value, Currency::Btc),
+        }
+    }
+
+    pub fn transaction(&self, tx: BlockchainTransaction) -> BlockchainTransactionResponse {
+        let currency = tx.currency;
+        // fees of STQ transfers are paid in ether
+        let fee_currency = match currency {
+            Currency::Stq => Currency::Eth,
+            currency => currency,
+        };
+        BlockchainTransactionResponse {
+            hash: tx.hash,
+            from: tx.from,
+            to: tx
+                .to
+                .into_iter()
+                .map(|entry| BlockchainTransactionEntryResponse {
+                    address: entry.address,
+                    value: self.amount(entry.value, currency),
+                })
+                .collect(),
+            block_number: tx.block_number,
+            block_hash: tx.block_hash,
+            block_time: tx.block_time,
+            currency,
+            fee: self.amount(tx.fee, fee_currency),
+            confirmations: tx.confirmations,
+            erc20_operation_kind: tx.erc20_operation_kind,
+            contract_deployment: tx.contract_deployment,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct BalanceResponse {
-    pub balance: Amount,
+    pub balance: AmountResponse,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BalancesResponse {
+    /// Block all balances are queried at, ethereum only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    /// Balances by address
+    pub balances: BTreeMap<String, AmountResponse>,
+    /// Errors by address, for addresses that couldn't be queried
+    pub errors: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UtxoResponse {
+    pub tx_hash: String,
+    pub index: usize,
+    pub value: AmountResponse,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockchainTransactionEntryResponse {
+    pub address: String,
+    pub value: AmountResponse,
+}
+
+/// `BlockchainTransaction` with amounts in requested units
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockchainTransactionResponse {
+    pub hash: String,
+    pub from: Vec<String>,
+    pub to: Vec<BlockchainTransactionEntryResponse>,
+    pub block_number: Option<u64>,
+    pub block_hash: Option<String>,
+    pub block_time: Option<DateTime<Utc>>,
+    pub currency: Currency,
+    pub fee: AmountResponse,
+    pub confirmations: usize,
+    pub erc20_operation_kind: Option<Erc20OperationKind>,
+    pub contract_deployment: bool,
 }
 
 #[derive(Debug, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct TxHashResponse {
+pub struct PostBitcoinTransactionResponse {
     pub tx_hash: TxHash,
+    /// Transaction as it was decoded and validated before sending it to blockchain
+    pub transaction: DecodedBitcoinTransaction,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PostEthereumTransactionResponse {
+    pub tx_hash: TxHash,
+    /// Transaction as it was decoded and validated before sending it to blockchain
+    pub transaction: DecodedEthereumTransaction,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionsResponse {
+    pub transactions: Vec<BlockchainTransactionResponse>,
+    /// Cursor of the next page, `null` if this page is the last one
+    pub next_cursor: Option<String>,
+}
+
+/// Block scanned by poller with transactions published from it
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockResponse {
+    pub currency: Currency,
+    pub height: u64,
+    pub hash: String,
+    pub time: Option<DateTime<Utc>>,
+    pub transactions: Vec<BlockchainTransactionResponse>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EstimateGasResponse {
+    pub gas: Amount,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CallResponse {
+    /// Hex encoded return data
+    pub result: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EthereumTransactionResponse {
+    /// `None` if transaction is not yet mined
+    pub transaction: Option<BlockchainTransactionResponse>,
+    pub erc20_transfers: Vec<BlockchainTransactionResponse>,
+    pub status: TransactionStatus,
 }
@@ -2,11 +2,15 @@ use super::error::*;
 use super::ControllerFuture;
 use failure::Fail;
 use futures::prelude::*;
-use hyper::Response;
+use hyper::{Response, Uri};
+use models::TxHash;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json;
+use serde_qs;
+use services::validation_error;
 use std::fmt::Debug;
+use validator::ValidationErrors;
 
 pub fn parse_body<T>(body: Vec<u8>) -> impl Future<Item = T, Error = Error> + Send
 where
@@ -18,7 +22,32 @@ where
         .and_then(|string| serde_json::from_str::<T>(&string).map_err(ectx!(ErrorContext::RequestJson, ErrorKind::BadRequest => string)))
 }
 
+pub fn parse_query<T>(uri: &Uri) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let query = uri.query().unwrap_or("");
+    serde_qs::from_str::<T>(query).map_err(|e| ectx!(err ErrorContext::RequestQueryParams, ErrorKind::BadRequest => query, e.to_string()))
+}
+
+/// Transaction hash of path param, rejected with validation error instead of being sent to node
+pub fn parse_tx_hash(hash: &str) -> Result<TxHash, Error> {
+    hash.parse().map_err(|_| {
+        let mut errors = ValidationErrors::new();
+        let message = "expected 64 hex chars with optional 0x prefix".to_string();
+        errors.add("hash", validation_error("invalid_hash", message));
+        ectx!(err ErrorContext::RequestPathParams, ErrorKind::UnprocessableEntity(errors) => hash)
+    })
+}
+
 pub fn response_with_model<M>(model: &M) -> ControllerFuture
+where
+    M: Debug + Serialize,
+{
+    response_with_status(model, 200)
+}
+
+pub fn response_with_status<M>(model: &M, status: u16) -> ControllerFuture
 where
     M: Debug + Serialize,
 {
@@ -26,12 +55,71 @@ where
         serde_json::to_string(&model)
             .map_err(ectx!(ErrorContext::ResponseJson, ErrorKind::Internal => model))
             .into_future()
-            .map(|text| {
+            .map(move |text| {
                 Response::builder()
-                    .status(200)
+                    .status(status)
                     .header("Content-Type", "application/json")
                     .body(text.into())
                     .unwrap()
             }),
     )
 }
+
+/// Route of request path for metrics, with path params replaced by `{}` to keep the number of labels bounded
+pub fn route_label(path: &str) -> String {
+    const STATIC_SEGMENTS: &[&str] = &[
+        "v1",
+        "bitcoin",
+        "ethereum",
+        "storiqa",
+        "transactions",
+        "raw",
+        "utxos",
+        "balance",
+        "balances",
+        "blocks",
+        "nonce",
+        "nonces",
+        "estimate_gas",
+        "erc20_transfer",
+        "call",
+        "broadcasts",
+        "admin",
+        "backfills",
+        "healthz",
+        "readyz",
+        "metrics",
+    ];
+    path.split('/')
+        .map(|segment| {
+            if segment.is_empty() || STATIC_SEGMENTS.contains(&segment) {
+                segment
+            } else {
+                "{}"
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_label() {
+        let cases = [
+            ("/v1/bitcoin/1BoatSLRHtKNngkdXEeobR76b53LETtpyT/balance", "/v1/bitcoin/{}/balance"),
+            ("/v1/ethereum/transactions/raw", "/v1/ethereum/transactions/raw"),
+            (
+                "/v1/ethereum/transactions/5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060",
+                "/v1/ethereum/transactions/{}",
+            ),
+            ("/metrics", "/metrics"),
+            ("/wp-admin/index.php", "/{}/{}"),
+        ];
+        for case in cases.iter() {
+            assert_eq!(route_label(case.0), case.1);
+        }
+    }
+}
@@ -1,15 +1,19 @@
 mod responses;
 
+pub use self::responses::Block;
+
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use chrono::{TimeZone, Utc};
 use hyper::{Body, Request};
 
 use self::responses::*;
 use super::error::*;
-use super::http_client::HttpClient;
-use config::Mode;
+use super::http_client::{HttpClient, Idempotent};
+use config::BitcoinNetwork;
 use futures::{future, stream};
+use metrics::{self, RPC_CALLS};
 use models::*;
 use prelude::*;
 use serde_json;
@@ -24,9 +28,21 @@ pub trait BitcoinClient: Send + Sync + 'static {
 
     /// Send raw transaction to blockchain
     fn send_raw_tx(&self, tx: RawBitcoinTransaction) -> Box<Future<Item = TxHash, Error = Error> + Send>;
-    /// Get transaction by hash. Since getting block_number from transaction is not yet
-    /// supported, you need to provide one in arguments
-    fn get_transaction(&self, hash: String, block_number: u64) -> Box<Future<Item = BlockchainTransaction, Error = Error> + Send>;
+    /// Check whether node would accept transaction to its mempool, without sending it
+    fn test_mempool_accept(&self, tx: RawBitcoinTransaction) -> Box<Future<Item = MempoolAcceptance, Error = Error> + Send>;
+    /// Get value of transaction output, `None` if it doesn't exist or is spent. Spends in mempool are counted
+    /// with `include_mempool`, otherwise only spends in blocks are
+    fn get_unspent_output_value(
+        &self,
+        txid: String,
+        index: u32,
+        include_mempool: bool,
+    ) -> Box<Future<Item = Option<Amount>, Error = Error> + Send>;
+    /// Get hashes of transactions in node's mempool
+    fn get_mempool_txids(&self) -> Box<Future<Item = Vec<String>, Error = Error> + Send>;
+    /// Get transaction by hash. Block number is resolved from the block containing the transaction,
+    /// for transactions in mempool it is `None`
+    fn get_transaction(&self, hash: String) -> Box<Future<Item = BlockchainTransaction, Error = Error> + Send>;
     /// Get blocks starting from `start_block_hash` (or the most recent block if not specified)
     /// and fetch previous blocks. Total number of blocks = `blocks_count`.
     /// `blocks_count` should be greater than 0.
@@ -37,12 +53,20 @@ pub trait BitcoinClient: Send + Sync + 'static {
         start_block_hash: Option<String>,
         blocks_count: u64,
     ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send>;
+    /// Get transactions of block at `height`, except for coinbase transaction
+    fn get_block_transactions(&self, height: u64) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send>;
+    /// Get height and hash of the best block with a single call
+    fn get_chain_tip(&self) -> Box<Future<Item = ChainTip, Error = Error> + Send>;
+    /// Get height of the best block and whether node is in initial block download
+    fn get_node_status(&self) -> Box<Future<Item = NodeStatus, Error = Error> + Send>;
+    /// Get chain node is connected to, i.e. `main`, `test`, `signet` or `regtest`
+    fn get_chain(&self) -> Box<Future<Item = String, Error = Error> + Send>;
 }
 
 #[derive(Clone)]
 pub struct BitcoinClientImpl {
     http_client: Arc<HttpClient>,
-    mode: Mode,
+    network: BitcoinNetwork,
     bitcoin_rpc_url: String,
     bitcoin_rpc_user: String,
     bitcoin_rpc_password: String,
@@ -53,14 +77,14 @@ const BLOCK_TXS_LIMIT: u64 = 10;
 impl BitcoinClientImpl {
     pub fn new(
         http_client: Arc<HttpClient>,
-        mode: Mode,
+        network: BitcoinNetwork,
         bitcoin_rpc_url: String,
         bitcoin_rpc_user: String,
         bitcoin_rpc_password: String,
     ) -> Self {
         Self {
             http_client,
-            mode,
+            network,
             bitcoin_rpc_url,
             bitcoin_rpc_user,
             bitcoin_rpc_password,
@@ -72,6 +96,8 @@ impl BitcoinClientImpl {
         for<'a> T: Send + 'static + ::serde::Deserialize<'a>,
     {
         let http_client = self.http_client.clone();
+        let method = params["method"].as_str().unwrap_or("unknown").to_string();
+        let is_send = method == "sendrawtransaction";
         let params_clone = params.clone();
         let basic = ::base64::encode(&format!("{}:{}", self.bitcoin_rpc_user, self.bitcoin_rpc_password));
         let basic = format!("Basic {}", basic);
@@ -85,6 +111,13 @@ impl BitcoinClientImpl {
                     .body(Body::from(body.clone()))
                     .map_err(ectx!(ErrorSource::Hyper, ErrorKind::Internal => body))
             })
+            .map(move |mut request| {
+                // sending transaction again would fail as it's already known to node
+                if !is_send {
+                    request.extensions_mut().insert(Idempotent);
+                }
+                request
+            })
             .into_future()
             .and_then(move |request| http_client.request(request))
             .and_then(|resp| read_body(resp.into_body()).map_err(ectx!(ErrorKind::Internal => params_clone)))
@@ -93,22 +126,34 @@ impl BitcoinClientImpl {
                 String::from_utf8(bytes).map_err(ectx!(ErrorContext::UTF8, ErrorKind::Internal => bytes_clone))
             })
             .and_then(|string| serde_json::from_str::<T>(&string).map_err(ectx!(ErrorContext::Json, ErrorKind::Internal => string.clone())))
+            .then(move |res| {
+                RPC_CALLS
+                    .with_label_values(&["bitcoin", &method, metrics::result_label(&res)])
+                    .inc();
+                res
+            })
     }
 
     fn block_transactions(&self, block: Block) -> impl Stream<Item = BlockchainTransaction, Error = Error> {
         let self_clone = self.clone();
         let Block {
             tx: transactions,
-            height: block_number,
+            hash,
+            height,
+            time,
             ..
         } = block;
+        let header = BlockHeader { hash, height, time };
         // skipping coinbase transaction
         let hash_stream = stream::iter_ok(transactions.into_iter().skip(1));
         hash_stream
             .chunks(BLOCK_TXS_LIMIT as usize)
             .and_then(move |hashes| {
                 let self_clone = self_clone.clone();
-                let fs = hashes.into_iter().map(move |hash| self_clone.get_transaction(hash, block_number));
+                let header = header.clone();
+                let fs = hashes
+                    .into_iter()
+                    .map(move |hash| self_clone.get_transaction_in_block(hash, header.clone()));
                 future::join_all(fs)
             })
             .map(|x| stream::iter_ok(x))
@@ -138,6 +183,26 @@ impl BitcoinClientImpl {
         self.get_rpc_response::<RpcBestBlockResponse>(&params).map(|r| r.result)
     }
 
+    fn get_block_hash(&self, height: u64) -> impl Future<Item = String, Error = Error> + Send {
+        let params = json!({
+            "jsonrpc": "2",
+            "id": "1",
+            "method": "getblockhash",
+            "params": [height]
+        });
+        self.get_rpc_response::<RpcBestBlockResponse>(&params).map(|r| r.result)
+    }
+
+    fn get_blockchain_info(&self) -> impl Future<Item = BlockchainInfo, Error = Error> + Send {
+        let params = json!({
+            "jsonrpc": "2",
+            "id": "1",
+            "method": "getblockchaininfo",
+            "params": []
+        });
+        self.get_rpc_response::<RpcBlockchainInfoResponse>(&params).map(|r| r.result)
+    }
+
     pub fn get_block_by_hash(&self, hash: String) -> impl Future<Item = Block, Error = Error> + Send {
         let params = json!({
             "jsonrpc": "2",
@@ -148,16 +213,74 @@ impl BitcoinClientImpl {
         self.get_rpc_response::<RpcBlockResponse>(&params).map(|r| r.result)
     }
 
+    fn get_block_header(&self, hash: String) -> impl Future<Item = BlockHeader, Error = Error> + Send {
+        let params = json!({
+            "jsonrpc": "2",
+            "id": "1",
+            "method": "getblockheader",
+            "params": [hash, true]
+        });
+        self.get_rpc_response::<RpcBlockHeaderResponse>(&params).map(|r| r.result)
+    }
+
+    fn get_raw_transaction(&self, hash: String) -> impl Future<Item = RpcRawTransaction, Error = Error> + Send {
+        let params = json!({
+            "jsonrpc": "2",
+            "id": "1",
+            "method": "getrawtransaction",
+            "params": [hash, true]
+        });
+        self.get_rpc_response::<RpcRawTransactionResponse>(&params).map(|r| r.result)
+    }
+
+    /// Same as `get_transaction`, used when the block of transaction is already known
+    fn get_transaction_in_block(
+        &self,
+        hash: String,
+        block: BlockHeader,
+    ) -> impl Future<Item = BlockchainTransaction, Error = Error> + Send {
+        let self_clone = self.clone();
+        self.get_raw_transaction(hash)
+            .and_then(move |tx| self_clone.raw_tx_to_tx(tx, Some(block)))
+    }
+
+    /// Fetches transactions referenced in vins (needed to get input addresses and values)
+    /// and converts raw transaction into blockchain transaction
+    fn raw_tx_to_tx(
+        &self,
+        tx: RpcRawTransaction,
+        block: Option<BlockHeader>,
+    ) -> impl Future<Item = BlockchainTransaction, Error = Error> + Send {
+        let self_clone = self.clone();
+        let in_transaction_fs: Vec<_> = tx
+            .vin
+            .iter()
+            .map(move |vin| {
+                let params = json!({
+                    "jsonrpc": "2",
+                    "id": "1",
+                    "method": "getrawtransaction",
+                    "params": [vin.txid, true]
+                });
+                self_clone
+                    .get_rpc_response::<RpcRawTransactionMaybeCoinbaseVinsResponse>(&params)
+                    .map(|r| r.result)
+            })
+            .collect();
+        future::join_all(in_transaction_fs).and_then(move |in_txs| BitcoinClientImpl::rpc_tx_to_tx(tx, in_txs, block))
+    }
+
     fn rpc_tx_to_tx(
         tx: RpcRawTransaction,
         in_txs: Vec<RpcRawTransactionWithMaybeCoinbaseVins>,
-        block_number: u64,
+        block: Option<BlockHeader>,
     ) -> Result<BlockchainTransaction, Error> {
         let RpcRawTransaction {
             txid: hash,
             vin: vins,
             vout: vouts,
             confirmations,
+            ..
         } = tx;
         let hash_clone = hash.clone();
         let hash_clone2 = hash.clone();
@@ -216,15 +339,21 @@ impl BitcoinClientImpl {
             })
             .map(|from| from.address)
             .collect();
+        let block_number = block.as_ref().map(|block| block.height);
+        let block_time = block.as_ref().and_then(|block| Utc.timestamp_opt(block.time, 0).single());
+        let block_hash = block.map(|block| block.hash);
         Ok(BlockchainTransaction {
             hash,
             from,
             to,
             block_number,
+            block_hash,
+            block_time,
             currency: Currency::Btc,
             fee,
             confirmations,
             erc20_operation_kind: None,
+            contract_deployment: false,
         })
     }
 }
@@ -238,37 +367,19 @@ impl BitcoinClient for BitcoinClientImpl {
                 .ok_or(ectx!(err ErrorContext::Overflow, ErrorKind::Internal))
         }))
     }
-    fn get_transaction(&self, hash: String, block_number: u64) -> Box<Future<Item = BlockchainTransaction, Error = Error> + Send> {
-        let params = json!({
-            "jsonrpc": "2",
-            "id": "1",
-            "method": "getrawtransaction",
-            "params": [hash, true]
-        });
+    fn get_transaction(&self, hash: String) -> Box<Future<Item = BlockchainTransaction, Error = Error> + Send> {
         let self_clone = self.clone();
+        let self_clone2 = self.clone();
         Box::new(
-            self.get_rpc_response::<RpcRawTransactionResponse>(&params)
-                .and_then(move |resp| {
-                    let self_clone = self_clone.clone();
-                    let in_transaction_fs: Vec<_> = resp
-                        .result
-                        .vin
-                        .iter()
-                        .map(move |vin| {
-                            let params = json!({
-                                "jsonrpc": "2",
-                                "id": "1",
-                                "method": "getrawtransaction",
-                                "params": [vin.txid, true]
-                            });
-                            self_clone
-                                .get_rpc_response::<RpcRawTransactionMaybeCoinbaseVinsResponse>(&params)
-                                .map(|r| r.result)
-                        })
-                        .collect();
-                    future::join_all(in_transaction_fs).map(move |in_transactions| (resp, in_transactions))
+            self.get_raw_transaction(hash)
+                .and_then(move |tx| {
+                    let block_f = match tx.blockhash.clone() {
+                        Some(block_hash) => future::Either::A(self_clone.get_block_header(block_hash).map(Some)),
+                        None => future::Either::B(Ok(None).into_future()),
+                    };
+                    block_f.map(move |block| (tx, block))
                 })
-                .and_then(move |(tx_resp, in_txs_resp)| BitcoinClientImpl::rpc_tx_to_tx(tx_resp.result, in_txs_resp, block_number)),
+                .and_then(move |(tx, block)| self_clone2.raw_tx_to_tx(tx, block)),
         )
     }
 
@@ -299,12 +410,46 @@ impl BitcoinClient for BitcoinClientImpl {
         )
     }
 
+    fn get_block_transactions(&self, height: u64) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        let self_clone = self.clone();
+        let self_clone2 = self.clone();
+        Box::new(
+            self.get_block_hash(height)
+                .and_then(move |hash| self_clone.get_block_by_hash(hash))
+                .into_stream()
+                .map(move |block| self_clone2.block_transactions(block))
+                .flatten(),
+        )
+    }
+
+    fn get_chain_tip(&self) -> Box<Future<Item = ChainTip, Error = Error> + Send> {
+        Box::new(self.get_blockchain_info().map(|info| ChainTip {
+            height: info.blocks,
+            hash: info.bestblockhash,
+        }))
+    }
+
+    fn get_node_status(&self) -> Box<Future<Item = NodeStatus, Error = Error> + Send> {
+        Box::new(self.get_blockchain_info().map(|info| NodeStatus {
+            block_number: info.blocks,
+            syncing: info.initialblockdownload,
+        }))
+    }
+
+    fn get_chain(&self) -> Box<Future<Item = String, Error = Error> + Send> {
+        Box::new(self.get_blockchain_info().map(|info| info.chain))
+    }
+
     fn get_utxos(&self, address: BitcoinAddress) -> Box<Future<Item = Vec<Utxo>, Error = Error> + Send> {
         let address_clone2 = address.clone();
         let http_client = self.http_client.clone();
-        let uri_base = match self.mode {
-            Mode::Production => "https://blockchain.info",
-            _ => "https://testnet.blockchain.info",
+        let uri_base = match self.network.blockchain_info_url() {
+            Some(uri_base) => uri_base,
+            None => {
+                return Box::new(future::err(
+                    ectx!(err ErrorContext::Network, ErrorKind::Internal => self.network, address),
+                ))
+            }
         };
         Box::new(
             Request::builder()
@@ -339,4 +484,55 @@ impl BitcoinClient for BitcoinClientImpl {
                 .map(|resp| resp.result),
         )
     }
+
+    fn test_mempool_accept(&self, tx: RawBitcoinTransaction) -> Box<Future<Item = MempoolAcceptance, Error = Error> + Send> {
+        let tx = format!("{}", tx);
+        let params = json!({
+            "jsonrpc": "2",
+            "id": "1",
+            "method": "testmempoolaccept",
+            "params": [[tx]]
+        });
+        Box::new(
+            self.get_rpc_response::<RpcTestMempoolAcceptResponse>(&params)
+                .and_then(move |resp| {
+                    resp.result
+                        .into_iter()
+                        .next()
+                        .map(|result| MempoolAcceptance {
+                            allowed: result.allowed,
+                            reject_reason: result.reject_reason,
+                        })
+                        .ok_or(ectx!(err ErrorContext::BitcoinRpcConversion, ErrorKind::Internal => tx))
+                }),
+        )
+    }
+
+    fn get_unspent_output_value(
+        &self,
+        txid: String,
+        index: u32,
+        include_mempool: bool,
+    ) -> Box<Future<Item = Option<Amount>, Error = Error> + Send> {
+        let params = json!({
+            "jsonrpc": "2",
+            "id": "1",
+            "method": "gettxout",
+            "params": [txid, index, include_mempool]
+        });
+        Box::new(
+            self.get_rpc_response::<RpcTxOutResponse>(&params)
+                .map(|resp| resp.result.map(|out| out.value)),
+        )
+    }
+
+    fn get_mempool_txids(&self) -> Box<Future<Item = Vec<String>, Error = Error> + Send> {
+        let params = json!({
+            "jsonrpc": "2",
+            "id": "1",
+            "method": "getrawmempool",
+            "params": [false]
+        });
+        Box::new(self.get_rpc_response::<RpcRawMempoolResponse>(&params).map(|r| r.result))
+    }
 }
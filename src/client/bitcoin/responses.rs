@@ -18,6 +18,35 @@ pub struct RpcSendTransactionsResponse {
     pub result: TxHash,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcTestMempoolAcceptResponse {
+    pub result: Vec<RpcMempoolAcceptResult>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcMempoolAcceptResult {
+    pub allowed: bool,
+    #[serde(rename = "reject-reason")]
+    pub reject_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcTxOutResponse {
+    // null for spent outputs
+    pub result: Option<TxOut>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcRawMempoolResponse {
+    pub result: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TxOut {
+    #[serde(deserialize_with = "de_bitcoin_decimal")]
+    pub value: Amount,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct GetTransactionResponse {
     pub hash: String,
@@ -49,6 +78,34 @@ pub struct Block {
     pub tx: Vec<String>,
     pub height: u64,
     pub confirmations: usize,
+    // unix timestamp
+    pub time: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcBlockHeaderResponse {
+    pub result: BlockHeader,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockHeader {
+    pub hash: String,
+    pub height: u64,
+    // unix timestamp
+    pub time: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcBlockchainInfoResponse {
+    pub result: BlockchainInfo,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockchainInfo {
+    pub chain: String,
+    pub blocks: u64,
+    pub bestblockhash: String,
+    pub initialblockdownload: bool,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -83,7 +140,11 @@ pub struct RpcRawTransaction {
     pub txid: String,
     pub vin: Vec<Vin>,
     pub vout: Vec<Vout>,
+    // absent for transactions in mempool
+    #[serde(default)]
     pub confirmations: usize,
+    // absent for transactions in mempool
+    pub blockhash: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -143,24 +204,7 @@ where
 {
     let num: ::serde_json::Number = Deserialize::deserialize(deserializer)?;
     let s = num.to_string();
-    decimal_string_to_satoshis(&s).ok_or(::serde::de::Error::custom("Failed to parse bitcoin rpc amount"))
-}
-
-fn decimal_string_to_satoshis(s: &str) -> Option<Amount> {
-    let parts: Vec<&str> = s.split(".").collect();
-    let int = parts.get(0)?;
-    let float = parts.get(1)?;
-    let mut s = float.to_string();
-    // making sure we have at least 8 numbers
-    for _ in s.len()..8 {
-        s.push('0');
-    }
-    if s.len() != 8 {
-        return None;
-    }
-    let satoshisstr = format!("{}{}", int, s);
-    let val: u128 = u128::from_str_radix(&satoshisstr, 10).ok()?;
-    Some(Amount::new(val))
+    Amount::from_decimal_str(&s, CurrencyDecimals::BTC).ok_or(::serde::de::Error::custom("Failed to parse bitcoin rpc amount"))
 }
 
 #[cfg(test)]
@@ -176,11 +220,12 @@ mod tests {
             ("0.12345678", Some(12345678)),
             ("0.123456789", None),
             ("1.12345670", Some(112345670)),
+            ("1", Some(100000000)),
         ];
 
         for case in cases.iter() {
             let case = case.clone();
-            assert_eq!(decimal_string_to_satoshis(case.0), case.1.map(Amount::new));
+            assert_eq!(Amount::from_decimal_str(case.0, CurrencyDecimals::BTC), case.1.map(Amount::new));
         }
     }
 }
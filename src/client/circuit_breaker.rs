@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+/// Circuit breaker of an upstream host. Opens after `failure_threshold` consecutive failures,
+/// so that requests fail fast instead of waiting for timeouts of a host that is down.
+/// Once `reset_timeout` passes, requests are let through again and the first failure opens it back.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    reset_timeout: Duration,
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, reset_timeout: Duration) -> Self {
+        Self {
+            failure_threshold,
+            reset_timeout,
+            consecutive_failures: 0,
+            open_until: None,
+        }
+    }
+
+    pub fn allows_request(&self, now: Instant) -> bool {
+        match self.open_until {
+            Some(open_until) => now >= open_until,
+            None => true,
+        }
+    }
+
+    pub fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.open_until = None;
+    }
+
+    pub fn record_failure(&mut self, now: Instant) {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if self.failure_threshold > 0 && self.consecutive_failures >= self.failure_threshold {
+            self.open_until = Some(now + self.reset_timeout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let now = Instant::now();
+        let reset_timeout = Duration::from_secs(10);
+        let mut breaker = CircuitBreaker::new(3, reset_timeout);
+        breaker.record_failure(now);
+        breaker.record_failure(now);
+        breaker.record_success();
+        breaker.record_failure(now);
+        breaker.record_failure(now);
+        assert!(breaker.allows_request(now), "success resets failures");
+        breaker.record_failure(now);
+        assert!(!breaker.allows_request(now));
+        assert!(!breaker.allows_request(now + Duration::from_secs(9)));
+        let half_open = now + reset_timeout;
+        assert!(breaker.allows_request(half_open));
+        breaker.record_failure(half_open);
+        assert!(!breaker.allows_request(half_open), "failure after reset timeout opens it back");
+        breaker.record_success();
+        assert!(breaker.allows_request(half_open));
+
+        let mut disabled = CircuitBreaker::new(0, reset_timeout);
+        for _ in 0..100 {
+            disabled.record_failure(now);
+        }
+        assert!(disabled.allows_request(now));
+    }
+}
@@ -47,6 +47,18 @@ pub enum ErrorContext {
     Topics,
     #[fail(display = "http client error - error converting rpc transaction into blockchain transaction")]
     BitcoinRpcConversion,
+    #[fail(display = "http client error - contract deployment transaction receipt has no contract address")]
+    ContractAddress,
+    #[fail(display = "http client error - block timestamp is out of range")]
+    Timestamp,
+    #[fail(display = "http client error - no response from upstream within timeout")]
+    Timeout,
+    #[fail(display = "http client error - circuit breaker of upstream is open after repeated failures")]
+    CircuitBreaker,
+    #[fail(display = "http client error - utxos of this bitcoin network are not available")]
+    Network,
+    #[fail(display = "http client error - STQ contract is not configured for this ethereum network")]
+    StqContract,
 }
 
 #[allow(dead_code)]
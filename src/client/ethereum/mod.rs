@@ -1,32 +1,44 @@
 mod responses;
 
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
+use chrono::{DateTime, TimeZone, Utc};
 use hyper::{Body, Request};
 
 use self::responses::*;
 use super::error::*;
-use super::http_client::HttpClient;
-use config::Mode;
+use super::http_client::{HttpClient, Idempotent};
 use futures::{future, stream};
+use metrics::{self, RPC_CALLS};
 use models::*;
 use prelude::*;
 use serde_json;
 use utils::read_body;
 
 pub trait EthereumClient: Send + Sync + 'static {
-    /// Get account nonce (needed for creating transactions)
-    fn get_nonce(&self, address: EthereumAddress) -> Box<Future<Item = u64, Error = Error> + Send>;
+    /// Get account nonce (needed for creating transactions), with `BlockTag::Pending` it counts transactions in mempool
+    fn get_nonce(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = u64, Error = Error> + Send>;
+
+    /// Estimate gas used by call. Item is `Err` if node rejects the call, e.g. because its execution reverts
+    fn estimate_gas(&self, call: EthereumCall) -> Box<Future<Item = Result<Amount, ExecutionError>, Error = Error> + Send>;
+
+    /// Execute call at block without creating a transaction, returns hex encoded return data.
+    /// Item is `Err` if node rejects the call
+    fn call(&self, call: EthereumCall, block: BlockTag) -> Box<Future<Item = Result<String, ExecutionError>, Error = Error> + Send>;
 
     /// Send raw eth/stq transaction to blockchain
     fn send_raw_tx(&self, tx: RawEthereumTransaction) -> Box<Future<Item = TxHash, Error = Error> + Send>;
 
-    /// Get transaction by hash. Since getting block_number from transaction is not yet
-    /// supported, you need to provide one in arguments
+    /// Get mined transaction by hash, with block number and time of its block and confirmations
+    /// counted from the current block
     fn get_eth_transaction(&self, hash: String) -> Box<Future<Item = BlockchainTransaction, Error = Error> + Send>;
 
-    /// Get latest wei balance
-    fn get_eth_balance(&self, address: EthereumAddress) -> Box<Future<Item = Amount, Error = Error> + Send>;
+    /// Get execution status of eth transaction by hash
+    fn get_eth_transaction_status(&self, hash: String) -> Box<Future<Item = TransactionStatus, Error = Error> + Send>;
+
+    /// Get wei balance at block
+    fn get_eth_balance(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = Amount, Error = Error> + Send>;
 
     /// Get transactions from blocks starting from `start_block_hash` (or the most recent block if not specified)
     /// and fetch previous blocks. Total number of blocks = `blocks_count`.
@@ -37,6 +49,14 @@ pub trait EthereumClient: Send + Sync + 'static {
         blocks_count: u64,
     ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send>;
 
+    /// Same as `last_eth_transactions`, but blocks are counted back from `current_block` known by caller,
+    /// so the latest block number is not requested again
+    fn last_eth_transactions_to(
+        &self,
+        current_block: u64,
+        blocks_count: u64,
+    ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send>;
+
     /// Same as `get_eth_transaction` for stq. Since there could be many stq transfers in one transaction
     /// we return Stream here.
     fn get_stq_transactions(&self, hash: String) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send>;
@@ -48,17 +68,62 @@ pub trait EthereumClient: Send + Sync + 'static {
         blocks_count: u64,
     ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send>;
 
-    /// Get latest stq-wei balance
-    fn get_stq_balance(&self, address: EthereumAddress) -> Box<Future<Item = Amount, Error = Error> + Send>;
+    /// Same as `last_eth_transactions_to` for stq
+    fn last_stq_transactions_to(
+        &self,
+        current_block: u64,
+        blocks_count: u64,
+    ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send>;
+
+    /// Get stq-wei balance at block
+    fn get_stq_balance(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = Amount, Error = Error> + Send>;
+
+    /// Get wei balances of addresses at block with a single JSON-RPC batch.
+    /// Results are in order of addresses, `Err` has the message of node's error for the address
+    fn get_eth_balances(
+        &self,
+        addresses: Vec<EthereumAddress>,
+        block: BlockTag,
+    ) -> Box<Future<Item = Vec<Result<Amount, String>>, Error = Error> + Send>;
+
+    /// Same as `get_eth_balances` for stq-wei
+    fn get_stq_balances(
+        &self,
+        addresses: Vec<EthereumAddress>,
+        block: BlockTag,
+    ) -> Box<Future<Item = Vec<Result<Amount, String>>, Error = Error> + Send>;
+
+    /// Get non-zero value eth transactions of block `number`
+    fn get_eth_block_transactions(&self, number: u64) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send>;
+
+    /// Get stq transactions of block `number`
+    fn get_stq_block_transactions(&self, number: u64) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send>;
+
+    /// Get number of the latest block
+    fn get_block_number(&self) -> Box<Future<Item = u64, Error = Error> + Send>;
+
+    /// Get current block number and whether node is syncing
+    fn get_node_status(&self) -> Box<Future<Item = NodeStatus, Error = Error> + Send>;
+
+    /// Get EIP-155 chain id of network node is connected to
+    fn get_chain_id(&self) -> Box<Future<Item = u64, Error = Error> + Send>;
+
+    /// Get hex encoded code of contract at address at block, `0x` if there is no contract
+    fn get_code(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = String, Error = Error> + Send>;
 }
 
 const ADDRESS_LENGTH: usize = 40;
+const ZERO_ADDRESS: &str = "0000000000000000000000000000000000000000";
+
+/// Times of blocks by block hash, shared by logs of a single request, so that each block is fetched once
+type BlockTimes = Arc<Mutex<HashMap<String, DateTime<Utc>>>>;
 
 #[derive(Clone)]
 pub struct EthereumClientImpl {
     http_client: Arc<HttpClient>,
-    infura_url: String,
-    stq_contract_address: String,
+    rpc_url: String,
+    /// STQ methods fail if the contract is not deployed on the network
+    stq_contract_address: Option<String>,
     stq_transfer_topic: String,
     stq_approval_topic: String,
     stq_balance_method: String,
@@ -67,32 +132,33 @@ pub struct EthereumClientImpl {
 impl EthereumClientImpl {
     pub fn new(
         http_client: Arc<HttpClient>,
-        mode: Mode,
-        api_key: String,
-        stq_contract_address: String,
+        rpc_url: String,
+        stq_contract_address: Option<String>,
         stq_transfer_topic: String,
         stq_approval_topic: String,
         stq_balance_method: String,
     ) -> Self {
-        let infura_url = match mode {
-            Mode::Production => format!("https://mainnet.infura.io/v3/{}", api_key),
-            _ => format!("https://kovan.infura.io/v3/{}", api_key),
-        };
         Self {
             http_client,
-            infura_url,
+            rpc_url,
             stq_contract_address,
             stq_transfer_topic,
             stq_approval_topic,
             stq_balance_method,
         }
     }
+
+    fn stq_contract_address(&self) -> Result<String, Error> {
+        self.stq_contract_address
+            .clone()
+            .ok_or(ectx!(err ErrorContext::StqContract, ErrorKind::Internal))
+    }
 }
 
 impl EthereumClientImpl {
     // Eth
 
-    /// Gets NON-ZERO value eth transactions (that means that ERC-20 are not here)
+    /// Gets NON-ZERO value eth transactions (that means that ERC-20 are not here) and contract deployments of any value
     fn get_eth_transactions_for_block(&self, block: u64) -> impl Stream<Item = PartialBlockchainTransaction, Error = Error> + Send {
         let block = format!("0x{:x}", block);
         let params = json!({
@@ -102,18 +168,23 @@ impl EthereumClientImpl {
             "params": [block, true]
         });
         self.get_rpc_response::<BlockByNumberResponse>(&params)
+            .and_then(|resp| {
+                let block = resp.result.unwrap_or(Default::default());
+                let block_time = if block.timestamp.is_empty() {
+                    None
+                } else {
+                    Some(EthereumClientImpl::parse_timestamp(block.timestamp.clone())?)
+                };
+                Ok((block, block_time))
+            })
             .into_stream()
-            .map(|resp| {
-                stream::iter_result(
-                    resp.result
-                        .unwrap_or(Default::default())
-                        .transactions
-                        .into_iter()
-                        .map(|tx_resp| EthereumClientImpl::eth_response_to_partial_tx(tx_resp.clone())),
-                )
+            .map(|(block, block_time)| {
+                stream::iter_result(block.transactions.into_iter().map(move |tx_resp| {
+                    EthereumClientImpl::eth_response_to_partial_tx(tx_resp).map(|tx| PartialBlockchainTransaction { block_time, ..tx })
+                }))
             })
             .flatten()
-            .filter(|tx| tx.to[0].value.inner() > 0)
+            .filter(|tx| tx.contract_deployment || tx.to[0].value.inner() > 0)
     }
 
     fn last_eth_transactions_with_current_block(
@@ -153,12 +224,14 @@ impl EthereumClientImpl {
             "params": [hash]
         });
         self.get_rpc_response::<TransactionByHashResponse>(&params)
-            .and_then(|resp| EthereumClientImpl::eth_response_to_partial_tx(resp.result))
+            .and_then(move |resp| resp.result.ok_or(ectx!(err ErrorSource::Server, ErrorKind::NotFound => hash)))
+            .and_then(EthereumClientImpl::eth_response_to_partial_tx)
     }
 
     fn eth_response_to_partial_tx(resp: TransactionResponse) -> Result<PartialBlockchainTransaction, Error> {
         let TransactionResponse {
             block_number,
+            block_hash,
             hash,
             from,
             to,
@@ -169,7 +242,9 @@ impl EthereumClientImpl {
         let value = Amount::new(EthereumClientImpl::parse_hex(value)?);
         let gas_price = Amount::new(EthereumClientImpl::parse_hex(gas_price)?);
         let from = vec![(&from[2..]).to_string()];
-        let to_address = to.map(|t| (&t[2..]).to_string()).unwrap_or("0".to_string());
+        // contract creation has no `to`, the address of created contract is filled in from receipt
+        let contract_deployment = to.is_none();
+        let to_address = to.map(|t| (&t[2..]).to_string()).unwrap_or_default();
         let to = vec![BlockchainTransactionEntry {
             address: to_address,
             value,
@@ -179,9 +254,12 @@ impl EthereumClientImpl {
             from,
             to,
             block_number,
+            block_hash: (&block_hash[2..]).to_string(),
+            block_time: None,
             currency: Currency::Eth,
             gas_price,
             erc20_operation_kind: None,
+            contract_deployment,
         })
     }
 
@@ -192,8 +270,14 @@ impl EthereumClientImpl {
         from_block: u64,
         to_block: u64,
     ) -> impl Stream<Item = PartialBlockchainTransaction, Error = Error> + Send {
+        let stq_contract_address = match self.stq_contract_address() {
+            Ok(address) => address,
+            Err(e) => return future::Either::A(stream::once(Err(e))),
+        };
         let self_clone = self.clone();
         let self_clone2 = self.clone();
+        let self_clone3 = self.clone();
+        let block_times = BlockTimes::default();
         let from_block = format!("0x{:x}", from_block);
         let to_block = format!("0x{:x}", to_block);
         let params_approval = json!({
@@ -201,7 +285,7 @@ impl EthereumClientImpl {
             "id": 1,
             "method": "eth_getLogs",
             "params": [{
-                "address": self.stq_contract_address,
+                "address": stq_contract_address,
                 "topics": [self.stq_approval_topic],
                 "fromBlock": from_block,
                 "toBlock": to_block,
@@ -212,13 +296,14 @@ impl EthereumClientImpl {
             "id": 1,
             "method": "eth_getLogs",
             "params": [{
-                "address": self.stq_contract_address,
+                "address": stq_contract_address,
                 "topics": [self.stq_transfer_topic],
                 "fromBlock": from_block,
                 "toBlock": to_block,
             }]
         });
-        self.get_rpc_response::<StqResponse>(&params_approval)
+        let logs = self
+            .get_rpc_response::<StqResponse>(&params_approval)
             .join(self.get_rpc_response::<StqResponse>(&params_transfer))
             .map(|(approval_resp, transfer_resp)| approval_resp.concat(transfer_resp))
             .into_stream()
@@ -230,6 +315,8 @@ impl EthereumClientImpl {
                     .map(|tx| (tx_resp, tx.gas_price))
             })
             .and_then(move |(tx_resp, gas_price)| self_clone2.stq_response_to_partial_tx(tx_resp, gas_price))
+            .and_then(move |tx| self_clone3.with_block_time(tx, block_times.clone()));
+        future::Either::B(logs)
     }
 
     fn last_stq_transactions_with_current_block(
@@ -267,7 +354,12 @@ impl EthereumClientImpl {
         let self_clone2 = self.clone();
         let self_clone3 = self.clone();
         let self_clone4 = self.clone();
-        let stq_contract_address = self.stq_contract_address.clone();
+        let self_clone5 = self.clone();
+        let block_times = BlockTimes::default();
+        let stq_contract_address = match self.stq_contract_address() {
+            Ok(address) => address,
+            Err(e) => return Box::new(stream::once(Err(e))),
+        };
         let stq_transfer_topic = self.stq_transfer_topic.clone();
         let stq_approval_topic = self.stq_approval_topic.clone();
         let hash_clone = hash.clone();
@@ -312,6 +404,7 @@ impl EthereumClientImpl {
                         .map(|tx| (tx_resp, tx.gas_price))
                 })
                 .and_then(move |(resp, gas_price)| self_clone4.stq_response_to_partial_tx(resp, gas_price))
+                .and_then(move |tx| self_clone5.with_block_time(tx, block_times.clone()))
                 .and_then(move |partial_tx| self_clone2.partial_tx_to_tx(&partial_tx, current_block))
                 .map(Some)
                 .or_else(|e| match e.kind() {
@@ -329,14 +422,6 @@ impl EthereumClientImpl {
             .get(0)
             .ok_or(ectx!(try err ErrorContext::Topics, ErrorKind::Internal))?
             .to_string();
-        let erc20_operation_kind = if topic == self.stq_approval_topic {
-            Some(Erc20OperationKind::Approve)
-        } else if topic == self.stq_transfer_topic {
-            Some(Erc20OperationKind::TransferFrom)
-        } else {
-            None
-        };
-
         let from = log
             .topics
             .get(1)
@@ -354,8 +439,16 @@ impl EthereumClientImpl {
                 slice.to_string()
             })
             .ok_or(ectx!(try err ErrorContext::Topics, ErrorKind::Internal))?;
+        let erc20_operation_kind = if topic == self.stq_approval_topic {
+            Some(Erc20OperationKind::Approve)
+        } else if topic == self.stq_transfer_topic {
+            Some(erc20_transfer_kind(&from, &to))
+        } else {
+            None
+        };
         let block_number = EthereumClientImpl::parse_hex(log.block_number).map(|x| x as u64)?;
         let value = EthereumClientImpl::parse_hex(log.data).map(Amount::new)?;
+        let block_hash = log.block_hash[2..].to_string();
         let log_index = EthereumClientImpl::parse_hex(log.transaction_log_index)?;
         // Since there can be many ERC-20 transfers per ETH transaction, we're giving extended hash here
         let hash = format!("{}:{}", log.transaction_hash[2..].to_string(), log_index);
@@ -366,9 +459,12 @@ impl EthereumClientImpl {
             from,
             to,
             block_number,
+            block_hash,
+            block_time: None,
             currency: Currency::Stq,
             gas_price,
             erc20_operation_kind,
+            contract_deployment: false,
         })
     }
 
@@ -387,6 +483,18 @@ impl EthereumClientImpl {
         })
     }
 
+    fn is_syncing(&self) -> impl Future<Item = bool, Error = Error> + Send {
+        let params = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_syncing",
+            "params": []
+        });
+
+        self.get_rpc_response::<SyncingResponse>(&params)
+            .map(|resp| resp.result != ::serde_json::Value::Bool(false))
+    }
+
     fn get_block_number_by_hash(&self, hash: String) -> impl Future<Item = u64, Error = Error> + Send {
         let hash = format!("0x{}", hash);
         let params = json!({
@@ -401,28 +509,123 @@ impl EthereumClientImpl {
             .map(|x| x as u64)
     }
 
+    fn get_block_time(&self, hash: String) -> impl Future<Item = DateTime<Utc>, Error = Error> + Send {
+        let hash = format!("0x{}", hash);
+        let params = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_getBlockByHash",
+            "params": [hash, false]
+        });
+
+        self.get_rpc_response::<ShortBlockResponse>(&params)
+            .and_then(|resp| EthereumClientImpl::parse_timestamp(resp.result.timestamp))
+    }
+
+    /// Sets time of the block of transaction, fetching it only if it's not in `block_times` yet
+    fn with_block_time(
+        &self,
+        tx: PartialBlockchainTransaction,
+        block_times: BlockTimes,
+    ) -> impl Future<Item = PartialBlockchainTransaction, Error = Error> + Send {
+        let cached = block_times.lock().unwrap().get(&tx.block_hash).cloned();
+        match tx.block_time.or(cached) {
+            Some(block_time) => future::Either::A(future::ok(PartialBlockchainTransaction {
+                block_time: Some(block_time),
+                ..tx
+            })),
+            None => future::Either::B(self.get_block_time(tx.block_hash.clone()).map(move |block_time| {
+                block_times.lock().unwrap().insert(tx.block_hash.clone(), block_time);
+                PartialBlockchainTransaction {
+                    block_time: Some(block_time),
+                    ..tx
+                }
+            })),
+        }
+    }
+
+    fn parse_timestamp(s: String) -> Result<DateTime<Utc>, Error> {
+        let secs = EthereumClientImpl::parse_hex(s.clone())?;
+        Utc.timestamp_opt(secs as i64, 0)
+            .single()
+            .ok_or(ectx!(err ErrorContext::Timestamp, ErrorKind::Internal => s))
+    }
+
     fn parse_hex(s: String) -> Result<u128, Error> {
         u128::from_str_radix(&s[2..], 16).map_err(ectx!(ErrorContext::Hex, ErrorKind::Internal => s))
     }
 
+    /// Sends requests that could be built as a single JSON-RPC batch, others are reported as errors
+    fn get_balances_batch(
+        &self,
+        requests: Vec<Result<::serde_json::Value, String>>,
+    ) -> impl Future<Item = Vec<Result<Amount, String>>, Error = Error> + Send {
+        let batch: Vec<_> = requests
+            .iter()
+            .enumerate()
+            .filter_map(|(id, request)| {
+                request.as_ref().ok().map(|request| {
+                    let mut request = request.clone();
+                    request["jsonrpc"] = json!("2.0");
+                    request["id"] = json!(id);
+                    request
+                })
+            })
+            .collect();
+        let responses = if batch.is_empty() {
+            future::Either::A(future::ok(vec![]))
+        } else {
+            future::Either::B(self.get_rpc_response::<Vec<RpcCallResponse>>(&json!(batch)))
+        };
+        responses.map(move |responses| {
+            let mut results: HashMap<u64, Result<Amount, String>> =
+                responses.into_iter().map(|resp| (resp.id, balance_result(resp))).collect();
+            requests
+                .into_iter()
+                .enumerate()
+                .map(|(id, request)| {
+                    request.and_then(|_| {
+                        results
+                            .remove(&(id as u64))
+                            .unwrap_or_else(|| Err("no response from node".to_string()))
+                    })
+                })
+                .collect()
+        })
+    }
+
     fn get_rpc_response<T>(&self, params: &::serde_json::Value) -> impl Future<Item = T, Error = Error> + Send
     where
         for<'a> T: Send + 'static + ::serde::Deserialize<'a>,
     {
         let http_client = self.http_client.clone();
+        // method of batch is the one of its first request
+        let method = params["method"]
+            .as_str()
+            .or_else(|| params[0]["method"].as_str())
+            .unwrap_or("unknown")
+            .to_string();
+        let is_send = method == "eth_sendRawTransaction";
         let params_clone = params.clone();
         let params_clone2 = params.clone();
-        let infura_url = self.infura_url.clone();
+        let rpc_url = self.rpc_url.clone();
         serde_json::to_string(params)
             .map_err(ectx!(ErrorContext::Json, ErrorKind::Internal => params))
             .and_then(|body| {
                 Request::builder()
                     .header("Content-Type", "application/json")
                     .method("POST")
-                    .uri(self.infura_url.clone())
+                    .uri(self.rpc_url.clone())
                     .body(Body::from(body.clone()))
                     .map_err(ectx!(ErrorSource::Hyper, ErrorKind::Internal => body))
             })
+            .map(move |mut request| {
+                // sending transaction again would fail as it's already known to node
+                if !is_send {
+                    request.extensions_mut().insert(Idempotent);
+                }
+                request
+            })
             .into_future()
             .and_then(move |request| http_client.request(request))
             .and_then(|resp| read_body(resp.into_body()).map_err(ectx!(ErrorKind::Internal => params_clone)))
@@ -432,7 +635,13 @@ impl EthereumClientImpl {
             })
             .and_then(move |string| {
                 serde_json::from_str::<T>(&string)
-                    .map_err(ectx!(ErrorContext::Json, ErrorKind::Internal => string.clone(), params_clone2, infura_url))
+                    .map_err(ectx!(ErrorContext::Json, ErrorKind::Internal => string.clone(), params_clone2, rpc_url))
+            })
+            .then(move |res| {
+                RPC_CALLS
+                    .with_label_values(&["ethereum", &method, metrics::result_label(&res)])
+                    .inc();
+                res
             })
     }
 
@@ -450,46 +659,78 @@ impl EthereumClientImpl {
             "params": [hash]
         });
         let gas_price = tx.gas_price;
+        let block_time_f = match tx.block_time {
+            Some(block_time) => future::Either::A(Ok(block_time).into_future()),
+            None => future::Either::B(self.get_block_time(tx.block_hash.clone())),
+        };
         let tx = tx.clone();
-        self.get_rpc_response::<TransactionReceiptResponse>(&params).and_then(move |resp| {
-            let result = match resp.result {
-                Some(ref res) => res.clone(),
-                None => return futures::future::Either::A(Err(ErrorKind::NoReceipt.into()).into_future()),
-            };
-            let resp_clone = resp.clone();
-            let gas_used = EthereumClientImpl::parse_hex(result.gas_used).map(Amount::new).into_future();
-            let block_number = EthereumClientImpl::parse_hex(result.block_number).into_future();
-            futures::future::Either::B(gas_used.join(block_number).and_then(move |(gas_used, block_number)| {
-                gas_used
-                    .checked_mul(gas_price)
-                    .ok_or(ectx!(err ErrorContext::Overflow, ErrorKind::Internal => resp_clone, gas_price))
-                    .map(move |fee| {
-                        let confirmations = (current_block as usize) - block_number as usize;
-                        BlockchainTransaction {
-                            hash: tx.hash,
-                            from: tx.from,
-                            to: tx.to,
-                            block_number: tx.block_number,
-                            currency: tx.currency,
-                            fee,
-                            confirmations,
-                            erc20_operation_kind: tx.erc20_operation_kind,
-                        }
-                    })
-            }))
-        })
+        self.get_rpc_response::<TransactionReceiptResponse>(&params)
+            .join(block_time_f)
+            .and_then(move |(resp, block_time)| {
+                let result = match resp.result {
+                    Some(ref res) => res.clone(),
+                    None => return futures::future::Either::A(Err(ErrorKind::NoReceipt.into()).into_future()),
+                };
+                let resp_clone = resp.clone();
+                let to = EthereumClientImpl::receipt_receivers(&tx, &result).into_future();
+                let gas_used = EthereumClientImpl::parse_hex(result.gas_used).map(Amount::new).into_future();
+                let block_number = EthereumClientImpl::parse_hex(result.block_number).into_future();
+                futures::future::Either::B(gas_used.join3(block_number, to).and_then(move |(gas_used, block_number, to)| {
+                    gas_used
+                        .checked_mul(gas_price)
+                        .ok_or(ectx!(err ErrorContext::Overflow, ErrorKind::Internal => resp_clone, gas_price))
+                        .map(move |fee| {
+                            let confirmations = (current_block as usize) - block_number as usize;
+                            BlockchainTransaction {
+                                hash: tx.hash,
+                                from: tx.from,
+                                to,
+                                block_number: Some(tx.block_number),
+                                block_hash: Some(tx.block_hash),
+                                block_time: Some(block_time),
+                                currency: tx.currency,
+                                fee,
+                                confirmations,
+                                erc20_operation_kind: tx.erc20_operation_kind,
+                                contract_deployment: tx.contract_deployment,
+                            }
+                        })
+                }))
+            })
+    }
+
+    /// Receivers of the transaction. For contract deployment it's the address of the created contract.
+    fn receipt_receivers(
+        tx: &PartialBlockchainTransaction,
+        receipt: &TransactionReceipt,
+    ) -> Result<Vec<BlockchainTransactionEntry>, Error> {
+        if !tx.contract_deployment {
+            return Ok(tx.to.clone());
+        }
+        let contract_address = receipt
+            .contract_address
+            .as_ref()
+            .map(|address| (&address[2..]).to_string())
+            .ok_or(ectx!(try err ErrorContext::ContractAddress, ErrorKind::Internal => tx.hash, receipt))?;
+        Ok(tx
+            .to
+            .iter()
+            .map(|entry| BlockchainTransactionEntry {
+                address: contract_address.clone(),
+                value: entry.value,
+            })
+            .collect())
     }
 }
 
 impl EthereumClient for EthereumClientImpl {
-    /// Get latest wei balance
-    fn get_eth_balance(&self, address: EthereumAddress) -> Box<Future<Item = Amount, Error = Error> + Send> {
+    fn get_eth_balance(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = Amount, Error = Error> + Send> {
         let address = format!("0x{}", address);
         let params = json!({
             "jsonrpc": "2.0",
             "id": 1,
             "method": "eth_getBalance",
-            "params": [address, "latest"]
+            "params": [address, block.to_string()]
         });
         Box::new(
             self.get_rpc_response::<BalanceResponse>(&params)
@@ -514,6 +755,43 @@ impl EthereumClient for EthereumClientImpl {
         )
     }
 
+    fn last_eth_transactions_to(
+        &self,
+        current_block: u64,
+        blocks_count: u64,
+    ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        Box::new(self.last_eth_transactions_with_current_block(None, blocks_count, current_block))
+    }
+
+    fn get_eth_transaction_status(&self, hash: String) -> Box<Future<Item = TransactionStatus, Error = Error> + Send> {
+        let self_clone = self.clone();
+        let hash = format!("0x{}", hash);
+        let params = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_getTransactionByHash",
+            "params": [hash]
+        });
+        Box::new(
+            self.get_rpc_response::<ShortTransactionByHashResponse>(&params)
+                .and_then(move |resp| resp.result.ok_or(ectx!(err ErrorSource::Server, ErrorKind::NotFound => hash)))
+                .and_then(move |tx| {
+                    let params = json!({
+                        "jsonrpc": "2.0",
+                        "id": 1,
+                        "method": "eth_getTransactionReceipt",
+                        "params": [tx.hash]
+                    });
+                    self_clone.get_rpc_response::<TransactionReceiptResponse>(&params)
+                })
+                .map(|resp| match resp.result {
+                    None => TransactionStatus::Pending,
+                    Some(ref receipt) if receipt.status == Some("0x0".to_string()) => TransactionStatus::Failed,
+                    Some(_) => TransactionStatus::Success,
+                }),
+        )
+    }
+
     fn get_eth_transaction(&self, hash: String) -> Box<Future<Item = BlockchainTransaction, Error = Error> + Send> {
         let self_clone = self.clone();
         let f1 = self.get_current_block_number();
@@ -524,18 +802,21 @@ impl EthereumClient for EthereumClientImpl {
         )
     }
 
-    /// Get latest stq-wei balance
-    fn get_stq_balance(&self, address: EthereumAddress) -> Box<Future<Item = Amount, Error = Error> + Send> {
+    fn get_stq_balance(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = Amount, Error = Error> + Send> {
         let address = match serialize_address(address) {
             Ok(address) => address,
             Err(e) => return Box::new(Err(e).into_future()),
         };
+        let stq_contract_address = match self.stq_contract_address() {
+            Ok(stq_contract_address) => stq_contract_address,
+            Err(e) => return Box::new(Err(e).into_future()),
+        };
         let data = format!("{}{}", self.stq_balance_method, address);
         let params = json!({
             "jsonrpc": "2.0",
             "id": 1,
             "method": "eth_call",
-            "params": [{"to": self.stq_contract_address, "data": data}, "latest"]
+            "params": [{"to": stq_contract_address, "data": data}, block.to_string()]
         });
         Box::new(
             self.get_rpc_response::<BalanceResponse>(&params)
@@ -560,6 +841,14 @@ impl EthereumClient for EthereumClientImpl {
         )
     }
 
+    fn last_stq_transactions_to(
+        &self,
+        current_block: u64,
+        blocks_count: u64,
+    ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        Box::new(self.last_stq_transactions_with_current_block(None, blocks_count, current_block))
+    }
+
     fn get_stq_transactions(&self, hash: String) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
         let self_clone = self.clone();
         Box::new(
@@ -573,13 +862,122 @@ impl EthereumClient for EthereumClientImpl {
         )
     }
 
-    fn get_nonce(&self, address: EthereumAddress) -> Box<Future<Item = u64, Error = Error> + Send> {
+    fn get_eth_balances(
+        &self,
+        addresses: Vec<EthereumAddress>,
+        block: BlockTag,
+    ) -> Box<Future<Item = Vec<Result<Amount, String>>, Error = Error> + Send> {
+        let requests = addresses
+            .into_iter()
+            .map(|address| {
+                Ok(json!({
+                    "method": "eth_getBalance",
+                    "params": [format!("0x{}", address), block.to_string()]
+                }))
+            })
+            .collect();
+        Box::new(self.get_balances_batch(requests))
+    }
+
+    fn get_stq_balances(
+        &self,
+        addresses: Vec<EthereumAddress>,
+        block: BlockTag,
+    ) -> Box<Future<Item = Vec<Result<Amount, String>>, Error = Error> + Send> {
+        let stq_contract_address = match self.stq_contract_address() {
+            Ok(stq_contract_address) => stq_contract_address,
+            Err(e) => return Box::new(Err(e).into_future()),
+        };
+        let requests = addresses
+            .into_iter()
+            .map(|address| {
+                let data = serialize_address(address.clone()).map_err(|_| format!("{} is not a hex encoded address", address))?;
+                Ok(json!({
+                    "method": "eth_call",
+                    "params": [
+                        {"to": stq_contract_address, "data": format!("{}{}", self.stq_balance_method, data)},
+                        block.to_string()
+                    ]
+                }))
+            })
+            .collect();
+        Box::new(self.get_balances_batch(requests))
+    }
+
+    fn get_eth_block_transactions(&self, number: u64) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        let self_clone = self.clone();
+        Box::new(
+            self.get_current_block_number()
+                .into_stream()
+                .map(move |current_block| {
+                    let self_clone2 = self_clone.clone();
+                    self_clone
+                        .get_eth_transactions_for_block(number)
+                        .and_then(move |tx| self_clone2.partial_tx_to_tx(&tx, current_block))
+                })
+                .flatten(),
+        )
+    }
+
+    fn get_stq_block_transactions(&self, number: u64) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        let self_clone = self.clone();
+        Box::new(
+            self.get_current_block_number()
+                .into_stream()
+                .map(move |current_block| {
+                    let self_clone2 = self_clone.clone();
+                    self_clone
+                        .get_stq_transactions_for_blocks(number, number)
+                        .and_then(move |tx| self_clone2.partial_tx_to_tx(&tx, current_block))
+                })
+                .flatten(),
+        )
+    }
+
+    fn get_block_number(&self) -> Box<Future<Item = u64, Error = Error> + Send> {
+        Box::new(self.get_current_block_number())
+    }
+
+    fn get_node_status(&self) -> Box<Future<Item = NodeStatus, Error = Error> + Send> {
+        Box::new(
+            self.get_current_block_number()
+                .join(self.is_syncing())
+                .map(|(block_number, syncing)| NodeStatus { block_number, syncing }),
+        )
+    }
+
+    fn get_chain_id(&self) -> Box<Future<Item = u64, Error = Error> + Send> {
+        let params = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_chainId",
+            "params": []
+        });
+
+        Box::new(self.get_rpc_response::<NonceResponse>(&params).and_then(|resp| {
+            u64::from_str_radix(resp.result.trim_start_matches("0x"), 16)
+                .map_err(ectx!(ErrorContext::Hex, ErrorKind::Internal => resp.result))
+        }))
+    }
+
+    fn get_code(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = String, Error = Error> + Send> {
+        let params = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_getCode",
+            "params": [format!("0x{}", address), block.to_string()]
+        });
+
+        Box::new(self.get_rpc_response::<NonceResponse>(&params).map(|resp| resp.result))
+    }
+
+    fn get_nonce(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = u64, Error = Error> + Send> {
         let address_str = format!("0x{}", address);
         let params = json!({
             "jsonrpc": "2.0",
             "id": 1,
             "method": "eth_getTransactionCount",
-            "params": [address_str, "latest"]
+            "params": [address_str, block.to_string()]
         });
 
         Box::new(self.get_rpc_response::<NonceResponse>(&params).and_then(|resp| {
@@ -587,6 +985,36 @@ impl EthereumClient for EthereumClientImpl {
         }))
     }
 
+    fn estimate_gas(&self, call: EthereumCall) -> Box<Future<Item = Result<Amount, ExecutionError>, Error = Error> + Send> {
+        let params = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_estimateGas",
+            "params": [call_object(&call)]
+        });
+        Box::new(
+            self.get_rpc_response::<RpcCallResponse>(&params)
+                .and_then(move |resp| match call_result(resp, &params)? {
+                    Ok(gas) => EthereumClientImpl::parse_hex(gas).map(|gas| Ok(Amount::new(gas))),
+                    Err(e) => Ok(Err(e)),
+                }),
+        )
+    }
+
+    fn call(&self, call: EthereumCall, block: BlockTag) -> Box<Future<Item = Result<String, ExecutionError>, Error = Error> + Send> {
+        let params = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_call",
+            "params": [call_object(&call), block.to_string()]
+        });
+        Box::new(
+            self.get_rpc_response::<RpcCallResponse>(&params)
+                .and_then(move |resp| call_result(resp, &params))
+                .map(|res| res.map(|data| data.trim_start_matches("0x").to_string())),
+        )
+    }
+
     fn send_raw_tx(&self, tx: RawEthereumTransaction) -> Box<Future<Item = TxHash, Error = Error> + Send> {
         let tx_str = format!("0x{}", tx);
         let params = json!({
@@ -626,6 +1054,86 @@ pub fn hex_to_bytes(hex: String) -> Result<Vec<u8>, Error> {
         .collect()
 }
 
+/// ERC-20 transfers from the zero address are mints and to the zero address are burns
+fn erc20_transfer_kind(from: &str, to: &str) -> Erc20OperationKind {
+    if from == ZERO_ADDRESS {
+        Erc20OperationKind::Mint
+    } else if to == ZERO_ADDRESS {
+        Erc20OperationKind::Burn
+    } else {
+        Erc20OperationKind::TransferFrom
+    }
+}
+
+/// Call object of `eth_call` and `eth_estimateGas`, absent fields are omitted
+fn call_object(call: &EthereumCall) -> ::serde_json::Value {
+    let mut object = json!({});
+    if let Some(ref from) = call.from {
+        object["from"] = json!(format!("0x{}", from));
+    }
+    if let Some(ref to) = call.to {
+        object["to"] = json!(format!("0x{}", to));
+    }
+    if let Some(value) = call.value {
+        object["value"] = json!(format!("0x{:x}", value.inner()));
+    }
+    if let Some(ref data) = call.data {
+        object["data"] = json!(format!("0x{}", data));
+    }
+    object
+}
+
+/// Fragments of messages of node errors caused by execution of the call, e.g. `execution reverted` of geth
+const EXECUTION_ERROR_MESSAGES: &[&str] = &[
+    "revert",
+    "out of gas",
+    "gas required exceeds",
+    "invalid opcode",
+    "invalid jump",
+    "insufficient funds",
+    "stack underflow",
+    "stack limit",
+];
+
+/// Whether call is rejected because of its execution rather than failure of node, e.g. rate limiting or unknown block
+fn is_execution_error(error: &ExecutionError) -> bool {
+    match error.code {
+        // geth reports reverts with revert data with code 3, parity reports VM errors with -32015
+        3 | -32015 => true,
+        -32000 => {
+            let message = error.message.to_lowercase();
+            EXECUTION_ERROR_MESSAGES.iter().any(|fragment| message.contains(fragment))
+        }
+        _ => false,
+    }
+}
+
+/// Result of call or the execution error node rejected it with. Other node errors fail the request
+fn call_result(resp: RpcCallResponse, params: &::serde_json::Value) -> Result<Result<String, ExecutionError>, Error> {
+    match (resp.result, resp.error) {
+        (_, Some(error)) => {
+            if is_execution_error(&error) {
+                Ok(Err(error))
+            } else {
+                Err(ectx!(err ErrorSource::Server, ErrorKind::BadGateway => params, error))
+            }
+        }
+        (Some(result), None) => Ok(Ok(result)),
+        (None, None) => Err(ectx!(err ErrorContext::Json, ErrorKind::Internal => params)),
+    }
+}
+
+/// Balance from response of batched `eth_getBalance` or `eth_call` of `balanceOf`
+fn balance_result(resp: RpcCallResponse) -> Result<Amount, String> {
+    match (resp.result, resp.error) {
+        (_, Some(error)) => Err(error.message),
+        (Some(result), None) => u128::from_str_radix(result.trim_start_matches("0x"), 16)
+            .map(Amount::new)
+            .map_err(|_| format!("node returned invalid balance {}", result)),
+        (None, None) => Err("node returned neither result, nor error".to_string()),
+    }
+}
+
 fn serialize_address(address: EthereumAddress) -> Result<String, Error> {
     hex_to_bytes(address.into_inner())
         .map(|data| to_padded_32_bytes(&data))
@@ -641,3 +1149,50 @@ fn to_padded_32_bytes(data: &[u8]) -> Vec<u8> {
     res.extend(data.iter());
     res
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_erc20_transfer_kind() {
+        let address = "1bf2092a42166b2ae19b7b23752e7d2dab5ba91a";
+        assert_eq!(erc20_transfer_kind(ZERO_ADDRESS, address), Erc20OperationKind::Mint);
+        assert_eq!(erc20_transfer_kind(address, ZERO_ADDRESS), Erc20OperationKind::Burn);
+        assert_eq!(erc20_transfer_kind(address, address), Erc20OperationKind::TransferFrom);
+    }
+
+    #[test]
+    fn test_balance_result() {
+        let resp: Vec<RpcCallResponse> = serde_json::from_value(json!([
+            {"jsonrpc": "2.0", "id": 0, "result": "0x0de0b6b3a7640000"},
+            {"jsonrpc": "2.0", "id": 1, "error": {"code": -32000, "message": "header not found"}},
+            {"jsonrpc": "2.0", "id": 2, "result": "0x"}
+        ]))
+        .unwrap();
+        let results: Vec<_> = resp.into_iter().map(|resp| (resp.id, balance_result(resp))).collect();
+        assert_eq!(results[0], (0, Ok(Amount::new(1_000_000_000_000_000_000))));
+        assert_eq!(results[1], (1, Err("header not found".to_string())));
+        assert!(results[2].1.is_err());
+    }
+
+    #[test]
+    fn test_call_result() {
+        let resp: Vec<RpcCallResponse> = serde_json::from_value(json!([
+            {"jsonrpc": "2.0", "id": 0, "result": "0x01"},
+            {"jsonrpc": "2.0", "id": 1, "error": {"code": 3, "message": "execution reverted: paused"}},
+            {"jsonrpc": "2.0", "id": 2, "error": {"code": -32000, "message": "gas required exceeds allowance (30000000)"}},
+            {"jsonrpc": "2.0", "id": 3, "error": {"code": -32000, "message": "header not found"}},
+            {"jsonrpc": "2.0", "id": 4, "error": {"code": -32005, "message": "daily request count exceeded"}},
+            {"jsonrpc": "2.0", "id": 5, "error": {"code": -32603, "message": "internal error"}}
+        ]))
+        .unwrap();
+        let results: Vec<_> = resp.into_iter().map(|resp| call_result(resp, &json!({}))).collect();
+        assert_eq!(results[0].as_ref().unwrap(), &Ok("0x01".to_string()));
+        assert!(results[1].as_ref().unwrap().is_err());
+        assert!(results[2].as_ref().unwrap().is_err());
+        for result in &results[3..] {
+            assert_eq!(result.as_ref().unwrap_err().kind(), ErrorKind::BadGateway);
+        }
+    }
+}
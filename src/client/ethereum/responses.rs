@@ -1,3 +1,5 @@
+use chrono::{DateTime, Utc};
+
 use models::*;
 
 #[derive(Debug, Clone, Deserialize)]
@@ -5,6 +7,16 @@ pub struct NonceResponse {
     pub result: String,
 }
 
+/// Response of `eth_call` or `eth_estimateGas`, that has `error` if node rejects the call
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcCallResponse {
+    /// Id of request, matches responses of batch to requests
+    #[serde(default)]
+    pub id: u64,
+    pub result: Option<String>,
+    pub error: Option<ExecutionError>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct BlockNumberResponse {
     pub result: String,
@@ -23,19 +35,33 @@ pub struct BlockByNumberResponse {
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct BlockResponse {
     pub number: String,
+    pub timestamp: String,
     pub transactions: Vec<TransactionResponse>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TransactionByHashResponse {
-    pub result: TransactionResponse,
+    pub result: Option<TransactionResponse>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShortTransactionByHashResponse {
+    pub result: Option<ShortTransaction>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShortTransaction {
+    pub hash: String,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TransactionResponse {
     pub block_number: String,
+    pub block_hash: String,
     pub hash: String,
     pub from: String,
     pub to: Option<String>,
@@ -81,6 +107,13 @@ fn default_tx_log_idx() -> String {
     "0x0".to_string()
 }
 
+/// `false` if node is not syncing, otherwise sync progress object
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncingResponse {
+    pub result: ::serde_json::Value,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ShortBlockResponse {
@@ -90,6 +123,7 @@ pub struct ShortBlockResponse {
 #[serde(rename_all = "camelCase")]
 pub struct ShortBlock {
     pub number: String,
+    pub timestamp: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -103,6 +137,9 @@ pub struct TransactionReceiptResponse {
 pub struct TransactionReceipt {
     pub block_number: String,
     pub gas_used: String,
+    pub contract_address: Option<String>,
+    /// `0x1` for success, `0x0` for failure. Absent in pre-byzantium receipts
+    pub status: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -112,7 +149,11 @@ pub struct PartialBlockchainTransaction {
     pub from: Vec<String>,
     pub to: Vec<BlockchainTransactionEntry>,
     pub block_number: u64,
+    pub block_hash: String,
+    // `None` if block header wasn't fetched along with transaction
+    pub block_time: Option<DateTime<Utc>>,
     pub currency: Currency,
     pub gas_price: Amount,
     pub erc20_operation_kind: Option<Erc20OperationKind>,
+    pub contract_deployment: bool,
 }
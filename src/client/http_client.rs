@@ -1,98 +1,364 @@
-use config::Config;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use config::{Config, Upstream, UpstreamSettings};
 use failure::Fail;
-use futures::future::Either;
+use futures::future::{self, Either, Loop};
 use futures::prelude::*;
 use hyper;
-use hyper::{client::HttpConnector, Body, Request, Response};
+use hyper::client::connect::{Connect, Connected, Destination};
+use hyper::header::{HeaderMap, HeaderValue};
+use hyper::{client::HttpConnector, Body, Method, Request, Response, Uri, Version};
 use hyper_tls::HttpsConnector;
-use log::{self, Level};
+use log::Level;
+use rand::{self, Rng};
+use tokio::timer::{Delay, Timeout};
 
+use super::circuit_breaker::CircuitBreaker;
 use super::error::*;
+use metrics::{self, HTTP_CLIENT_REQUESTS, HTTP_CLIENT_REQUEST_DURATION};
 use utils::read_body;
 
 pub trait HttpClient: Send + Sync + 'static {
     fn request(&self, req: Request<Body>) -> Box<Future<Item = Response<Body>, Error = Error> + Send>;
 }
 
+/// Request extension marking request as safe to retry regardless of its method,
+/// e.g. JSON-RPC call that doesn't change state
+#[derive(Debug, Clone, Copy)]
+pub struct Idempotent;
+
 #[derive(Clone)]
 pub struct HttpClientImpl {
-    cli: hyper::Client<HttpsConnector<HttpConnector>>,
+    cli: hyper::Client<TimeoutConnector>,
+    upstreams: Arc<Upstreams>,
     log_level: Level,
 }
 
 impl HttpClientImpl {
     pub fn new(config: &Config, log_level: Level) -> Self {
-        let connector = HttpsConnector::new(config.client.dns_threads).unwrap();
+        let upstreams = Arc::new(Upstreams::new(config.client.upstreams.clone()));
         // connector.https_only(true);
-        let cli = hyper::Client::builder().build(connector);
-        Self { cli, log_level }
+        let connector = TimeoutConnector {
+            inner: HttpsConnector::new(config.client.dns_threads).unwrap(),
+            upstreams: upstreams.clone(),
+        };
+        let cli = hyper::Client::builder()
+            .keep_alive(true)
+            .keep_alive_timeout(Duration::from_secs(config.client.http_pool_idle_timeout_secs))
+            .max_idle_per_host(config.client.http_pool_max_idle_per_host)
+            .build(connector);
+        Self { cli, upstreams, log_level }
+    }
+
+    /// Client sharing the same connection pool and circuit breakers, but logging requests with another level
+    pub fn with_log_level(&self, log_level: Level) -> Self {
+        Self {
+            cli: self.cli.clone(),
+            upstreams: self.upstreams.clone(),
+            log_level,
+        }
+    }
+
+    /// Single attempt to send request, response with error status is returned as error
+    fn attempt(&self, req: Request<Body>, host: String) -> Box<Future<Item = Response<Vec<u8>>, Error = AttemptError> + Send> {
+        if !self.upstreams.allows_request(&host) {
+            let error = ectx!(err ErrorContext::CircuitBreaker, ErrorKind::BadGateway => host);
+            return Box::new(future::err(AttemptError { error, retriable: false }));
+        }
+        let upstreams = self.upstreams.clone();
+        Box::new(self.send(req, host.clone()).then(move |res| {
+            // only unavailability of the host is a failure, e.g. bitcoind answers JSON-RPC errors
+            // like unknown transaction or rejected broadcast with 500
+            let failed = match res {
+                Ok(ref resp) => is_unavailable_status(resp.status().as_u16()),
+                Err(_) => true,
+            };
+            upstreams.record(&host, !failed);
+            let resp = res?;
+            if !resp.status().is_client_error() && !resp.status().is_server_error() {
+                return Ok(resp);
+            }
+            let status = resp.status().as_u16();
+            let kind = match status {
+                400 => ErrorKind::BadRequest,
+                401 => ErrorKind::Unauthorized,
+                404 => ErrorKind::NotFound,
+                500 => ErrorKind::InternalServer,
+                502 => ErrorKind::BadGateway,
+                504 => ErrorKind::GatewayTimeout,
+                _ => ErrorKind::UnknownServerError,
+            };
+            let body_message = String::from_utf8_lossy(resp.body()).to_string();
+            Err(AttemptError {
+                error: ectx!(err ErrorSource::Server, kind => body_message),
+                retriable: status == 502 || status == 504,
+            })
+        }))
+    }
+
+    /// Sends request and reads the whole response within connect and read timeouts of the host
+    fn send(&self, req: Request<Body>, host: String) -> impl Future<Item = Response<Vec<u8>>, Error = AttemptError> + Send {
+        let upstream = self.upstreams.get(&host);
+        let timeout = Duration::from_millis(upstream.connect_timeout_ms + upstream.read_timeout_ms);
+        let started_at = Instant::now();
+        let fut = self.cli.request(req).and_then(|resp| {
+            let (parts, body) = resp.into_parts();
+            read_body(body).map(|body| Response::from_parts(parts, body))
+        });
+        Timeout::new(fut, timeout).then(move |res| {
+            let status = match res {
+                Ok(ref resp) => resp.status().as_u16().to_string(),
+                Err(_) => "error".to_string(),
+            };
+            HTTP_CLIENT_REQUESTS.with_label_values(&[&host, &status]).inc();
+            HTTP_CLIENT_REQUEST_DURATION
+                .with_label_values(&[&host])
+                .observe(metrics::duration_secs(started_at));
+            res.map_err(|e| match e.into_inner() {
+                Some(e) => AttemptError::from_hyper(e, host),
+                None => AttemptError {
+                    error: ectx!(err ErrorContext::Timeout, ErrorKind::GatewayTimeout => host, timeout),
+                    retriable: false,
+                },
+            })
+        })
     }
 }
 
 impl HttpClient for HttpClientImpl {
     fn request(&self, req: Request<Body>) -> Box<Future<Item = Response<Body>, Error = Error> + Send> {
-        let cli = self.cli.clone();
-        let level = log::max_level();
-        let self_log_level = self.log_level;
-        let self_log_level_2 = self.log_level;
-        let fut = if level == Level::Debug || level == Level::Trace {
-            let (parts, body) = req.into_parts();
-            Either::A(
-                read_body(body)
-                    .map_err(ectx!(ErrorSource::Hyper, ErrorKind::Internal))
-                    .and_then(move |body| {
-                        log!(
-                            self_log_level,
-                            "HttpClient, sent request {} {}, headers: {:#?}, body: {:?}",
-                            parts.method,
-                            parts.uri,
-                            parts.headers,
-                            String::from_utf8(body.clone()).ok()
-                        );
-                        let req = Request::from_parts(parts, body.into());
-                        cli.request(req).map_err(ectx!(ErrorSource::Hyper, ErrorKind::Internal))
-                    })
-                    .and_then(|resp| {
-                        let (parts, body) = resp.into_parts();
-                        read_body(body)
-                            .map_err(ectx!(ErrorSource::Hyper, ErrorKind::Internal))
-                            .map(|body| (parts, body))
+        let self_clone = self.clone();
+        let log_level = self.log_level;
+        let host = req.uri().host().unwrap_or("unknown").to_string();
+        let upstream = self.upstreams.get(&host).clone();
+        // JSON-RPC uses POST for reads as well, so clients mark such requests explicitly
+        let idempotent = req.method().is_idempotent() || req.extensions().get::<Idempotent>().is_some();
+        let max_retries = if idempotent { upstream.max_retries } else { 0 };
+        let (parts, body) = req.into_parts();
+        let fut = read_body(body)
+            .map_err(ectx!(ErrorSource::Hyper, ErrorKind::Internal))
+            .and_then(move |body| {
+                log!(
+                    log_level,
+                    "HttpClient, sent request {} {}, headers: {:#?}, body: {:?}",
+                    parts.method,
+                    parts.uri,
+                    parts.headers,
+                    String::from_utf8(body.clone()).ok()
+                );
+                let template = RequestTemplate {
+                    method: parts.method,
+                    uri: parts.uri,
+                    version: parts.version,
+                    headers: parts.headers,
+                    body,
+                };
+                future::loop_fn(0, move |retry| {
+                    let upstream = upstream.clone();
+                    self_clone.attempt(template.build(), host.clone()).then(move |res| match res {
+                        Ok(resp) => Either::A(future::ok(Loop::Break(resp))),
+                        Err(ref e) if e.retriable && retry < max_retries => {
+                            let delay = backoff(upstream.retry_backoff_ms, retry);
+                            Either::B(Delay::new(Instant::now() + delay).then(move |_| Ok(Loop::Continue(retry + 1))))
+                        }
+                        Err(e) => Either::A(future::err(e.error)),
                     })
-                    .map(move |(parts, body)| {
-                        log!(
-                            self_log_level_2,
-                            "HttpClient, recieved response with status {} headers: {:#?} and body: {:?}",
-                            parts.status.as_u16(),
-                            parts.headers,
-                            String::from_utf8(body.clone()).ok()
-                        );
-                        Response::from_parts(parts, body.into())
-                    }),
+                })
+            })
+            .map(move |resp| {
+                let (parts, body) = resp.into_parts();
+                log!(
+                    log_level,
+                    "HttpClient, recieved response with status {} headers: {:#?} and body: {:?}",
+                    parts.status.as_u16(),
+                    parts.headers,
+                    String::from_utf8(body.clone()).ok()
+                );
+                Response::from_parts(parts, body.into())
+            });
+        Box::new(fut)
+    }
+}
+
+/// Failed attempt to send request
+struct AttemptError {
+    error: Error,
+    retriable: bool,
+}
+
+impl AttemptError {
+    // `Error::source` is not implemented by older hyper versions, so `cause2` is used instead
+    #[allow(deprecated)]
+    fn from_hyper(e: hyper::Error, host: String) -> Self {
+        let io_error_kind = e
+            .cause2()
+            .and_then(|cause| cause.downcast_ref::<io::Error>())
+            .map(|cause| cause.kind());
+        match io_error_kind {
+            // connect timeout of `TimeoutConnector`
+            Some(io::ErrorKind::TimedOut) => AttemptError {
+                error: ectx!(err e, ErrorContext::Timeout, ErrorKind::GatewayTimeout => host),
+                retriable: false,
+            },
+            Some(io::ErrorKind::ConnectionReset) => AttemptError {
+                error: ectx!(err e, ErrorSource::Hyper, ErrorKind::Internal => host),
+                retriable: true,
+            },
+            _ => AttemptError {
+                error: ectx!(err e, ErrorSource::Hyper, ErrorKind::Internal => host),
+                retriable: false,
+            },
+        }
+    }
+}
+
+/// Parts of request needed to send it again, since `Request` can't be cloned
+struct RequestTemplate {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    headers: HeaderMap<HeaderValue>,
+    body: Vec<u8>,
+}
+
+impl RequestTemplate {
+    fn build(&self) -> Request<Body> {
+        let mut req = Request::new(Body::from(self.body.clone()));
+        *req.method_mut() = self.method.clone();
+        *req.uri_mut() = self.uri.clone();
+        *req.version_mut() = self.version;
+        *req.headers_mut() = self.headers.clone();
+        req
+    }
+}
+
+/// Statuses of proxies and load balancers in front of a host that is down or overloaded
+fn is_unavailable_status(status: u16) -> bool {
+    status == 502 || status == 503 || status == 504
+}
+
+/// Exponential backoff with jitter, so that retries of concurrent requests are spread in time
+fn backoff(base_ms: u64, retry: u32) -> Duration {
+    let max_ms = base_ms.saturating_mul(1 << retry.min(10));
+    Duration::from_millis(rand::thread_rng().gen_range(max_ms / 2, max_ms.saturating_add(1)))
+}
+
+/// Settings and circuit breakers of upstream hosts
+struct Upstreams {
+    hosts: HashMap<String, UpstreamSettings>,
+    default: UpstreamSettings,
+    breakers: Mutex<HashMap<String, CircuitBreaker>>,
+}
+
+impl Upstreams {
+    fn new(configs: Vec<Upstream>) -> Self {
+        let default = configs
+            .iter()
+            .find(|upstream| upstream.host.is_none())
+            .map(|upstream| upstream.inherit(&UpstreamSettings::default()))
+            .unwrap_or_default();
+        let hosts = configs
+            .iter()
+            .filter_map(|upstream| upstream.host.clone().map(|host| (host, upstream.inherit(&default))))
+            .collect();
+        Self {
+            hosts,
+            default,
+            breakers: Default::default(),
+        }
+    }
+
+    fn get(&self, host: &str) -> &UpstreamSettings {
+        self.hosts.get(host).unwrap_or(&self.default)
+    }
+
+    fn allows_request(&self, host: &str) -> bool {
+        self.breakers
+            .lock()
+            .unwrap()
+            .get(host)
+            .map(|breaker| breaker.allows_request(Instant::now()))
+            .unwrap_or(true)
+    }
+
+    fn record(&self, host: &str, success: bool) {
+        let upstream = self.get(host);
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(host.to_string()).or_insert_with(|| {
+            CircuitBreaker::new(
+                upstream.circuit_breaker_failures,
+                Duration::from_secs(upstream.circuit_breaker_reset_secs),
             )
+        });
+        if success {
+            breaker.record_success();
         } else {
-            Either::B(cli.request(req).map_err(ectx!(ErrorSource::Hyper, ErrorKind::Internal)))
-        };
+            breaker.record_failure(Instant::now());
+        }
+    }
+}
 
-        Box::new(fut.and_then(|resp| {
-            if resp.status().is_client_error() || resp.status().is_server_error() {
-                let kind = match resp.status().as_u16() {
-                    400 => ErrorKind::BadRequest,
-                    401 => ErrorKind::Unauthorized,
-                    404 => ErrorKind::NotFound,
-                    500 => ErrorKind::InternalServer,
-                    502 => ErrorKind::BadGateway,
-                    504 => ErrorKind::GatewayTimeout,
-                    _ => ErrorKind::UnknownServerError,
-                };
-                Either::A(
-                    read_body(resp.into_body())
-                        .map(|bytes| String::from_utf8(bytes).unwrap_or("Failed to read response body".to_string()))
-                        .or_else(|_| Ok("Failed to read response body".to_string()))
-                        .and_then(move |body_message| Err(ectx!(err ErrorSource::Server, kind => body_message))),
-                )
-            } else {
-                Either::B(Ok(resp).into_future())
-            }
+/// Connector limiting time to establish connection with connect timeout of the host
+#[derive(Clone)]
+struct TimeoutConnector {
+    inner: HttpsConnector<HttpConnector>,
+    upstreams: Arc<Upstreams>,
+}
+
+impl Connect for TimeoutConnector {
+    type Transport = <HttpsConnector<HttpConnector> as Connect>::Transport;
+    type Error = io::Error;
+    type Future = Box<Future<Item = (Self::Transport, Connected), Error = io::Error> + Send>;
+
+    fn connect(&self, dst: Destination) -> Self::Future {
+        let timeout = Duration::from_millis(self.upstreams.get(dst.host()).connect_timeout_ms);
+        Box::new(Timeout::new(self.inner.connect(dst), timeout).map_err(move |e| {
+            e.into_inner()
+                .unwrap_or_else(|| io::Error::new(io::ErrorKind::TimedOut, format!("connect timed out after {:?}", timeout)))
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upstream_settings() {
+        let bitcoind = Upstream {
+            host: Some("bitcoind".to_string()),
+            read_timeout_ms: Some(1),
+            ..Default::default()
+        };
+        let default = Upstream {
+            read_timeout_ms: Some(2),
+            max_retries: Some(0),
+            ..Default::default()
+        };
+        let upstreams = Upstreams::new(vec![bitcoind, default]);
+        assert_eq!(upstreams.get("bitcoind").read_timeout_ms, 1);
+        // missing settings of host are inherited from the entry without host
+        assert_eq!(upstreams.get("bitcoind").max_retries, 0);
+        assert_eq!(
+            upstreams.get("bitcoind").connect_timeout_ms,
+            UpstreamSettings::default().connect_timeout_ms
+        );
+        assert_eq!(upstreams.get("infura.io").read_timeout_ms, 2);
+        let upstreams = Upstreams::new(vec![]);
+        assert_eq!(upstreams.get("infura.io"), &UpstreamSettings::default());
+    }
+
+    #[test]
+    fn test_backoff() {
+        for retry in 0..5 {
+            let max = Duration::from_millis(100 << retry);
+            let delay = backoff(100, retry);
+            assert!(delay >= max / 2 && delay <= max, "Retry {}: {:?}", retry, delay);
+        }
+        assert_eq!(backoff(0, 3), Duration::from_millis(0));
+        backoff(u64::max_value(), 100);
+    }
+}
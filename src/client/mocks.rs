@@ -0,0 +1,129 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use futures::prelude::*;
+use hyper::service::service_fn;
+use hyper::{Body, Request, Response, Server, StatusCode};
+use serde_json::{self, Value};
+use tokio::runtime::Runtime;
+
+/// Recorded answer of a node to JSON-RPC call
+#[derive(Debug, Clone, Deserialize)]
+struct Fixture {
+    method: String,
+    /// Fixture without params answers calls of the method with any params
+    params: Option<Value>,
+    #[serde(default)]
+    result: Value,
+    error: Option<Value>,
+    /// HTTP status of the answer, by default it's error status of server for errors and 200 otherwise
+    status: Option<u16>,
+}
+
+/// JSON-RPC server emulating bitcoind or ethereum node with recorded fixtures.
+/// Calls without fixture are answered with `-32601` error.
+#[derive(Clone)]
+pub struct MockRpcServer {
+    addr: SocketAddr,
+    calls: Arc<Mutex<Vec<Value>>>,
+    failure: Arc<Mutex<Option<StatusCode>>>,
+}
+
+impl MockRpcServer {
+    /// Starts server on a free local port, `fixtures` is JSON array of `method`, `params` and `result` or `error`.
+    /// Single calls answered with error get `error_status`, e.g. 500 of bitcoind or 200 of ethereum nodes,
+    /// while batches are always answered with 200
+    pub fn start(rt: &mut Runtime, fixtures: &str, error_status: StatusCode) -> Self {
+        let fixtures: Arc<Vec<Fixture>> = Arc::new(serde_json::from_str(fixtures).expect("Invalid fixtures"));
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let failure: Arc<Mutex<Option<StatusCode>>> = Arc::new(Mutex::new(None));
+        let calls_clone = calls.clone();
+        let failure_clone = failure.clone();
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(move || {
+            let fixtures = fixtures.clone();
+            let calls = calls_clone.clone();
+            let failure = failure_clone.clone();
+            service_fn(move |req: Request<Body>| {
+                let fixtures = fixtures.clone();
+                let calls = calls.clone();
+                let failure = failure.clone();
+                req.into_body().concat2().map(move |body| {
+                    let request = serde_json::from_slice(&body).unwrap_or(Value::Null);
+                    if let Some(status) = *failure.lock().unwrap() {
+                        calls.lock().unwrap().push(request);
+                        let mut response = Response::new(Body::from(status.to_string()));
+                        *response.status_mut() = status;
+                        return response;
+                    }
+                    let (body, status) = match request {
+                        Value::Array(batch) => (
+                            Value::Array(
+                                batch
+                                    .into_iter()
+                                    .map(|call| answer(&fixtures, &calls, call, error_status).0)
+                                    .collect(),
+                            ),
+                            StatusCode::OK,
+                        ),
+                        call => answer(&fixtures, &calls, call, error_status),
+                    };
+                    let mut response = Response::new(Body::from(body.to_string()));
+                    *response.status_mut() = status;
+                    response
+                })
+            })
+        });
+        let addr = server.local_addr();
+        rt.spawn(server.map_err(|e| panic!("Mock RPC server failed: {}", e)));
+        MockRpcServer { addr, calls, failure }
+    }
+
+    /// Requests are answered with `status` without JSON-RPC body until server is recovered,
+    /// e.g. 503 of a proxy in front of a node that is down. Calls are still recorded
+    pub fn fail(&self, status: StatusCode) {
+        *self.failure.lock().unwrap() = Some(status);
+    }
+
+    pub fn recover(&self) {
+        *self.failure.lock().unwrap() = None;
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Params of received calls of `method`
+    pub fn calls(&self, method: &str) -> Vec<Value> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|call| call["method"] == method)
+            .map(|call| call["params"].clone())
+            .collect()
+    }
+}
+
+fn answer(fixtures: &[Fixture], calls: &Mutex<Vec<Value>>, call: Value, error_status: StatusCode) -> (Value, StatusCode) {
+    calls.lock().unwrap().push(call.clone());
+    let fixture = fixtures.iter().find(|fixture| {
+        call["method"] == fixture.method.as_str() && fixture.params.as_ref().map_or(true, |params| *params == call["params"])
+    });
+    let (result, error, status) = match fixture {
+        Some(fixture) => (
+            fixture.result.clone(),
+            fixture.error.clone().unwrap_or(Value::Null),
+            fixture
+                .status
+                .map(|status| StatusCode::from_u16(status).expect("Invalid status of fixture")),
+        ),
+        None => (
+            Value::Null,
+            json!({"code": -32601, "message": format!("No fixture for {} {}", call["method"], call["params"])}),
+            None,
+        ),
+    };
+    let status = status.unwrap_or(if error.is_null() { StatusCode::OK } else { error_status });
+    let body = json!({"jsonrpc": call["jsonrpc"], "id": call["id"], "result": result, "error": error});
+    (body, status)
+}
@@ -1,9 +1,14 @@
 mod bitcoin;
+mod circuit_breaker;
 mod error;
 mod ethereum;
 mod http_client;
+#[cfg(test)]
+mod mocks;
 
 pub use self::bitcoin::*;
 pub use self::error::*;
 pub use self::ethereum::*;
 pub use self::http_client::*;
+#[cfg(test)]
+pub use self::mocks::*;
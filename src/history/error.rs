@@ -0,0 +1,37 @@
+use failure::{Backtrace, Context, Fail};
+use std::fmt;
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub struct Error {
+    inner: Context<ErrorKind>,
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Fail)]
+pub enum ErrorKind {
+    #[fail(display = "history error - internal error")]
+    Internal,
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Fail)]
+pub enum ErrorSource {
+    #[fail(display = "history error source - sled")]
+    Sled,
+    #[fail(display = "history error source - serde_json")]
+    SerdeJson,
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Fail)]
+pub enum ErrorContext {
+    #[fail(display = "history error context - error opening index")]
+    Open,
+    #[fail(display = "history error context - error reading index")]
+    Read,
+    #[fail(display = "history error context - error writing index")]
+    Write,
+}
+
+derive_error_impls!();
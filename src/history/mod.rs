@@ -0,0 +1,226 @@
+//! Index of transactions by address, filled by pollers and served by transactions history endpoints.
+//!
+//! Transactions of address are stored under `{currency}:{address}:{!block_number}{hash}` keys,
+//! so that iteration in key order returns the newest transactions first. Location of each transaction
+//! is stored separately, so that a transaction moved to another block by reorg doesn't appear twice.
+
+mod error;
+
+use std::ops::Bound;
+
+use serde_json;
+use sled;
+
+pub use self::error::*;
+use models::*;
+use prelude::*;
+
+/// Position of the last transaction of a page, the next page starts after it
+pub type Cursor = Vec<u8>;
+
+pub trait HistoryIndex: Send + Sync + 'static {
+    /// Adds mined transaction to history of its senders and receivers. Transaction indexed again replaces
+    /// its previous entries, transactions that are not yet included in a block are skipped.
+    /// Confirmations are not stored, they are 0 in transactions returned by `transactions`
+    fn index(&self, tx: &BlockchainTransaction) -> Result<(), Error>;
+    /// Up to `limit` transactions of address, the newest first, starting after `cursor`.
+    /// Returns cursor of the next page if there are more transactions
+    fn transactions(
+        &self,
+        currency: Currency,
+        address: &str,
+        cursor: Option<Cursor>,
+        limit: usize,
+    ) -> Result<(Vec<BlockchainTransaction>, Option<Cursor>), Error>;
+}
+
+/// Where transaction is indexed, to remove its entries if it's moved to another block
+#[derive(Debug, Serialize, Deserialize)]
+struct Location {
+    block_number: u64,
+    addresses: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct SledHistoryIndex {
+    addresses: sled::Tree,
+    locations: sled::Tree,
+}
+
+impl SledHistoryIndex {
+    pub fn open(path: &str) -> Result<Self, Error> {
+        let db = sled::open(path).map_err(ectx!(try ErrorSource::Sled, ErrorContext::Open, ErrorKind::Internal => path))?;
+        Self::with_db(db)
+    }
+
+    /// Index that is not persisted
+    #[cfg(test)]
+    pub fn temporary() -> Result<Self, Error> {
+        let db = sled::Config::new()
+            .temporary(true)
+            .open()
+            .map_err(ectx!(try ErrorSource::Sled, ErrorContext::Open, ErrorKind::Internal))?;
+        Self::with_db(db)
+    }
+
+    fn with_db(db: sled::Db) -> Result<Self, Error> {
+        let addresses = db
+            .open_tree("addresses")
+            .map_err(ectx!(try ErrorSource::Sled, ErrorContext::Open, ErrorKind::Internal))?;
+        let locations = db
+            .open_tree("locations")
+            .map_err(ectx!(try ErrorSource::Sled, ErrorContext::Open, ErrorKind::Internal))?;
+        Ok(Self { addresses, locations })
+    }
+
+    fn remove_entries(&self, currency: Currency, hash: &str, location: &Location) -> Result<(), Error> {
+        for address in &location.addresses {
+            self.addresses
+                .remove(entry_key(currency, address, location.block_number, hash))
+                .map_err(ectx!(try ErrorSource::Sled, ErrorContext::Write, ErrorKind::Internal => address, hash))?;
+        }
+        Ok(())
+    }
+}
+
+impl HistoryIndex for SledHistoryIndex {
+    fn index(&self, tx: &BlockchainTransaction) -> Result<(), Error> {
+        let block_number = match tx.block_number {
+            Some(block_number) => block_number,
+            None => return Ok(()),
+        };
+        let location_key = format!("{}:{}", tx.currency, tx.hash);
+        let previous = self
+            .locations
+            .get(&location_key)
+            .map_err(ectx!(try ErrorSource::Sled, ErrorContext::Read, ErrorKind::Internal => tx.currency, tx.hash))?;
+        if let Some(previous) = previous {
+            let previous: Location = serde_json::from_slice(&previous)
+                .map_err(ectx!(try ErrorSource::SerdeJson, ErrorContext::Read, ErrorKind::Internal => tx.currency, tx.hash))?;
+            self.remove_entries(tx.currency, &tx.hash, &previous)?;
+        }
+
+        let mut addresses: Vec<String> = tx
+            .from
+            .iter()
+            .chain(tx.to.iter().map(|entry| &entry.address))
+            .map(|address| normalize_address(tx.currency, address))
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+        let stored = BlockchainTransaction {
+            confirmations: 0,
+            ..tx.clone()
+        };
+        let value =
+            serde_json::to_vec(&stored).map_err(ectx!(try ErrorSource::SerdeJson, ErrorContext::Write, ErrorKind::Internal => tx))?;
+        for address in &addresses {
+            self.addresses
+                .insert(entry_key(tx.currency, address, block_number, &tx.hash), value.clone())
+                .map_err(ectx!(try ErrorSource::Sled, ErrorContext::Write, ErrorKind::Internal => address, tx))?;
+        }
+        let location = serde_json::to_vec(&Location { block_number, addresses })
+            .map_err(ectx!(try ErrorSource::SerdeJson, ErrorContext::Write, ErrorKind::Internal => tx))?;
+        self.locations
+            .insert(location_key.as_bytes(), location)
+            .map_err(ectx!(try ErrorSource::Sled, ErrorContext::Write, ErrorKind::Internal => tx.currency, tx.hash))?;
+        Ok(())
+    }
+
+    fn transactions(
+        &self,
+        currency: Currency,
+        address: &str,
+        cursor: Option<Cursor>,
+        limit: usize,
+    ) -> Result<(Vec<BlockchainTransaction>, Option<Cursor>), Error> {
+        let prefix = address_prefix(currency, &normalize_address(currency, address));
+        let start = match cursor {
+            Some(cursor) => Bound::Excluded([prefix.clone(), cursor].concat()),
+            None => Bound::Included(prefix.clone()),
+        };
+        let mut transactions = Vec::new();
+        let mut last_key = None;
+        for entry in self.addresses.range::<Vec<u8>, _>((start, Bound::Unbounded)) {
+            let (key, value) = entry.map_err(ectx!(try ErrorSource::Sled, ErrorContext::Read, ErrorKind::Internal => address))?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            if transactions.len() == limit {
+                // there is at least one more transaction after this page
+                return Ok((transactions, last_key));
+            }
+            let tx = serde_json::from_slice(&value)
+                .map_err(ectx!(try ErrorSource::SerdeJson, ErrorContext::Read, ErrorKind::Internal => address))?;
+            transactions.push(tx);
+            last_key = Some(key[prefix.len()..].to_vec());
+        }
+        Ok((transactions, None))
+    }
+}
+
+/// Ethereum addresses are case insensitive, case of bitcoin ones matters
+fn normalize_address(currency: Currency, address: &str) -> String {
+    match currency {
+        Currency::Btc => address.to_string(),
+        _ => address.trim_start_matches("0x").to_lowercase(),
+    }
+}
+
+fn address_prefix(currency: Currency, address: &str) -> Vec<u8> {
+    format!("{}:{}:", currency, address).into_bytes()
+}
+
+fn entry_key(currency: Currency, address: &str, block_number: u64, hash: &str) -> Vec<u8> {
+    let mut key = address_prefix(currency, address);
+    // inverted, so that the newest blocks go first
+    key.extend_from_slice(&(!block_number).to_be_bytes());
+    key.extend_from_slice(hash.as_bytes());
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(hash: &str, from: &str, to: &str, block_number: u64) -> BlockchainTransaction {
+        BlockchainTransaction {
+            hash: hash.to_string(),
+            from: vec![from.to_string()],
+            to: vec![BlockchainTransactionEntry {
+                address: to.to_string(),
+                value: Amount::new(1),
+            }],
+            block_number: Some(block_number),
+            block_hash: None,
+            block_time: None,
+            currency: Currency::Eth,
+            fee: Amount::new(0),
+            confirmations: 1,
+            erc20_operation_kind: None,
+            contract_deployment: false,
+        }
+    }
+
+    #[test]
+    fn test_transactions() {
+        let index = SledHistoryIndex::temporary().unwrap();
+        index.index(&transaction("a1", "aa", "bb", 1)).unwrap();
+        index.index(&transaction("a2", "BB", "cc", 2)).unwrap();
+        index.index(&transaction("a3", "aa", "cc", 3)).unwrap();
+        // a1 is moved to block 4 by reorg
+        index.index(&transaction("a1", "aa", "bb", 4)).unwrap();
+
+        let hashes = |txs: Vec<BlockchainTransaction>| txs.into_iter().map(|tx| tx.hash).collect::<Vec<_>>();
+        let (txs, cursor) = index.transactions(Currency::Eth, "bb", None, 1).unwrap();
+        assert_eq!(hashes(txs), vec!["a1"]);
+        let (txs, cursor) = index.transactions(Currency::Eth, "0xBB", cursor, 1).unwrap();
+        assert_eq!((hashes(txs), cursor), (vec!["a2".to_string()], None));
+
+        let (txs, cursor) = index.transactions(Currency::Eth, "aa", None, 10).unwrap();
+        assert!(txs.iter().all(|tx| tx.confirmations == 0), "confirmations are not stored");
+        assert_eq!((hashes(txs), cursor), (vec!["a1".to_string(), "a3".to_string()], None));
+        let (txs, _) = index.transactions(Currency::Stq, "aa", None, 10).unwrap();
+        assert!(txs.is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use config_crate::{File, FileFormat};
+use hyper::{Body, Client, Request, StatusCode};
+use serde_json::{self, Value};
+
+use super::*;
+use api;
+use config::Database;
+use mocks::TransactionPublisherMock;
+use pollers::{BackfillRegistry, BitcoinPollerService, BroadcastRegistry, PollerStatus};
+use prelude::*;
+use services::*;
+use storage::{DbStorage, Storage};
+
+/// Starts API with services backed by mock nodes on a free port, returns its url
+fn start_api(env: &mut TestEnv, storage: Option<Arc<Storage>>) -> String {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    env.config.server.port = port.to_string();
+    let config = env.config.clone();
+    let bitcoin_client = env.bitcoin_client();
+    let ethereum_client = env.ethereum_client();
+    let server = api::start_server(
+        config.clone(),
+        Arc::new(BitcoinServiceImpl::new(
+            bitcoin_client.clone(),
+            config.bitcoin_broadcast.clone(),
+            config.balances.clone(),
+        )),
+        Arc::new(EthereumServiceImpl::new(
+            ethereum_client.clone(),
+            config.network.ethereum.chain_id,
+            config.balances.clone(),
+        )),
+        Arc::new(BroadcastServiceImpl::new(None, BroadcastRegistry::default())),
+        Arc::new(NonceServiceImpl::new(ethereum_client.clone(), config.nonce_reservation.clone())),
+        Arc::new(HistoryServiceImpl::new(None, bitcoin_client, ethereum_client)),
+        Arc::new(BackfillServiceImpl::new(None, BackfillRegistry::default())),
+        Arc::new(BlockServiceImpl::new(storage)),
+        Arc::new(HealthServiceMock),
+    );
+    env.rt.spawn(server);
+    // server is bound in runtime thread
+    for _ in 0..100 {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    format!("http://127.0.0.1:{}", port)
+}
+
+fn get(env: &mut TestEnv, url: String) -> (StatusCode, Value) {
+    let f = Client::new().get(url.parse().unwrap()).and_then(|resp| {
+        let status = resp.status();
+        resp.into_body()
+            .concat2()
+            .map(move |body| (status, serde_json::from_slice(&body).unwrap_or(Value::Null)))
+    });
+    env.rt.block_on(f).unwrap()
+}
+
+/// Status of GET request with bearer `token`
+fn get_status(env: &mut TestEnv, url: String, token: &str) -> StatusCode {
+    let req = Request::get(url)
+        .header("Authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    env.rt.block_on(Client::new().request(req).map(|resp| resp.status())).unwrap()
+}
+
+#[test]
+fn test_bitcoin_transaction() {
+    let mut env = TestEnv::new();
+    let url = start_api(&mut env, None);
+    // prefix is stripped before hash is sent to node
+    let (status, body) = get(&mut env, format!("{}/v1/bitcoin/transactions/0x{}", url, BTC_TX_HASH));
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["hash"], BTC_TX_HASH);
+    assert_eq!(body["from"], json!([BTC_SENDER]));
+    assert_eq!(body["fee"], 10_000);
+    assert_eq!(body["blockNumber"], 102);
+
+    let calls = env.bitcoind.calls("getrawtransaction").len();
+    let (status, _) = get(&mut env, format!("{}/v1/bitcoin/transactions/0x0x{}", url, BTC_TX_HASH));
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        env.bitcoind.calls("getrawtransaction").len(),
+        calls,
+        "invalid hash is not sent to node"
+    );
+}
+
+#[test]
+fn test_ethereum_balance() {
+    let mut env = TestEnv::new();
+    let url = start_api(&mut env, None);
+    let (status, body) = get(&mut env, format!("{}/v1/ethereum/{}/balance", url, ETH_RECEIVER));
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["balance"], 1_000_000_000_000_000_000u64);
+}
+
+#[test]
+fn test_scanned_block() {
+    let mut env = TestEnv::new();
+    let storage = DbStorage::connect(&Database {
+        url: "sqlite://:memory:".to_string(),
+        thread_pool_size: 1,
+    })
+    .unwrap();
+    let storage = Arc::new(storage) as Arc<Storage>;
+    let url = start_api(&mut env, Some(storage.clone()));
+
+    let (status, _) = get(&mut env, format!("{}/v1/bitcoin/blocks/102", url));
+    assert_eq!(status, StatusCode::NOT_FOUND, "block is not scanned yet");
+
+    let poller = BitcoinPollerService::new(
+        Duration::from_secs(60),
+        env.bitcoin_client(),
+        Arc::new(TransactionPublisherMock::default()),
+        1,
+        100,
+        PollerStatus::default(),
+        None,
+        Some(storage),
+        None,
+    );
+    env.rt.block_on(poller.publish_transactions(None, 2)).unwrap();
+
+    let (status, body) = get(&mut env, format!("{}/v1/bitcoin/blocks/102", url));
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["hash"], BTC_TIP_HASH);
+    assert_eq!(body["transactions"][0]["hash"], BTC_TX_HASH);
+}
+
+#[test]
+fn test_rate_limit() {
+    let mut env = TestEnv::with_config(|config| {
+        let auth = r#"
+            [auth]
+            disabled = false
+            [[auth.clients]]
+            id = "payments"
+            token = "payments-token"
+            scopes = ["read"]
+            [[auth.clients]]
+            id = "billing"
+            token = "billing-token"
+            scopes = ["read"]
+            [rate_limit]
+            read = { requests_per_sec = 0.001, burst = 1 }
+            broadcast = { requests_per_sec = 0.001, burst = 1 }
+        "#;
+        config.merge(File::from_str(auth, FileFormat::Toml)).unwrap();
+    });
+    let url = start_api(&mut env, None);
+    let balance_url = format!("{}/v1/ethereum/{}/balance", url, ETH_RECEIVER);
+
+    assert_eq!(get_status(&mut env, balance_url.clone(), "payments-token"), StatusCode::OK);
+    assert_eq!(
+        get_status(&mut env, balance_url.clone(), "payments-token"),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(
+        get_status(&mut env, balance_url.clone(), "billing-token"),
+        StatusCode::OK,
+        "clients from the same address have their own limits"
+    );
+
+    assert_eq!(get_status(&mut env, balance_url.clone(), "wrong-token"), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        get_status(&mut env, balance_url.clone(), "wrong-token"),
+        StatusCode::TOO_MANY_REQUESTS,
+        "failed authentication uses up limit of the address"
+    );
+}
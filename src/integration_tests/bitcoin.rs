@@ -0,0 +1,113 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::StatusCode;
+use serde_json;
+
+use super::*;
+use client::{BitcoinClient, ErrorKind};
+use mocks::TransactionPublisherMock;
+use models::*;
+use pollers::{BitcoinPollerService, PollerStatus};
+use prelude::*;
+
+#[test]
+fn test_last_transactions() {
+    let mut env = TestEnv::new();
+    let client = env.bitcoin_client();
+    let txs = env.rt.block_on(client.last_transactions(None, 2).collect()).unwrap();
+
+    // coinbase transactions are skipped
+    assert_eq!(txs.len(), 1);
+    let tx = &txs[0];
+    assert_eq!(tx.hash, BTC_TX_HASH);
+    assert_eq!(tx.from, vec![BTC_SENDER.to_string()]);
+    let to: Vec<_> = tx.to.iter().map(|entry| (entry.address.as_str(), entry.value)).collect();
+    assert_eq!(
+        to,
+        vec![(BTC_RECEIVER, Amount::new(1_000_000_000)), (BTC_SENDER, Amount::new(3_999_990_000))]
+    );
+    assert_eq!(tx.fee, Amount::new(10_000));
+    assert_eq!(tx.block_number, Some(102));
+    assert_eq!(tx.block_hash, Some(BTC_TIP_HASH.to_string()));
+    assert_eq!(tx.confirmations, 1);
+
+    let blocks = env.bitcoind.calls("getblock");
+    assert_eq!(blocks.len(), 2, "blocks are walked back from the best one");
+    assert_eq!(blocks[0], json!([BTC_TIP_HASH]));
+}
+
+#[test]
+fn test_chain_tip() {
+    let mut env = TestEnv::new();
+    let client = env.bitcoin_client();
+    let tip = env.rt.block_on(client.get_chain_tip()).unwrap();
+
+    assert_eq!(
+        tip,
+        ChainTip {
+            height: 102,
+            hash: BTC_TIP_HASH.to_string(),
+        }
+    );
+    assert_eq!(env.bitcoind.calls("getblockchaininfo").len(), 1);
+}
+
+#[test]
+fn test_poller_publishes_transactions() {
+    let mut env = TestEnv::new();
+    let publisher = TransactionPublisherMock::default();
+    let poller = BitcoinPollerService::new(
+        Duration::from_secs(60),
+        env.bitcoin_client(),
+        Arc::new(publisher.clone()),
+        1,
+        100,
+        PollerStatus::default(),
+        None,
+        None,
+        None,
+    );
+    let last_block = env.rt.block_on(poller.publish_transactions(None, 2)).unwrap();
+
+    assert_eq!(last_block, Some(102));
+    let published: Vec<_> = publisher.transactions().into_iter().map(|tx| (tx.hash, tx.currency)).collect();
+    assert_eq!(published, vec![(BTC_TX_HASH.to_string(), Currency::Btc)]);
+}
+
+#[test]
+fn test_circuit_breaker() {
+    let mut env = TestEnv::with_config(|config| {
+        config.set("client.upstreams[0].circuit_breaker_failures", 2).unwrap();
+    });
+    let client = env.bitcoin_client();
+    let raw: RawBitcoinTransaction = serde_json::from_value(json!("0200000001")).unwrap();
+
+    // bitcoind answers JSON-RPC errors with 500, so they are not failures of the node
+    for _ in 0..3 {
+        assert!(env.rt.block_on(client.send_raw_tx(raw.clone())).is_err());
+    }
+    assert!(env.rt.block_on(client.get_chain_tip()).is_ok());
+
+    env.bitcoind.fail(StatusCode::SERVICE_UNAVAILABLE);
+    for _ in 0..2 {
+        let e = env.rt.block_on(client.get_chain_tip()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnknownServerError);
+    }
+    env.bitcoind.recover();
+    let e = env.rt.block_on(client.get_chain_tip()).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::BadGateway, "breaker is open");
+    assert_eq!(
+        env.bitcoind.calls("getblockchaininfo").len(),
+        3,
+        "requests are not sent while breaker is open"
+    );
+}
+
+#[test]
+fn test_cli_command() {
+    let env = TestEnv::new();
+    // command runs in its own runtime, that provides timer for timeouts of HTTP client
+    assert_eq!(::print_btc_blocks(&env.config, None, 2), Ok(()));
+    assert_eq!(env.bitcoind.calls("getblock").len(), 2);
+}
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use hyper::StatusCode;
+use serde_json;
+
+use super::*;
+use config::BroadcastTracker;
+use mocks::TransactionPublisherMock;
+use models::*;
+use pollers::{BitcoinPollerService, BroadcastRegistry, BroadcastTrackerService, PollerStatus};
+
+/// Bitcoin transaction spending `txid:index`, posted through API a moment ago
+fn broadcast(hash: &str, txid: &str, index: u32) -> Broadcast {
+    Broadcast {
+        hash: TxHash::new(hash.to_string()),
+        currency: Currency::Btc,
+        status: BroadcastStatus::Pending,
+        stuck: false,
+        rebroadcasts: 0,
+        submitted_at: Utc::now(),
+        updated_at: Utc::now(),
+        transaction: BroadcastTransaction::Bitcoin {
+            raw: serde_json::from_value(json!("0200000001")).unwrap(),
+            inputs: vec![BitcoinTransactionInput {
+                txid: txid.to_string(),
+                index,
+                sequence: 4_294_967_294,
+            }],
+        },
+    }
+}
+
+fn tracker(env: &TestEnv, publisher: &TransactionPublisherMock, registry: &BroadcastRegistry) -> BroadcastTrackerService {
+    let config = BroadcastTracker {
+        max_rebroadcasts: 1,
+        ..Default::default()
+    };
+    BroadcastTrackerService::new(
+        config,
+        env.bitcoin_client(),
+        env.ethereum_client(),
+        Arc::new(publisher.clone()),
+        registry.clone(),
+    )
+}
+
+fn statuses(publisher: &TransactionPublisherMock) -> Vec<BroadcastStatus> {
+    publisher
+        .broadcast_statuses()
+        .into_iter()
+        .map(|broadcast| broadcast.status)
+        .collect()
+}
+
+#[test]
+fn test_tracker_drops_missing_transaction() {
+    let mut env = TestEnv::new();
+    let publisher = TransactionPublisherMock::default();
+    let registry = BroadcastRegistry::default();
+    let tracker = tracker(&env, &publisher, &registry);
+    let broadcast = broadcast("8a3f7f0a2d2c1b5e9e4d6c0b1a2f3e4d5c6b7a8f9e0d1c2b3a4f5e6d7c8b9a0f", BTC_TX_HASH, 0);
+    env.rt.block_on(tracker.track(broadcast.clone())).unwrap();
+    assert_eq!(statuses(&publisher), vec![BroadcastStatus::Pending]);
+
+    env.bitcoind.fail(StatusCode::SERVICE_UNAVAILABLE);
+    assert!(env.rt.block_on(tracker.check_pending()).is_err(), "mempool is not available");
+    assert_eq!(registry.get(&broadcast.hash).unwrap().rebroadcasts, 0);
+    env.bitcoind.recover();
+
+    // transaction is not in mempool and its input is unspent, node rejects it again with 500
+    env.rt.block_on(tracker.check_pending()).unwrap();
+    assert_eq!(env.bitcoind.calls("sendrawtransaction").len(), 1);
+    let tracked = registry.get(&broadcast.hash).unwrap();
+    assert_eq!((tracked.status, tracked.rebroadcasts), (BroadcastStatus::Pending, 1));
+
+    env.rt.block_on(tracker.check_pending()).unwrap();
+    assert_eq!(env.bitcoind.calls("sendrawtransaction").len(), 1);
+    assert_eq!(registry.get(&broadcast.hash).unwrap().status, BroadcastStatus::Dropped);
+    assert_eq!(statuses(&publisher), vec![BroadcastStatus::Pending, BroadcastStatus::Dropped]);
+}
+
+#[test]
+fn test_tracker_finds_mined_transaction() {
+    let mut env = TestEnv::new();
+    let publisher = TransactionPublisherMock::default();
+    let registry = BroadcastRegistry::default();
+    let tracker = tracker(&env, &publisher, &registry);
+    let broadcast = broadcast(BTC_TX_HASH, "17f970176f0e5e4fcf5872e3868a8cc3719d9d450e8bda8952bff70b7eb7be62", 0);
+    env.rt.block_on(tracker.track(broadcast.clone())).unwrap();
+
+    // input is spent in a block that poller didn't scan yet
+    env.rt.block_on(tracker.check_pending()).unwrap();
+    assert_eq!(env.bitcoind.calls("sendrawtransaction").len(), 0);
+    assert_eq!(registry.get(&broadcast.hash).unwrap().status, BroadcastStatus::Pending);
+
+    let poller = BitcoinPollerService::new(
+        Duration::from_secs(60),
+        env.bitcoin_client(),
+        Arc::new(TransactionPublisherMock::default()),
+        1,
+        100,
+        PollerStatus::default(),
+        None,
+        None,
+        Some(registry.clone()),
+    );
+    env.rt.block_on(poller.publish_transactions(None, 2)).unwrap();
+    env.rt.block_on(tracker.check_pending()).unwrap();
+    assert_eq!(registry.get(&broadcast.hash).unwrap().status, BroadcastStatus::Mined);
+    assert_eq!(statuses(&publisher), vec![BroadcastStatus::Pending, BroadcastStatus::Mined]);
+}
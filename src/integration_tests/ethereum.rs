@@ -0,0 +1,97 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::*;
+use client::EthereumClient;
+use mocks::TransactionPublisherMock;
+use models::*;
+use pollers::{EthereumPollerService, PollerStatus};
+use prelude::*;
+
+#[test]
+fn test_last_eth_transactions() {
+    let mut env = TestEnv::new();
+    let client = env.ethereum_client();
+    let txs = env.rt.block_on(client.last_eth_transactions(None, 2).collect()).unwrap();
+
+    // zero value contract call is not an eth transfer, while zero value contract deployment is published
+    assert_eq!(txs.len(), 2);
+    let tx = &txs[0];
+    assert_eq!(tx.hash, ETH_TX_HASH);
+    assert_eq!(tx.from, vec![ETH_SENDER.to_string()]);
+    assert_eq!(tx.to[0].address, ETH_RECEIVER);
+    assert_eq!(tx.to[0].value, Amount::new(1_000_000_000_000_000_000));
+    // 21000 gas by 1 gwei
+    assert_eq!(tx.fee, Amount::new(21_000_000_000_000));
+    assert_eq!(tx.block_number, Some(16));
+    assert_eq!(tx.confirmations, 0);
+    assert_eq!(tx.block_time.map(|time| time.timestamp()), Some(1_551_399_600));
+    assert!(!tx.contract_deployment);
+
+    let deployment = &txs[1];
+    assert_eq!(deployment.hash, ETH_DEPLOYMENT_HASH);
+    assert!(deployment.contract_deployment);
+    assert_eq!(deployment.to[0].address, ETH_CREATED_CONTRACT);
+    assert_eq!(deployment.to[0].value, Amount::new(0));
+
+    assert_eq!(
+        env.ethereum.calls("eth_getTransactionReceipt"),
+        vec![
+            json!([format!("0x{}", ETH_TX_HASH)]),
+            json!([format!("0x{}", ETH_DEPLOYMENT_HASH)])
+        ]
+    );
+}
+
+#[test]
+fn test_last_eth_transactions_to() {
+    let mut env = TestEnv::new();
+    let client = env.ethereum_client();
+    let txs = env.rt.block_on(client.last_eth_transactions_to(16, 2).collect()).unwrap();
+
+    let hashes: Vec<_> = txs.iter().map(|tx| tx.hash.as_str()).collect();
+    assert_eq!(hashes, vec![ETH_TX_HASH, ETH_DEPLOYMENT_HASH]);
+    assert_eq!(txs[0].confirmations, 0);
+    assert!(env.ethereum.calls("eth_blockNumber").is_empty(), "block number is not requested again");
+}
+
+#[test]
+fn test_last_stq_transactions_to() {
+    let mut env = TestEnv::new();
+    let client = env.ethereum_client();
+    let txs = env.rt.block_on(client.last_stq_transactions_to(16, 1).collect()).unwrap();
+
+    let hashes: Vec<_> = txs.iter().map(|tx| tx.hash.as_str()).collect();
+    assert_eq!(hashes, vec![format!("{}:0", STQ_TX_HASH), format!("{}:1", STQ_TX_HASH)]);
+    assert_eq!(txs[0].erc20_operation_kind, Some(Erc20OperationKind::Approve));
+    assert_eq!(txs[1].to[0].value, Amount::new(1_000_000_000_000_000_000));
+    assert!(txs.iter().all(|tx| tx.block_time.map(|time| time.timestamp()) == Some(1_551_399_600)));
+    assert_eq!(env.ethereum.calls("eth_getBlockByHash").len(), 1, "block time is fetched once per block");
+}
+
+#[test]
+fn test_poller_publishes_transactions() {
+    let mut env = TestEnv::new();
+    let publisher = TransactionPublisherMock::default();
+    let poller = EthereumPollerService::new(
+        Duration::from_secs(10),
+        env.ethereum_client(),
+        Arc::new(publisher.clone()),
+        1,
+        100,
+        PollerStatus::default(),
+        None,
+        None,
+    );
+    let last_block = env.rt.block_on(poller.publish_transactions(None, 2)).unwrap();
+
+    assert_eq!(last_block, Some(16));
+    let published: Vec<_> = publisher.transactions().into_iter().map(|tx| (tx.hash, tx.currency)).collect();
+    assert_eq!(
+        published,
+        vec![
+            (ETH_TX_HASH.to_string(), Currency::Eth),
+            (ETH_DEPLOYMENT_HASH.to_string(), Currency::Eth)
+        ]
+    );
+}
@@ -0,0 +1,82 @@
+//! End to end tests of clients, pollers and API against mock bitcoind and ethereum node, answering
+//! with fixtures of regtest and dev chains. Fixtures describe:
+//!
+//! - bitcoin blocks 101 and 102, block 102 has a transaction spending coinbase output of block 1.
+//!   Mempool is empty, only outputs of the transaction of block 102 are unspent
+//! - ethereum blocks 15 and 16, block 16 has a transfer of 1 ether, a zero value contract call and a zero value
+//!   contract deployment. The contract call emits STQ approval and transfer logs
+//!
+//! Entries of the contract deployment (`ETH_DEPLOYMENT_HASH`, its receipt and `ETH_CREATED_CONTRACT`) and the STQ
+//! logs of the contract call with its transaction and receipt are synthetic: they are written by hand in the format
+//! of the recorded ones, so their hashes are not derived from the transaction data.
+
+use std::sync::Arc;
+
+use config_crate::{Config as RawConfig, File, FileFormat};
+use hyper::StatusCode;
+use tokio::runtime::Runtime;
+
+use client::{BitcoinClientImpl, EthereumClientImpl, MockRpcServer};
+use config::Config;
+
+mod api;
+mod bitcoin;
+mod broadcasts;
+mod ethereum;
+mod networks;
+
+const BITCOIND_FIXTURES: &str = include_str!("fixtures/bitcoind.json");
+const ETHEREUM_FIXTURES: &str = include_str!("fixtures/ethereum.json");
+const CONFIG: &str = include_str!("fixtures/config.toml");
+
+pub const BTC_TIP_HASH: &str = "221bbdc21c1435201fd6fdb4cc646184bd832b586ddd720dc4d5fe5c7892be51";
+pub const BTC_TX_HASH: &str = "4e0336dd9c284e3921a9ef79816de80bd5eda6b7364955ab48cebe7c767bf84a";
+pub const BTC_SENDER: &str = "bcrt1qcr8te4kr609gcawutmrza0j4xv80jy8zeqchgx";
+pub const BTC_RECEIVER: &str = "bcrt1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+pub const ETH_TX_HASH: &str = "1919ab0e85b85ae8d26ffb9e55838d9cf3cb63140b63e70f39f8aadaf883eb8d";
+pub const ETH_SENDER: &str = "5409ed021d9299bf6814279a6a1411a7e866a631";
+pub const ETH_RECEIVER: &str = "6ecbe1db9ef729cbe972c83fb886247691fb6beb";
+pub const STQ_TX_HASH: &str = "b104cfa8e854e23c632d8248beb5d7de2a70a558c0e9666ed6f98d4f5676989a";
+pub const ETH_DEPLOYMENT_HASH: &str = "6a7c0b4c1fc7d6e5b8e3b2d3a0e9f4c5b6a7d8e9f0a1b2c3d4e5f60718293a4b";
+pub const ETH_CREATED_CONTRACT: &str = "2c4bd064b998838076fa341a83d007fc2fa50957";
+
+/// Mock nodes running in the runtime of a test
+pub struct TestEnv {
+    pub rt: Runtime,
+    pub bitcoind: MockRpcServer,
+    pub ethereum: MockRpcServer,
+    pub config: Config,
+}
+
+impl TestEnv {
+    pub fn new() -> Self {
+        Self::with_config(|_| ())
+    }
+
+    /// Environment with config changed by `f`, node urls are already set
+    pub fn with_config<F: FnOnce(&mut RawConfig)>(f: F) -> Self {
+        let mut rt = Runtime::new().unwrap();
+        let bitcoind = MockRpcServer::start(&mut rt, BITCOIND_FIXTURES, StatusCode::INTERNAL_SERVER_ERROR);
+        let ethereum = MockRpcServer::start(&mut rt, ETHEREUM_FIXTURES, StatusCode::OK);
+        let mut config = RawConfig::new();
+        config.merge(File::from_str(CONFIG, FileFormat::Toml)).unwrap();
+        config.set("client.bitcoin_rpc_url", bitcoind.url()).unwrap();
+        config.set("client.ethereum_rpc_url", ethereum.url()).unwrap();
+        f(&mut config);
+        let config = config.try_into().unwrap();
+        TestEnv {
+            rt,
+            bitcoind,
+            ethereum,
+            config,
+        }
+    }
+
+    pub fn bitcoin_client(&self) -> Arc<BitcoinClientImpl> {
+        Arc::new(::create_btc_client(&self.config))
+    }
+
+    pub fn ethereum_client(&self) -> Arc<EthereumClientImpl> {
+        Arc::new(::create_eth_client(&self.config))
+    }
+}
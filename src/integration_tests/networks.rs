@@ -0,0 +1,40 @@
+use super::*;
+use client::EthereumClient;
+use models::*;
+
+#[test]
+fn test_check_networks() {
+    let mut env = TestEnv::new();
+    let (bitcoin_client, ethereum_client) = (env.bitcoin_client(), env.ethereum_client());
+    let check = ::check_networks(&env.config, &*bitcoin_client, &*ethereum_client);
+    assert_eq!(env.rt.block_on(check), Ok(()));
+
+    let mut env = TestEnv::with_config(|config| {
+        config.set("network.bitcoin", "testnet").unwrap();
+    });
+    let (bitcoin_client, ethereum_client) = (env.bitcoin_client(), env.ethereum_client());
+    let check = ::check_networks(&env.config, &*bitcoin_client, &*ethereum_client);
+    assert_eq!(env.rt.block_on(check), Err(()), "bitcoind reports regtest chain");
+
+    let mut env = TestEnv::with_config(|config| {
+        config
+            .set(
+                "network.ethereum.stq_contract_address",
+                "0x6ecbe1db9ef729cbe972c83fb886247691fb6beb",
+            )
+            .unwrap();
+    });
+    let (bitcoin_client, ethereum_client) = (env.bitcoin_client(), env.ethereum_client());
+    let check = ::check_networks(&env.config, &*bitcoin_client, &*ethereum_client);
+    assert_eq!(env.rt.block_on(check), Err(()), "there is no contract at the address");
+
+    // STQ contract is optional, e.g. until it's deployed on the network
+    let mut env = TestEnv::new();
+    env.config.network.ethereum.stq_contract_address = None;
+    let (bitcoin_client, ethereum_client) = (env.bitcoin_client(), env.ethereum_client());
+    let check = ::check_networks(&env.config, &*bitcoin_client, &*ethereum_client);
+    assert_eq!(env.rt.block_on(check), Ok(()));
+    assert!(env.ethereum.calls("eth_getCode").is_empty());
+    let address = EthereumAddress::new(ETH_SENDER.to_string());
+    assert!(env.rt.block_on(ethereum_client.get_stq_balance(address, BlockTag::Latest)).is_err());
+}
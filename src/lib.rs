@@ -9,7 +9,13 @@ extern crate log;
 #[macro_use]
 extern crate http_router;
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate sentry;
+#[macro_use]
+extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
 
 extern crate base64;
 extern crate chrono;
@@ -20,76 +26,96 @@ extern crate futures_cpupool;
 extern crate gelf;
 extern crate hyper;
 extern crate hyper_tls;
+extern crate prometheus;
 extern crate rand;
 extern crate regex;
 extern crate rlp;
+#[macro_use]
+extern crate schemars;
 extern crate serde;
 #[macro_use]
 extern crate serde_json;
 extern crate lapin_async;
 extern crate lapin_futures;
+extern crate openssl;
 extern crate r2d2;
 extern crate serde_qs;
 extern crate simplelog;
+extern crate sled;
+extern crate tiny_keccak;
 extern crate tokio;
-extern crate tokio_core;
 extern crate uuid;
 extern crate validator;
 
 #[macro_use]
 mod macros;
 mod api;
-mod client;
-mod config;
+pub mod client;
+pub mod config;
+mod history;
+#[cfg(test)]
+mod integration_tests;
 mod logger;
-mod models;
+mod metrics;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod mocks;
+pub mod models;
 mod pollers;
 mod prelude;
-mod rabbit;
+pub mod rabbit;
 mod sentry_integration;
 mod services;
+mod storage;
 mod utils;
 
 use std::sync::Arc;
 use std::time::Duration;
 
-use self::client::{BitcoinClient, BitcoinClientImpl, EthereumClient, EthereumClientImpl, HttpClientImpl};
-use self::pollers::{BitcoinPollerService, EthereumPollerService, StoriqaPollerService};
+use futures::future;
+
+use self::client::{BitcoinClient, BitcoinClientImpl, EthereumClient, EthereumClientImpl, HttpClient, HttpClientImpl};
+use self::pollers::{
+    BackfillRegistry, BackfillRunner, BitcoinPollerService, BroadcastRegistry, BroadcastTrackerService, EthereumPollerService,
+    PollerStatus, StoriqaPollerService,
+};
 use self::utils::log_error;
 use config::Config;
+use history::{HistoryIndex, SledHistoryIndex};
+use models::{BlockTag, Currency, EthereumAddress};
 use prelude::*;
-use rabbit::{RabbitConnectionManager, TransactionPublisherImpl};
+use rabbit::{RabbitConnectionManager, TransactionPublisher, TransactionPublisherImpl};
+use services::{
+    BackfillServiceImpl, BitcoinServiceImpl, BlockServiceImpl, BroadcastServiceImpl, EthereumServiceImpl, HealthServiceImpl,
+    HistoryServiceImpl, NonceServiceImpl,
+};
+use storage::{DbStorage, Storage};
 
 pub fn print_config() {
     println!("Parsed config: {:?}", get_config());
 }
 
+pub fn print_message_schema() {
+    println!("{}", serde_json::to_string_pretty(&rabbit::transaction_envelope_schema()).unwrap());
+}
+
 pub fn start_server() {
     let config = get_config();
     // Prepare sentry integration
-    let _sentry = sentry_integration::init(config.sentry.as_ref());
+    let _sentry = sentry_integration::init(config.sentry.as_ref(), &config.mode);
     // Prepare logger
     logger::init(&config);
 
     let mut rt = tokio::runtime::Runtime::new().unwrap();
 
+    // Clients are shared by pollers and api, so that they reuse the same connection pool
     let http_client = HttpClientImpl::new(&config, log::Level::Trace);
-    let bitcoin_client = Arc::new(BitcoinClientImpl::new(
-        Arc::new(http_client.clone()),
-        config.mode.clone(),
-        config.client.bitcoin_rpc_url.clone(),
-        config.client.bitcoin_rpc_user.clone(),
-        config.client.bitcoin_rpc_password.clone(),
-    ));
-    let ethereum_client = Arc::new(EthereumClientImpl::new(
-        Arc::new(http_client.clone()),
-        config.mode.clone(),
-        config.client.infura_key.clone(),
-        config.client.stq_contract_address.clone(),
-        config.client.stq_transfer_topic.clone(),
-        config.client.stq_approval_topic.clone(),
-        config.client.stq_balance_method.clone(),
-    ));
+    let bitcoin_client = Arc::new(create_btc_client_with(&config, Arc::new(http_client.clone())));
+    let ethereum_client = Arc::new(create_eth_client_with(&config, Arc::new(http_client.clone())));
+    let api_http_client = Arc::new(http_client.with_log_level(log::Level::Debug));
+    let api_bitcoin_client = Arc::new(create_btc_client_with(&config, api_http_client.clone()));
+    let api_ethereum_client = Arc::new(create_eth_client_with(&config, api_http_client.clone()));
+    rt.block_on(check_networks(&config, &*bitcoin_client, &*ethereum_client))
+        .expect("Nodes are not connected to configured networks");
 
     debug!("Started creating rabbit connection pool");
     let rabbit_connection_manager = rt
@@ -99,48 +125,153 @@ pub fn start_server() {
         })
         .expect("Can not create rabbit connection manager");
     debug!("Finished creating rabbit connection manager");
+    let poller_status = PollerStatus::default();
+    let backfill_registry = BackfillRegistry::default();
+    let mut backfill_runner = None;
+    let mut broadcast_tracker = None;
+    let mut health_publisher = None;
+    let history_index = config.history.as_ref().map(|history| {
+        let index = SledHistoryIndex::open(&history.path)
+            .map_err(|e| {
+                log_error(&e);
+            })
+            .expect("Can not open history index");
+        Arc::new(index) as Arc<HistoryIndex>
+    });
+    let storage = config.database.as_ref().map(|database| {
+        let storage = DbStorage::connect(database)
+            .map_err(|e| {
+                log_error(&e);
+            })
+            .expect("Can not connect to database");
+        Arc::new(storage) as Arc<Storage>
+    });
+    let broadcast_registry = BroadcastRegistry::new(storage.clone());
     if config.poller.enabled {
         let channel = Arc::new(rabbit_connection_manager.get_channel().expect("Can not get channel from pool"));
         let publisher = rt
-            .block_on(TransactionPublisherImpl::init(channel))
+            .block_on(TransactionPublisherImpl::init(channel, &config))
             .map_err(|e| {
                 log_error(&e);
             })
             .expect("Can not create rabbit connection manager");
 
         let publisher = Arc::new(publisher);
+        health_publisher = Some(publisher.clone() as Arc<TransactionPublisher>);
+        let retention = chrono::Duration::seconds(config.broadcast_tracker.retention_secs as i64);
+        rt.block_on(broadcast_registry.load(chrono::Utc::now() - retention))
+            .map_err(|e| {
+                log_error(&e);
+            })
+            .expect("Can not load tracked transactions");
         let ethereum_poller = EthereumPollerService::new(
             Duration::from_secs(config.poller.ethereum_interval_secs as u64),
             ethereum_client.clone(),
             publisher.clone(),
             config.poller.ethereum_number_of_tracked_confirmations,
+            config.poller.max_catch_up_blocks,
+            poller_status.clone(),
+            history_index.clone(),
+            storage.clone(),
         );
         let storiqa_poller = StoriqaPollerService::new(
             Duration::from_secs(config.poller.storiqa_interval_secs as u64),
             ethereum_client.clone(),
             publisher.clone(),
             config.poller.storiqa_number_of_tracked_confirmations,
+            config.poller.max_catch_up_blocks,
+            poller_status.clone(),
+            history_index.clone(),
+            storage.clone(),
         );
         let bitcoin_poller = BitcoinPollerService::new(
             Duration::from_secs(config.poller.bitcoin_interval_secs as u64),
             bitcoin_client.clone(),
             publisher.clone(),
             config.poller.bitcoin_number_of_tracked_confirmations,
+            config.poller.max_catch_up_blocks,
+            poller_status.clone(),
+            history_index.clone(),
+            storage.clone(),
+            Some(broadcast_registry.clone()),
+        );
+        let tracker = BroadcastTrackerService::new(
+            config.broadcast_tracker.clone(),
+            bitcoin_client.clone(),
+            ethereum_client.clone(),
+            publisher.clone(),
+            broadcast_registry.clone(),
         );
+        backfill_runner = Some(BackfillRunner::new(
+            config.backfill.clone(),
+            bitcoin_client.clone(),
+            ethereum_client.clone(),
+            publisher.clone(),
+            backfill_registry.clone(),
+            history_index.clone(),
+        ));
 
         rt.spawn(bitcoin_poller.start());
         rt.spawn(ethereum_poller.start());
-        rt.spawn(storiqa_poller.start());
+        if config.network.ethereum.stq_contract_address.is_some() {
+            rt.spawn(storiqa_poller.start());
+        } else {
+            info!(
+                "STQ contract is not configured for {} network, STQ poller is not started",
+                config.network.ethereum.name
+            );
+        }
+        rt.spawn(tracker.start());
+        broadcast_tracker = Some(tracker);
     }
 
-    rt.spawn(api::start_server(config));
+    let bitcoin_service = Arc::new(BitcoinServiceImpl::new(
+        api_bitcoin_client.clone(),
+        config.bitcoin_broadcast.clone(),
+        config.balances.clone(),
+    ));
+    let ethereum_service = Arc::new(EthereumServiceImpl::new(
+        api_ethereum_client.clone(),
+        config.network.ethereum.chain_id,
+        config.balances.clone(),
+    ));
+    let broadcast_service = Arc::new(BroadcastServiceImpl::new(broadcast_tracker, broadcast_registry));
+    let nonce_service = Arc::new(NonceServiceImpl::new(api_ethereum_client.clone(), config.nonce_reservation.clone()));
+    let history_service = Arc::new(HistoryServiceImpl::new(
+        history_index,
+        api_bitcoin_client.clone(),
+        api_ethereum_client.clone(),
+    ));
+    let backfill_service = Arc::new(BackfillServiceImpl::new(backfill_runner, backfill_registry));
+    let block_service = Arc::new(BlockServiceImpl::new(storage));
+    let health_service = Arc::new(HealthServiceImpl::new(
+        api_bitcoin_client,
+        api_ethereum_client,
+        rabbit_connection_manager,
+        health_publisher,
+        poller_status,
+    ));
+    rt.spawn(api::start_server(
+        config,
+        bitcoin_service,
+        ethereum_service,
+        broadcast_service,
+        nonce_service,
+        history_service,
+        backfill_service,
+        block_service,
+        health_service,
+    ));
 
     rt.shutdown_on_idle().wait().expect("Tokio runtime shutdown failed");
 }
 
 pub fn get_btc_blocks(hash: Option<String>, number: u64) {
-    let config = get_config();
-    let bitcoin_client = create_btc_client(&config);
+    let _ = print_btc_blocks(&get_config(), hash, number);
+}
+
+fn print_btc_blocks(config: &Config, hash: Option<String>, number: u64) -> Result<(), ()> {
+    let bitcoin_client = create_btc_client(config);
 
     let fut = bitcoin_client
         .last_blocks(hash, number)
@@ -152,8 +283,7 @@ pub fn get_btc_blocks(hash: Option<String>, number: u64) {
             log_error(&e);
         });
 
-    let mut core = ::tokio_core::reactor::Core::new().unwrap();
-    let _ = core.run(fut);
+    run_command(fut)
 }
 
 pub fn get_btc_transaction(hash: &str) {
@@ -161,7 +291,7 @@ pub fn get_btc_transaction(hash: &str) {
     let bitcoin_client = create_btc_client(&config);
 
     let fut = bitcoin_client
-        .get_transaction(hash.to_string(), 0)
+        .get_transaction(hash.to_string())
         .map(|tx| {
             println!("{:#?}", tx);
         })
@@ -169,8 +299,7 @@ pub fn get_btc_transaction(hash: &str) {
             log_error(&e);
         });
 
-    let mut core = ::tokio_core::reactor::Core::new().unwrap();
-    let _ = core.run(fut);
+    let _ = run_command(fut);
 }
 
 pub fn get_btc_transactions(hash: Option<String>, number: u64) {
@@ -187,22 +316,21 @@ pub fn get_btc_transactions(hash: Option<String>, number: u64) {
             log_error(&e);
         });
 
-    let mut core = ::tokio_core::reactor::Core::new().unwrap();
-    let _ = core.run(fut);
+    let _ = run_command(fut);
 }
 
 pub fn publish_btc_transactions(hash: Option<String>, number: u64) {
     let config = get_config();
     let bitcoin_client = Arc::new(create_btc_client(&config));
-    let mut core = ::tokio_core::reactor::Core::new().unwrap();
-    let rabbit_connection_manager = core
-        .run(RabbitConnectionManager::create(&config))
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+    let rabbit_connection_manager = rt
+        .block_on(RabbitConnectionManager::create(&config))
         .map_err(|e| {
             log_error(&e);
         })
         .expect("Can not create rabbit connection manager");
     let channel = Arc::new(rabbit_connection_manager.get_channel().expect("Can not get channel from pool"));
-    let f = TransactionPublisherImpl::init(channel)
+    let f = TransactionPublisherImpl::init(channel, &config)
         .map_err(|e| {
             log_error(&e);
         })
@@ -212,12 +340,17 @@ pub fn publish_btc_transactions(hash: Option<String>, number: u64) {
                 bitcoin_client,
                 Arc::new(publisher),
                 number as usize,
+                config.poller.max_catch_up_blocks,
+                PollerStatus::default(),
+                None,
+                None,
+                None,
             );
             btc_poller.publish_transactions(hash, number).map_err(|e| {
                 log_error(&e);
             })
         });
-    let _ = core.run(f);
+    let _ = rt.block_on(f);
 }
 
 pub fn get_eth_transaction(hash: &str) {
@@ -233,8 +366,7 @@ pub fn get_eth_transaction(hash: &str) {
             log_error(&e);
         });
 
-    let mut core = ::tokio_core::reactor::Core::new().unwrap();
-    let _ = core.run(fut);
+    let _ = run_command(fut);
 }
 
 pub fn get_eth_transactions(hash: Option<String>, number: u64) {
@@ -251,22 +383,21 @@ pub fn get_eth_transactions(hash: Option<String>, number: u64) {
             log_error(&e);
         });
 
-    let mut core = ::tokio_core::reactor::Core::new().unwrap();
-    let _ = core.run(fut);
+    let _ = run_command(fut);
 }
 
 pub fn publish_eth_transactions(hash: Option<String>, number: u64) {
     let config = get_config();
     let ethereum_client = Arc::new(create_eth_client(&config));
-    let mut core = ::tokio_core::reactor::Core::new().unwrap();
-    let rabbit_connection_manager = core
-        .run(RabbitConnectionManager::create(&config))
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+    let rabbit_connection_manager = rt
+        .block_on(RabbitConnectionManager::create(&config))
         .map_err(|e| {
             log_error(&e);
         })
         .expect("Can not create rabbit connection manager");
     let channel = Arc::new(rabbit_connection_manager.get_channel().expect("Can not get channel from pool"));
-    let f = TransactionPublisherImpl::init(channel)
+    let f = TransactionPublisherImpl::init(channel, &config)
         .map_err(|e| {
             log_error(&e);
         })
@@ -276,12 +407,16 @@ pub fn publish_eth_transactions(hash: Option<String>, number: u64) {
                 ethereum_client,
                 Arc::new(publisher),
                 number as usize,
+                config.poller.max_catch_up_blocks,
+                PollerStatus::default(),
+                None,
+                None,
             );
             eth_poller.publish_transactions(hash, number).map_err(|e| {
                 log_error(&e);
             })
         });
-    let _ = core.run(f);
+    let _ = rt.block_on(f);
 }
 
 pub fn get_stq_transaction(hash: &str) {
@@ -298,8 +433,7 @@ pub fn get_stq_transaction(hash: &str) {
             log_error(&e);
         });
 
-    let mut core = ::tokio_core::reactor::Core::new().unwrap();
-    let _ = core.run(fut);
+    let _ = run_command(fut);
 }
 
 pub fn get_stq_transactions(hash: Option<String>, number: u64) {
@@ -316,22 +450,21 @@ pub fn get_stq_transactions(hash: Option<String>, number: u64) {
             log_error(&e);
         });
 
-    let mut core = ::tokio_core::reactor::Core::new().unwrap();
-    let _ = core.run(fut);
+    let _ = run_command(fut);
 }
 
 pub fn publish_stq_transactions(hash: Option<String>, number: u64) {
     let config = get_config();
     let storiqa_client = Arc::new(create_eth_client(&config));
-    let mut core = ::tokio_core::reactor::Core::new().unwrap();
-    let rabbit_connection_manager = core
-        .run(RabbitConnectionManager::create(&config))
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+    let rabbit_connection_manager = rt
+        .block_on(RabbitConnectionManager::create(&config))
         .map_err(|e| {
             log_error(&e);
         })
         .expect("Can not create rabbit connection manager");
     let channel = Arc::new(rabbit_connection_manager.get_channel().expect("Can not get channel from pool"));
-    let f = TransactionPublisherImpl::init(channel)
+    let f = TransactionPublisherImpl::init(channel, &config)
         .map_err(|e| {
             log_error(&e);
         })
@@ -341,19 +474,150 @@ pub fn publish_stq_transactions(hash: Option<String>, number: u64) {
                 storiqa_client,
                 Arc::new(publisher),
                 number as usize,
+                config.poller.max_catch_up_blocks,
+                PollerStatus::default(),
+                None,
+                None,
             );
             stq_poller.publish_transactions(hash, number).map_err(|e| {
                 log_error(&e);
             })
         });
-    let _ = core.run(f);
+    let _ = rt.block_on(f);
+}
+
+/// Publishes transactions of blocks from `from_height` to `to_height` again, resuming the previous run of the range
+pub fn backfill(currency: &str, from_height: u64, to_height: u64) {
+    let config = get_config();
+    let currency = match currency {
+        "btc" => Currency::Btc,
+        "eth" => Currency::Eth,
+        "stq" => Currency::Stq,
+        other => {
+            eprintln!("Unknown currency: {}, expected btc, eth or stq", other);
+            return;
+        }
+    };
+    let bitcoin_client = Arc::new(create_btc_client(&config));
+    let ethereum_client = Arc::new(create_eth_client(&config));
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(check_networks(&config, &*bitcoin_client, &*ethereum_client))
+        .expect("Nodes are not connected to configured networks");
+    let rabbit_connection_manager = rt
+        .block_on(RabbitConnectionManager::create(&config))
+        .map_err(|e| {
+            log_error(&e);
+        })
+        .expect("Can not create rabbit connection manager");
+    let channel = Arc::new(rabbit_connection_manager.get_channel().expect("Can not get channel from pool"));
+    let publisher = rt
+        .block_on(TransactionPublisherImpl::init(channel, &config))
+        .map_err(|e| {
+            log_error(&e);
+        })
+        .expect("Can not create rabbit connection manager");
+    // history index is locked by running server, it's only filled by backfills started through admin API
+    let runner = BackfillRunner::new(
+        config.backfill.clone(),
+        bitcoin_client,
+        ethereum_client,
+        Arc::new(publisher),
+        BackfillRegistry::default(),
+        None,
+    );
+    let runner_clone = runner.clone();
+    let f = runner
+        .head(currency)
+        .map_err(|e| {
+            log_error(&e);
+        })
+        .and_then(move |head| {
+            services::validate_backfill_range(from_height, to_height, head).map_err(|errors| {
+                eprintln!("Invalid range: {}", serde_json::to_string(&errors).unwrap_or_default());
+            })
+        })
+        .and_then(move |_| {
+            let backfill = runner_clone
+                .prepare(currency, from_height, to_height)
+                .map_err(|e| {
+                    log_error(&e);
+                })?
+                .expect("backfill registry of a new process is empty");
+            if backfill.next_height > backfill.from_height {
+                println!("Resuming backfill {} from block {}", backfill.id, backfill.next_height);
+            }
+            Ok((runner_clone, backfill))
+        })
+        .and_then(|(runner, backfill)| {
+            runner.run(backfill).map_err(|e| {
+                log_error(&e);
+            })
+        })
+        .map(|backfill| {
+            println!("{:#?}", backfill);
+        });
+    let _ = rt.block_on(f);
+}
+
+/// Checks that nodes report networks from config, so that transactions of another network are never published
+fn check_networks(config: &Config, bitcoin_client: &BitcoinClient, ethereum_client: &EthereumClient) -> impl Future<Item = (), Error = ()> {
+    let bitcoin_network = config.network.bitcoin;
+    let ethereum_network = config.network.ethereum.clone();
+    let stq_contract_code = match ethereum_network.stq_contract_address {
+        Some(ref address) => {
+            let address = EthereumAddress::new(address.trim_start_matches("0x").to_string());
+            future::Either::A(ethereum_client.get_code(address, BlockTag::Latest).map(Some))
+        }
+        None => future::Either::B(future::ok(None)),
+    };
+    bitcoin_client
+        .get_chain()
+        .join3(ethereum_client.get_chain_id(), stq_contract_code)
+        .map_err(|e| {
+            log_error(&e);
+        })
+        .and_then(move |(chain, chain_id, stq_contract_code)| {
+            let mut matches = true;
+            if chain != bitcoin_network.chain() {
+                error!(
+                    "Bitcoin node is connected to {} chain, expected {} chain of {}",
+                    chain,
+                    bitcoin_network.chain(),
+                    bitcoin_network
+                );
+                matches = false;
+            }
+            if chain_id != ethereum_network.chain_id {
+                error!(
+                    "Ethereum node is connected to chain {}, expected chain {} of {}",
+                    chain_id, ethereum_network.chain_id, ethereum_network.name
+                );
+                matches = false;
+            } else if stq_contract_code.as_ref().map_or(false, |code| code == "0x") {
+                error!(
+                    "There is no STQ contract at {} on chain {} of {}",
+                    ethereum_network.stq_contract_address.unwrap_or_default(),
+                    ethereum_network.chain_id,
+                    ethereum_network.name
+                );
+                matches = false;
+            }
+            if matches {
+                Ok(())
+            } else {
+                Err(())
+            }
+        })
 }
 
 fn create_btc_client(config: &Config) -> BitcoinClientImpl {
-    let http_client = Arc::new(HttpClientImpl::new(config, log::Level::Debug));
+    create_btc_client_with(config, Arc::new(HttpClientImpl::new(config, log::Level::Debug)))
+}
+
+fn create_btc_client_with(config: &Config, http_client: Arc<HttpClient>) -> BitcoinClientImpl {
     BitcoinClientImpl::new(
-        http_client.clone(),
-        config.mode.clone(),
+        http_client,
+        config.network.bitcoin,
         config.client.bitcoin_rpc_url.clone(),
         config.client.bitcoin_rpc_user.clone(),
         config.client.bitcoin_rpc_password.clone(),
@@ -361,18 +625,31 @@ fn create_btc_client(config: &Config) -> BitcoinClientImpl {
 }
 
 fn create_eth_client(config: &Config) -> EthereumClientImpl {
-    let http_client = Arc::new(HttpClientImpl::new(config, log::Level::Debug));
+    create_eth_client_with(config, Arc::new(HttpClientImpl::new(config, log::Level::Debug)))
+}
+
+fn create_eth_client_with(config: &Config, http_client: Arc<HttpClient>) -> EthereumClientImpl {
     EthereumClientImpl::new(
-        http_client.clone(),
-        config.mode.clone(),
-        config.client.infura_key.clone(),
-        config.client.stq_contract_address.clone(),
+        http_client,
+        config.ethereum_rpc_url(),
+        config.network.ethereum.stq_contract_address.clone(),
         config.client.stq_transfer_topic.clone(),
         config.client.stq_approval_topic.clone(),
         config.client.stq_balance_method.clone(),
     )
 }
 
+/// Runs future of a CLI command to completion. HTTP client needs timer of tokio runtime for its timeouts and retries
+fn run_command<F>(f: F) -> Result<F::Item, F::Error>
+where
+    F: Future + Send + 'static,
+    F::Item: Send + 'static,
+    F::Error: Send + 'static,
+{
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(f)
+}
+
 fn get_config() -> Config {
     config::Config::new().unwrap_or_else(|e| panic!("Error parsing config: {}", e))
 }
@@ -11,8 +11,15 @@ fn main() {
 
     if let Some(_) = matches.subcommand_matches("config") {
         blockchain_gateway_lib::print_config();
+    } else if let Some(_) = matches.subcommand_matches("print_message_schema") {
+        blockchain_gateway_lib::print_message_schema();
     } else if let Some(_) = matches.subcommand_matches("server") {
         blockchain_gateway_lib::start_server();
+    } else if let Some(matches) = matches.subcommand_matches("backfill") {
+        let currency = matches.value_of("currency").unwrap();
+        let from_height: u64 = matches.value_of("from-height").unwrap().parse().unwrap();
+        let to_height: u64 = matches.value_of("to-height").unwrap().parse().unwrap();
+        blockchain_gateway_lib::backfill(currency, from_height, to_height);
     } else if let Some(matches) = matches.subcommand_matches("get_btc_blocks") {
         let param = matches.value_of("number").unwrap_or("1");
         let number: u64 = param.parse().unwrap();
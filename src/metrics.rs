@@ -0,0 +1,137 @@
+//! Prometheus metrics of the gateway, exported in text exposition format on `GET /metrics`
+
+use std::time::{Duration, Instant};
+
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+
+/// Prefix of all metric names
+const NAMESPACE: &str = "blockchain_gateway";
+/// Buckets for durations of network calls, in seconds
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+/// Buckets for durations of poller ticks, in seconds
+const TICK_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::new();
+    pub static ref HTTP_CLIENT_REQUESTS: IntCounterVec = counter(
+        "http_client_requests_total",
+        "Requests to upstream services by host and response status",
+        &["host", "status"],
+    );
+    pub static ref HTTP_CLIENT_REQUEST_DURATION: HistogramVec = histogram(
+        "http_client_request_duration_seconds",
+        "Latency of requests to upstream services by host",
+        &["host"],
+        LATENCY_BUCKETS,
+    );
+    pub static ref RPC_CALLS: IntCounterVec = counter(
+        "rpc_calls_total",
+        "JSON-RPC calls to blockchain nodes by method and result",
+        &["chain", "method", "result"],
+    );
+    pub static ref POLLER_TICK_DURATION: HistogramVec = histogram(
+        "poller_tick_duration_seconds",
+        "Duration of poller ticks by currency and result",
+        &["currency", "result"],
+        TICK_BUCKETS,
+    );
+    pub static ref POLLER_CHAIN_TIP: IntGaugeVec = gauge(
+        "poller_chain_tip_block",
+        "Number of the latest block known by node at the start of poller tick",
+        &["currency"],
+    );
+    pub static ref POLLER_LAG_BLOCKS: IntGaugeVec = gauge(
+        "poller_lag_blocks",
+        "Number of blocks between chain tip and the latest block with published transactions",
+        &["currency"],
+    );
+    pub static ref PUBLISHED_MESSAGES: IntCounterVec = counter(
+        "published_messages_total",
+        "Messages published to RabbitMQ by routing key and result",
+        &["routing_key", "result"],
+    );
+    pub static ref BROADCAST_STATUS_CHANGES: IntCounterVec = counter(
+        "broadcast_status_changes_total",
+        "Changes of status of posted transactions by currency and new status, stuck transactions are labeled as stuck",
+        &["currency", "status"],
+    );
+    pub static ref REBROADCASTS: IntCounterVec = counter(
+        "rebroadcasts_total",
+        "Posted transactions sent again after they fell out of mempool by currency and result",
+        &["currency", "result"],
+    );
+    pub static ref API_REQUEST_DURATION: HistogramVec = histogram(
+        "api_request_duration_seconds",
+        "Latency of API requests by route, method and response status",
+        &["route", "method", "status"],
+        LATENCY_BUCKETS,
+    );
+    pub static ref API_RATE_LIMITED_REQUESTS: IntCounterVec = counter(
+        "api_rate_limited_requests_total",
+        "API requests rejected by rate limiter by client and route scope, limits of source IP are labeled as ip",
+        &["client", "scope"],
+    );
+}
+
+/// Renders all metrics in Prometheus text exposition format
+pub fn render() -> String {
+    let mut out = Vec::new();
+    // encoding into memory fails only for malformed metric families, which are never registered
+    let _ = TextEncoder::new().encode(&REGISTRY.gather(), &mut out);
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Label for success or failure of an operation
+pub fn result_label<T, E>(result: &Result<T, E>) -> &'static str {
+    match result {
+        Ok(_) => "ok",
+        Err(_) => "error",
+    }
+}
+
+pub fn duration_secs(start: Instant) -> f64 {
+    let elapsed: Duration = start.elapsed();
+    elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9
+}
+
+// Metric names and labels are constants, so creating and registering them can fail only on a typo
+
+fn counter(name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    let counter = IntCounterVec::new(Opts::new(name, help).namespace(NAMESPACE), labels).expect("Invalid counter");
+    REGISTRY.register(Box::new(counter.clone())).expect("Counter is already registered");
+    counter
+}
+
+fn gauge(name: &str, help: &str, labels: &[&str]) -> IntGaugeVec {
+    let gauge = IntGaugeVec::new(Opts::new(name, help).namespace(NAMESPACE), labels).expect("Invalid gauge");
+    REGISTRY.register(Box::new(gauge.clone())).expect("Gauge is already registered");
+    gauge
+}
+
+fn histogram(name: &str, help: &str, labels: &[&str], buckets: &[f64]) -> HistogramVec {
+    let opts = HistogramOpts::new(name, help).namespace(NAMESPACE).buckets(buckets.to_vec());
+    let histogram = HistogramVec::new(opts, labels).expect("Invalid histogram");
+    REGISTRY
+        .register(Box::new(histogram.clone()))
+        .expect("Histogram is already registered");
+    histogram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        // metrics are global, so labels are unique to this test
+        RPC_CALLS.with_label_values(&["test", "say \"hi\"", "ok"]).inc();
+        RPC_CALLS.with_label_values(&["test", "say \"hi\"", "ok"]).inc();
+        POLLER_TICK_DURATION.with_label_values(&["test", "ok"]).observe(0.3);
+        let out = render();
+        assert!(out.contains("# TYPE blockchain_gateway_rpc_calls_total counter\n"));
+        assert!(out.contains("blockchain_gateway_rpc_calls_total{chain=\"test\",method=\"say \\\"hi\\\"\",result=\"ok\"} 2\n"));
+        assert!(out.contains("# TYPE blockchain_gateway_poller_tick_duration_seconds histogram\n"));
+        assert!(out.contains("blockchain_gateway_poller_tick_duration_seconds_bucket{currency=\"test\",result=\"ok\",le=\"0.5\"} 1\n"));
+        assert!(out.contains("blockchain_gateway_poller_tick_duration_seconds_count{currency=\"test\",result=\"ok\"} 1\n"));
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use futures::{future, stream};
+use serde_json::Value;
+
+use super::Script;
+use client::{BitcoinClient, Block, Error, ErrorContext, ErrorKind, ErrorSource};
+use models::*;
+use prelude::*;
+
+/// Fake of bitcoin node client. Blocks are added with `add_block`, the highest one is the best block
+#[derive(Clone, Default)]
+pub struct BitcoinClientMock {
+    state: Arc<Mutex<BitcoinState>>,
+}
+
+#[derive(Default)]
+struct BitcoinState {
+    script: Script<ErrorKind>,
+    blocks: BTreeMap<u64, ScannedBlock>,
+    // transactions found by hash besides the ones of blocks
+    transactions: HashMap<String, BlockchainTransaction>,
+    utxos: HashMap<String, Vec<Utxo>>,
+    unspent_outputs: HashMap<(String, u32), Amount>,
+    mempool: Vec<String>,
+    mempool_reject_reason: Option<String>,
+    sent_tx_hash: Option<TxHash>,
+    chain: Option<String>,
+    syncing: bool,
+}
+
+impl BitcoinClientMock {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds block with its transactions, the block with the same height is replaced
+    pub fn add_block(&self, block: ScannedBlock) {
+        self.state.lock().unwrap().blocks.insert(block.height, block);
+    }
+
+    /// Adds transaction found by `get_transaction`, e.g. the one in mempool
+    pub fn add_transaction(&self, tx: BlockchainTransaction) {
+        self.state.lock().unwrap().transactions.insert(tx.hash.clone(), tx);
+    }
+
+    /// Sets utxos of address, its balance is the sum of utxos
+    pub fn set_utxos(&self, address: BitcoinAddress, utxos: Vec<Utxo>) {
+        self.state.lock().unwrap().utxos.insert(address.to_string(), utxos);
+    }
+
+    /// Sets value of unspent output, other outputs are spent. Spends in mempool are not told from the ones in blocks
+    pub fn set_unspent_output(&self, txid: String, index: u32, value: Amount) {
+        self.state.lock().unwrap().unspent_outputs.insert((txid, index), value);
+    }
+
+    /// Sets hashes of transactions in mempool, it's empty by default
+    pub fn set_mempool(&self, txids: Vec<String>) {
+        self.state.lock().unwrap().mempool = txids;
+    }
+
+    /// Transactions are rejected from mempool with `reason`, all are accepted if it's `None`
+    pub fn set_mempool_reject_reason(&self, reason: Option<String>) {
+        self.state.lock().unwrap().mempool_reject_reason = reason;
+    }
+
+    /// Sets hash answered to sent transactions, sending fails with `ErrorKind::Internal` until it's set
+    pub fn set_sent_tx_hash(&self, hash: TxHash) {
+        self.state.lock().unwrap().sent_tx_hash = Some(hash);
+    }
+
+    /// Sets chain reported by node, `regtest` by default
+    pub fn set_chain(&self, chain: &str) {
+        self.state.lock().unwrap().chain = Some(chain.to_string());
+    }
+
+    pub fn set_syncing(&self, syncing: bool) {
+        self.state.lock().unwrap().syncing = syncing;
+    }
+
+    /// Calls of `method` fail with `kind` until it's recovered
+    pub fn fail(&self, method: &str, kind: ErrorKind) {
+        self.state.lock().unwrap().script.fail(method, kind);
+    }
+
+    pub fn recover(&self, method: &str) {
+        self.state.lock().unwrap().script.recover(method);
+    }
+
+    /// Arguments of calls of `method`
+    pub fn calls(&self, method: &str) -> Vec<Value> {
+        self.state.lock().unwrap().script.calls(method)
+    }
+
+    /// Records call and answers it with `f`, unless the method is scripted to fail
+    fn answer<T, F>(&self, method: &str, args: Value, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&BitcoinState) -> Result<T, Error>,
+    {
+        let mut state = self.state.lock().unwrap();
+        match state.script.call(method, args) {
+            Some(kind) => Err(kind.into()),
+            None => f(&state),
+        }
+    }
+}
+
+impl BitcoinState {
+    fn best_height(&self) -> Option<u64> {
+        self.blocks.keys().next_back().cloned()
+    }
+
+    /// Blocks from `start_block_hash` or the best one down to previous blocks, the highest first
+    fn last_blocks(&self, start_block_hash: Option<String>, blocks_count: u64) -> Result<Vec<&ScannedBlock>, Error> {
+        let start = match start_block_hash {
+            Some(hash) => self
+                .blocks
+                .values()
+                .find(|block| block.hash == hash)
+                .map(|block| block.height)
+                .ok_or(ectx!(try err ErrorSource::Server, ErrorKind::NotFound => hash))?,
+            None => match self.best_height() {
+                Some(height) => height,
+                None => return Ok(Vec::new()),
+            },
+        };
+        let from = (start + 1).saturating_sub(blocks_count);
+        Ok(self.blocks.range(from..=start).rev().map(|(_, block)| block).collect())
+    }
+
+    /// Block in format of node. Its `tx` has no coinbase transaction, since it's not a part of `ScannedBlock`
+    fn block(&self, block: &ScannedBlock) -> Block {
+        let previousblockhash = self
+            .blocks
+            .range(..block.height)
+            .next_back()
+            .map(|(_, block)| block.hash.clone())
+            .unwrap_or_default();
+        let best_height = self.best_height().unwrap_or(block.height);
+        Block {
+            hash: block.hash.clone(),
+            previousblockhash,
+            tx: block.transactions.iter().map(|tx| tx.hash.clone()).collect(),
+            height: block.height,
+            confirmations: (best_height - block.height + 1) as usize,
+            time: block.time.map(|time| time.timestamp()).unwrap_or_default(),
+        }
+    }
+
+    fn transaction(&self, hash: &str) -> Option<BlockchainTransaction> {
+        self.blocks
+            .values()
+            .flat_map(|block| block.transactions.iter())
+            .chain(self.transactions.values())
+            .find(|tx| tx.hash == hash)
+            .cloned()
+    }
+}
+
+impl BitcoinClient for BitcoinClientMock {
+    fn get_utxos(&self, address: BitcoinAddress) -> Box<Future<Item = Vec<Utxo>, Error = Error> + Send> {
+        let res = self.answer("get_utxos", json!([address]), |state| {
+            Ok(state.utxos.get(&address.to_string()).cloned().unwrap_or_default())
+        });
+        Box::new(future::result(res))
+    }
+
+    fn get_balance(&self, address: BitcoinAddress) -> Box<Future<Item = Amount, Error = Error> + Send> {
+        let res = self.answer("get_balance", json!([address]), |state| {
+            state
+                .utxos
+                .get(&address.to_string())
+                .into_iter()
+                .flat_map(|utxos| utxos.iter())
+                .fold(Some(Amount::new(0)), |acc, utxo| acc.and_then(|acc| acc.checked_add(utxo.value)))
+                .ok_or(ectx!(err ErrorContext::Overflow, ErrorKind::Internal => address))
+        });
+        Box::new(future::result(res))
+    }
+
+    fn send_raw_tx(&self, tx: RawBitcoinTransaction) -> Box<Future<Item = TxHash, Error = Error> + Send> {
+        let res = self.answer("send_raw_tx", json!([tx]), |state| {
+            state
+                .sent_tx_hash
+                .clone()
+                .ok_or(ectx!(err ErrorSource::Server, ErrorKind::Internal => tx))
+        });
+        Box::new(future::result(res))
+    }
+
+    fn test_mempool_accept(&self, tx: RawBitcoinTransaction) -> Box<Future<Item = MempoolAcceptance, Error = Error> + Send> {
+        let res = self.answer("test_mempool_accept", json!([tx]), |state| {
+            Ok(MempoolAcceptance {
+                allowed: state.mempool_reject_reason.is_none(),
+                reject_reason: state.mempool_reject_reason.clone(),
+            })
+        });
+        Box::new(future::result(res))
+    }
+
+    fn get_unspent_output_value(
+        &self,
+        txid: String,
+        index: u32,
+        include_mempool: bool,
+    ) -> Box<Future<Item = Option<Amount>, Error = Error> + Send> {
+        let res = self.answer("get_unspent_output_value", json!([txid, index, include_mempool]), |state| {
+            Ok(state.unspent_outputs.get(&(txid, index)).cloned())
+        });
+        Box::new(future::result(res))
+    }
+
+    fn get_mempool_txids(&self) -> Box<Future<Item = Vec<String>, Error = Error> + Send> {
+        let res = self.answer("get_mempool_txids", json!([]), |state| Ok(state.mempool.clone()));
+        Box::new(future::result(res))
+    }
+
+    fn get_transaction(&self, hash: String) -> Box<Future<Item = BlockchainTransaction, Error = Error> + Send> {
+        let res = self.answer("get_transaction", json!([hash]), |state| {
+            state
+                .transaction(&hash)
+                .ok_or(ectx!(err ErrorSource::Server, ErrorKind::NotFound => hash))
+        });
+        Box::new(future::result(res))
+    }
+
+    fn last_blocks(&self, start_block_hash: Option<String>, blocks_count: u64) -> Box<Stream<Item = Block, Error = Error> + Send> {
+        let res = self.answer("last_blocks", json!([start_block_hash, blocks_count]), |state| {
+            state
+                .last_blocks(start_block_hash, blocks_count)
+                .map(|blocks| blocks.into_iter().map(|block| state.block(block)).collect::<Vec<_>>())
+        });
+        Box::new(future::result(res).map(stream::iter_ok).flatten_stream())
+    }
+
+    fn last_transactions(
+        &self,
+        start_block_hash: Option<String>,
+        blocks_count: u64,
+    ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        let res = self.answer("last_transactions", json!([start_block_hash, blocks_count]), |state| {
+            state.last_blocks(start_block_hash, blocks_count).map(|blocks| {
+                blocks
+                    .into_iter()
+                    .flat_map(|block| block.transactions.iter().cloned())
+                    .collect::<Vec<_>>()
+            })
+        });
+        Box::new(future::result(res).map(stream::iter_ok).flatten_stream())
+    }
+
+    fn get_block_transactions(&self, height: u64) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        let res = self.answer("get_block_transactions", json!([height]), |state| {
+            state
+                .blocks
+                .get(&height)
+                .map(|block| block.transactions.clone())
+                .ok_or(ectx!(err ErrorSource::Server, ErrorKind::NotFound => height))
+        });
+        Box::new(future::result(res).map(stream::iter_ok).flatten_stream())
+    }
+
+    fn get_chain_tip(&self) -> Box<Future<Item = ChainTip, Error = Error> + Send> {
+        let res = self.answer("get_chain_tip", json!([]), |state| {
+            state
+                .blocks
+                .values()
+                .next_back()
+                .map(|block| ChainTip {
+                    height: block.height,
+                    hash: block.hash.clone(),
+                })
+                .ok_or(ectx!(err ErrorSource::Server, ErrorKind::NotFound))
+        });
+        Box::new(future::result(res))
+    }
+
+    fn get_node_status(&self) -> Box<Future<Item = NodeStatus, Error = Error> + Send> {
+        let res = self.answer("get_node_status", json!([]), |state| {
+            Ok(NodeStatus {
+                block_number: state.best_height().unwrap_or_default(),
+                syncing: state.syncing,
+            })
+        });
+        Box::new(future::result(res))
+    }
+
+    fn get_chain(&self) -> Box<Future<Item = String, Error = Error> + Send> {
+        let res = self.answer("get_chain", json!([]), |state| {
+            Ok(state.chain.clone().unwrap_or_else(|| "regtest".to_string()))
+        });
+        Box::new(future::result(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(height: u64, hash: &str, tx_hashes: &[&str]) -> ScannedBlock {
+        let transactions = tx_hashes
+            .iter()
+            .map(|tx_hash| BlockchainTransaction {
+                hash: tx_hash.to_string(),
+                from: vec![],
+                to: vec![],
+                block_number: Some(height),
+                block_hash: Some(hash.to_string()),
+                block_time: None,
+                currency: Currency::Btc,
+                fee: Amount::new(1000),
+                confirmations: 1,
+                erc20_operation_kind: None,
+                contract_deployment: false,
+            })
+            .collect();
+        ScannedBlock {
+            currency: Currency::Btc,
+            height,
+            hash: hash.to_string(),
+            time: None,
+            transactions,
+        }
+    }
+
+    #[test]
+    fn test_bitcoin_client_mock() {
+        let client = BitcoinClientMock::new();
+        client.add_block(block(100, "a", &["tx1"]));
+        client.add_block(block(101, "b", &["tx2", "tx3"]));
+        client.add_block(block(102, "c", &["tx4"]));
+
+        let hashes: Vec<_> = client
+            .last_transactions(Some("b".to_string()), 2)
+            .map(|tx| tx.hash)
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(hashes, vec!["tx2", "tx3", "tx1"]);
+        let blocks = client.last_blocks(None, 1).collect().wait().unwrap();
+        assert_eq!((blocks[0].previousblockhash.as_str(), blocks[0].confirmations), ("b", 1));
+        assert_eq!(client.get_transaction("tx3".to_string()).wait().unwrap().block_number, Some(101));
+
+        client.fail("get_node_status", ErrorKind::GatewayTimeout);
+        assert_eq!(client.get_node_status().wait().unwrap_err().kind(), ErrorKind::GatewayTimeout);
+        client.recover("get_node_status");
+        assert_eq!(client.get_node_status().wait().unwrap().block_number, 102);
+        assert_eq!(client.calls("last_transactions"), vec![json!(["b", 2])]);
+    }
+}
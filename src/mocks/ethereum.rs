@@ -0,0 +1,437 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use futures::{future, stream};
+use serde_json::Value;
+
+use super::Script;
+use client::{Error, ErrorKind, ErrorSource, EthereumClient};
+use models::*;
+use prelude::*;
+
+/// Fake of ethereum node client. Blocks of eth and stq transactions are added with `add_block`,
+/// the highest one is the latest block unless block number is set explicitly. Block tags of balances are ignored
+#[derive(Clone, Default)]
+pub struct EthereumClientMock {
+    state: Arc<Mutex<EthereumState>>,
+}
+
+#[derive(Default)]
+struct EthereumState {
+    script: Script<ErrorKind>,
+    blocks: HashMap<Currency, BTreeMap<u64, ScannedBlock>>,
+    // transactions found by hash besides the ones of blocks
+    transactions: HashMap<String, BlockchainTransaction>,
+    statuses: HashMap<String, TransactionStatus>,
+    nonces: HashMap<String, u64>,
+    balances: HashMap<(Currency, String), Amount>,
+    balance_errors: HashMap<String, String>,
+    gas_estimate: Option<Result<Amount, ExecutionError>>,
+    call_result: Option<Result<String, ExecutionError>>,
+    sent_tx_hash: Option<TxHash>,
+    block_number: Option<u64>,
+    syncing: bool,
+    chain_id: Option<u64>,
+    codes: HashMap<String, String>,
+}
+
+impl EthereumClientMock {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds block with eth or stq transactions, the block of the same currency and height is replaced
+    pub fn add_block(&self, block: ScannedBlock) {
+        self.state
+            .lock()
+            .unwrap()
+            .blocks
+            .entry(block.currency)
+            .or_insert_with(BTreeMap::new)
+            .insert(block.height, block);
+    }
+
+    /// Adds eth transaction found by `get_eth_transaction`, e.g. the one in mempool
+    pub fn add_transaction(&self, tx: BlockchainTransaction) {
+        self.state.lock().unwrap().transactions.insert(tx.hash.clone(), tx);
+    }
+
+    /// Sets status of transaction. Otherwise known transactions are `Success` if they are in a block
+    /// and `Pending` if they are not
+    pub fn set_transaction_status(&self, hash: String, status: TransactionStatus) {
+        self.state.lock().unwrap().statuses.insert(hash, status);
+    }
+
+    /// Sets nonce of address, it's 0 by default
+    pub fn set_nonce(&self, address: EthereumAddress, nonce: u64) {
+        self.state.lock().unwrap().nonces.insert(address.to_string(), nonce);
+    }
+
+    /// Sets eth or stq balance of address, it's 0 by default
+    pub fn set_balance(&self, currency: Currency, address: EthereumAddress, balance: Amount) {
+        self.state.lock().unwrap().balances.insert((currency, address.to_string()), balance);
+    }
+
+    /// Balance of address fails with node's error `message` in batch requests
+    pub fn set_balance_error(&self, address: EthereumAddress, message: String) {
+        self.state.lock().unwrap().balance_errors.insert(address.to_string(), message);
+    }
+
+    /// Sets result of gas estimates, it's 21000 gas by default
+    pub fn set_gas_estimate(&self, estimate: Result<Amount, ExecutionError>) {
+        self.state.lock().unwrap().gas_estimate = Some(estimate);
+    }
+
+    /// Sets result of calls, it's empty return data by default
+    pub fn set_call_result(&self, result: Result<String, ExecutionError>) {
+        self.state.lock().unwrap().call_result = Some(result);
+    }
+
+    /// Sets hash answered to sent transactions, sending fails with `ErrorKind::Internal` until it's set
+    pub fn set_sent_tx_hash(&self, hash: TxHash) {
+        self.state.lock().unwrap().sent_tx_hash = Some(hash);
+    }
+
+    pub fn set_block_number(&self, block_number: u64) {
+        self.state.lock().unwrap().block_number = Some(block_number);
+    }
+
+    pub fn set_syncing(&self, syncing: bool) {
+        self.state.lock().unwrap().syncing = syncing;
+    }
+
+    /// Sets chain id reported by node, 1337 of dev chain by default
+    pub fn set_chain_id(&self, chain_id: u64) {
+        self.state.lock().unwrap().chain_id = Some(chain_id);
+    }
+
+    /// Sets hex encoded code of contract at address, there is no contract (`0x`) by default
+    pub fn set_code(&self, address: EthereumAddress, code: String) {
+        self.state.lock().unwrap().codes.insert(address.to_string(), code);
+    }
+
+    /// Calls of `method` fail with `kind` until it's recovered
+    pub fn fail(&self, method: &str, kind: ErrorKind) {
+        self.state.lock().unwrap().script.fail(method, kind);
+    }
+
+    pub fn recover(&self, method: &str) {
+        self.state.lock().unwrap().script.recover(method);
+    }
+
+    /// Arguments of calls of `method`
+    pub fn calls(&self, method: &str) -> Vec<Value> {
+        self.state.lock().unwrap().script.calls(method)
+    }
+
+    /// Records call and answers it with `f`, unless the method is scripted to fail
+    fn answer<T, F>(&self, method: &str, args: Value, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&EthereumState) -> Result<T, Error>,
+    {
+        let mut state = self.state.lock().unwrap();
+        match state.script.call(method, args) {
+            Some(kind) => Err(kind.into()),
+            None => f(&state),
+        }
+    }
+
+    fn balance(
+        &self,
+        method: &str,
+        currency: Currency,
+        address: EthereumAddress,
+        block: BlockTag,
+    ) -> Box<Future<Item = Amount, Error = Error> + Send> {
+        let res = self.answer(method, json!([address, block.to_string()]), |state| {
+            Ok(state.balance(currency, &address))
+        });
+        Box::new(future::result(res))
+    }
+
+    fn balances(
+        &self,
+        method: &str,
+        currency: Currency,
+        addresses: Vec<EthereumAddress>,
+        block: BlockTag,
+    ) -> Box<Future<Item = Vec<Result<Amount, String>>, Error = Error> + Send> {
+        let res = self.answer(method, json!([addresses, block.to_string()]), |state| {
+            Ok(addresses
+                .iter()
+                .map(|address| match state.balance_errors.get(&address.to_string()) {
+                    Some(message) => Err(message.clone()),
+                    None => Ok(state.balance(currency, address)),
+                })
+                .collect())
+        });
+        Box::new(future::result(res))
+    }
+
+    fn last_transactions(
+        &self,
+        method: &str,
+        currency: Currency,
+        start_block_hash: Option<String>,
+        blocks_count: u64,
+    ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        let res = self.answer(method, json!([start_block_hash, blocks_count]), |state| {
+            let blocks = match state.blocks.get(&currency) {
+                Some(blocks) => blocks,
+                None => return Ok(Vec::new()),
+            };
+            let start = match start_block_hash {
+                Some(hash) => blocks
+                    .values()
+                    .find(|block| block.hash == hash)
+                    .map(|block| block.height)
+                    .ok_or(ectx!(try err ErrorSource::Server, ErrorKind::NotFound => hash))?,
+                None => state.block_number(),
+            };
+            let from = (start + 1).saturating_sub(blocks_count);
+            Ok(blocks
+                .range(from..=start)
+                .flat_map(|(_, block)| block.transactions.iter().cloned())
+                .collect::<Vec<_>>())
+        });
+        Box::new(future::result(res).map(stream::iter_ok).flatten_stream())
+    }
+
+    fn last_transactions_to(
+        &self,
+        method: &str,
+        currency: Currency,
+        current_block: u64,
+        blocks_count: u64,
+    ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        let res = self.answer(method, json!([current_block, blocks_count]), |state| {
+            let from = (current_block + 1).saturating_sub(blocks_count);
+            Ok(state
+                .blocks
+                .get(&currency)
+                .into_iter()
+                .flat_map(|blocks| blocks.range(from..=current_block))
+                .flat_map(|(_, block)| block.transactions.iter().cloned())
+                .collect::<Vec<_>>())
+        });
+        Box::new(future::result(res).map(stream::iter_ok).flatten_stream())
+    }
+
+    fn block_transactions(
+        &self,
+        method: &str,
+        currency: Currency,
+        number: u64,
+    ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        let res = self.answer(method, json!([number]), |state| {
+            state
+                .blocks
+                .get(&currency)
+                .and_then(|blocks| blocks.get(&number))
+                .map(|block| block.transactions.clone())
+                .ok_or(ectx!(err ErrorSource::Server, ErrorKind::NotFound => currency, number))
+        });
+        Box::new(future::result(res).map(stream::iter_ok).flatten_stream())
+    }
+}
+
+impl EthereumState {
+    fn block_number(&self) -> u64 {
+        self.block_number.unwrap_or_else(|| {
+            self.blocks
+                .values()
+                .filter_map(|blocks| blocks.keys().next_back().cloned())
+                .max()
+                .unwrap_or_default()
+        })
+    }
+
+    fn balance(&self, currency: Currency, address: &EthereumAddress) -> Amount {
+        self.balances
+            .get(&(currency, address.to_string()))
+            .cloned()
+            .unwrap_or(Amount::new(0))
+    }
+
+    fn transactions(&self, currency: Currency) -> impl Iterator<Item = &BlockchainTransaction> {
+        self.blocks
+            .get(&currency)
+            .into_iter()
+            .flat_map(|blocks| blocks.values())
+            .flat_map(|block| block.transactions.iter())
+    }
+
+    fn eth_transaction(&self, hash: &str) -> Option<BlockchainTransaction> {
+        self.transactions(Currency::Eth)
+            .chain(self.transactions.values())
+            .find(|tx| tx.hash == hash)
+            .cloned()
+    }
+}
+
+impl EthereumClient for EthereumClientMock {
+    fn get_nonce(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = u64, Error = Error> + Send> {
+        let res = self.answer("get_nonce", json!([address, block.to_string()]), |state| {
+            Ok(state.nonces.get(&address.to_string()).cloned().unwrap_or_default())
+        });
+        Box::new(future::result(res))
+    }
+
+    fn estimate_gas(&self, call: EthereumCall) -> Box<Future<Item = Result<Amount, ExecutionError>, Error = Error> + Send> {
+        let res = self.answer("estimate_gas", json!([call]), |state| {
+            Ok(state.gas_estimate.clone().unwrap_or(Ok(Amount::new(21_000))))
+        });
+        Box::new(future::result(res))
+    }
+
+    fn call(&self, call: EthereumCall, block: BlockTag) -> Box<Future<Item = Result<String, ExecutionError>, Error = Error> + Send> {
+        let res = self.answer("call", json!([call, block.to_string()]), |state| {
+            Ok(state.call_result.clone().unwrap_or(Ok("0x".to_string())))
+        });
+        Box::new(future::result(res))
+    }
+
+    fn send_raw_tx(&self, tx: RawEthereumTransaction) -> Box<Future<Item = TxHash, Error = Error> + Send> {
+        let res = self.answer("send_raw_tx", json!([tx]), |state| {
+            state
+                .sent_tx_hash
+                .clone()
+                .ok_or(ectx!(err ErrorSource::Server, ErrorKind::Internal => tx))
+        });
+        Box::new(future::result(res))
+    }
+
+    fn get_eth_transaction(&self, hash: String) -> Box<Future<Item = BlockchainTransaction, Error = Error> + Send> {
+        let res = self.answer("get_eth_transaction", json!([hash]), |state| {
+            state
+                .eth_transaction(&hash)
+                .ok_or(ectx!(err ErrorSource::Server, ErrorKind::NotFound => hash))
+        });
+        Box::new(future::result(res))
+    }
+
+    fn get_eth_transaction_status(&self, hash: String) -> Box<Future<Item = TransactionStatus, Error = Error> + Send> {
+        let res = self.answer("get_eth_transaction_status", json!([hash]), |state| {
+            match state.statuses.get(&hash) {
+                Some(status) => Ok(*status),
+                None => state
+                    .eth_transaction(&hash)
+                    .map(|tx| match tx.block_number {
+                        Some(_) => TransactionStatus::Success,
+                        None => TransactionStatus::Pending,
+                    })
+                    .ok_or(ectx!(err ErrorSource::Server, ErrorKind::NotFound => hash)),
+            }
+        });
+        Box::new(future::result(res))
+    }
+
+    fn get_eth_balance(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = Amount, Error = Error> + Send> {
+        self.balance("get_eth_balance", Currency::Eth, address, block)
+    }
+
+    fn last_eth_transactions(
+        &self,
+        start_block_hash: Option<String>,
+        blocks_count: u64,
+    ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        self.last_transactions("last_eth_transactions", Currency::Eth, start_block_hash, blocks_count)
+    }
+
+    fn last_eth_transactions_to(
+        &self,
+        current_block: u64,
+        blocks_count: u64,
+    ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        self.last_transactions_to("last_eth_transactions_to", Currency::Eth, current_block, blocks_count)
+    }
+
+    fn get_stq_transactions(&self, hash: String) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        let res = self.answer("get_stq_transactions", json!([hash]), |state| {
+            // hashes of stq transfers are `hash:index`
+            let prefix = format!("{}:", hash);
+            let txs: Vec<_> = state
+                .transactions(Currency::Stq)
+                .filter(|tx| tx.hash == hash || tx.hash.starts_with(&prefix))
+                .cloned()
+                .collect();
+            if txs.is_empty() {
+                Err(ectx!(err ErrorSource::Server, ErrorKind::NotFound => hash))
+            } else {
+                Ok(txs)
+            }
+        });
+        Box::new(future::result(res).map(stream::iter_ok).flatten_stream())
+    }
+
+    fn last_stq_transactions(
+        &self,
+        start_block_hash: Option<String>,
+        blocks_count: u64,
+    ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        self.last_transactions("last_stq_transactions", Currency::Stq, start_block_hash, blocks_count)
+    }
+
+    fn last_stq_transactions_to(
+        &self,
+        current_block: u64,
+        blocks_count: u64,
+    ) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        self.last_transactions_to("last_stq_transactions_to", Currency::Stq, current_block, blocks_count)
+    }
+
+    fn get_stq_balance(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = Amount, Error = Error> + Send> {
+        self.balance("get_stq_balance", Currency::Stq, address, block)
+    }
+
+    fn get_eth_balances(
+        &self,
+        addresses: Vec<EthereumAddress>,
+        block: BlockTag,
+    ) -> Box<Future<Item = Vec<Result<Amount, String>>, Error = Error> + Send> {
+        self.balances("get_eth_balances", Currency::Eth, addresses, block)
+    }
+
+    fn get_stq_balances(
+        &self,
+        addresses: Vec<EthereumAddress>,
+        block: BlockTag,
+    ) -> Box<Future<Item = Vec<Result<Amount, String>>, Error = Error> + Send> {
+        self.balances("get_stq_balances", Currency::Stq, addresses, block)
+    }
+
+    fn get_eth_block_transactions(&self, number: u64) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        self.block_transactions("get_eth_block_transactions", Currency::Eth, number)
+    }
+
+    fn get_stq_block_transactions(&self, number: u64) -> Box<Stream<Item = BlockchainTransaction, Error = Error> + Send> {
+        self.block_transactions("get_stq_block_transactions", Currency::Stq, number)
+    }
+
+    fn get_block_number(&self) -> Box<Future<Item = u64, Error = Error> + Send> {
+        let res = self.answer("get_block_number", json!([]), |state| Ok(state.block_number()));
+        Box::new(future::result(res))
+    }
+
+    fn get_node_status(&self) -> Box<Future<Item = NodeStatus, Error = Error> + Send> {
+        let res = self.answer("get_node_status", json!([]), |state| {
+            Ok(NodeStatus {
+                block_number: state.block_number(),
+                syncing: state.syncing,
+            })
+        });
+        Box::new(future::result(res))
+    }
+
+    fn get_chain_id(&self) -> Box<Future<Item = u64, Error = Error> + Send> {
+        let res = self.answer("get_chain_id", json!([]), |state| Ok(state.chain_id.unwrap_or(1337)));
+        Box::new(future::result(res))
+    }
+
+    fn get_code(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = String, Error = Error> + Send> {
+        let res = self.answer("get_code", json!([address, block.to_string()]), |state| {
+            Ok(state.codes.get(&address.to_string()).cloned().unwrap_or_else(|| "0x".to_string()))
+        });
+        Box::new(future::result(res))
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use hyper::{Body, Method, Request, Response};
+
+use client::{Error, ErrorKind, ErrorSource, HttpClient};
+use prelude::*;
+use utils::read_body;
+
+/// Fake of http client, answering requests with queued responses in order
+#[derive(Clone, Default)]
+pub struct HttpClientMock {
+    state: Arc<Mutex<HttpState>>,
+}
+
+#[derive(Default)]
+struct HttpState {
+    requests: Vec<RecordedRequest>,
+    responses: VecDeque<Result<(u16, String), ErrorKind>>,
+}
+
+/// Request received by `HttpClientMock`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub uri: String,
+    pub body: String,
+}
+
+impl HttpClientMock {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Queues response to request. Requests without queued response fail with `ErrorKind::NotFound`
+    pub fn push_response(&self, status: u16, body: &str) {
+        self.state.lock().unwrap().responses.push_back(Ok((status, body.to_string())));
+    }
+
+    /// Queues failure of request
+    pub fn push_error(&self, kind: ErrorKind) {
+        self.state.lock().unwrap().responses.push_back(Err(kind));
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl HttpClient for HttpClientMock {
+    fn request(&self, req: Request<Body>) -> Box<Future<Item = Response<Body>, Error = Error> + Send> {
+        let state = self.state.clone();
+        let (parts, body) = req.into_parts();
+        Box::new(
+            read_body(body)
+                .map_err(ectx!(ErrorSource::Hyper, ErrorKind::Internal))
+                .and_then(move |body| {
+                    let request = RecordedRequest {
+                        method: parts.method,
+                        uri: parts.uri.to_string(),
+                        body: String::from_utf8_lossy(&body).into_owned(),
+                    };
+                    let mut state = state.lock().unwrap();
+                    state.requests.push(request.clone());
+                    match state.responses.pop_front() {
+                        Some(Ok((status, body))) => Response::builder()
+                            .status(status)
+                            .body(Body::from(body))
+                            .map_err(ectx!(ErrorSource::Hyper, ErrorKind::Internal => status)),
+                        Some(Err(kind)) => Err(kind.into()),
+                        None => Err(ectx!(err ErrorSource::Server, ErrorKind::NotFound => request)),
+                    }
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::{BitcoinClient, BitcoinClientImpl};
+    use config::BitcoinNetwork;
+
+    #[test]
+    fn test_http_client_mock() {
+        let http_client = HttpClientMock::new();
+        http_client.push_response(
+            200,
+            r#"{"result": {"chain": "regtest", "blocks": 102, "bestblockhash": "b", "initialblockdownload": false}}"#,
+        );
+        let client = BitcoinClientImpl::new(
+            Arc::new(http_client.clone()),
+            BitcoinNetwork::Regtest,
+            "http://127.0.0.1:18443".to_string(),
+            "user".to_string(),
+            "password".to_string(),
+        );
+
+        assert_eq!(client.get_chain().wait().unwrap(), "regtest");
+        assert_eq!(
+            client.get_chain().wait().unwrap_err().kind(),
+            ErrorKind::NotFound,
+            "no queued response"
+        );
+        let requests = http_client.requests();
+        assert_eq!(
+            (requests[0].method.clone(), requests[0].uri.as_str()),
+            (Method::POST, "http://127.0.0.1:18443/")
+        );
+        assert!(requests[0].body.contains("getblockchaininfo"));
+    }
+}
@@ -0,0 +1,69 @@
+//! Fakes of clients and publisher for tests of code using the library, enabled with `test-utils` feature.
+//!
+//! Fakes are cheap to clone and clones share state, so that a test keeps a clone to script answers and check calls
+//! after passing another one as trait object. Methods answer with canned blocks, transactions and balances,
+//! any method can be scripted to fail with `fail`.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+mod bitcoin;
+mod ethereum;
+mod http_client;
+mod publisher;
+
+pub use self::bitcoin::*;
+pub use self::ethereum::*;
+pub use self::http_client::*;
+pub use self::publisher::*;
+
+/// Call of fake method with its arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub method: String,
+    pub args: Value,
+}
+
+/// Calls received by fake and failures scripted for its methods
+#[derive(Debug)]
+struct Script<K> {
+    calls: Vec<Call>,
+    failures: HashMap<String, K>,
+}
+
+impl<K: Copy> Script<K> {
+    /// Records call, returns failure if it's scripted for the method
+    fn call(&mut self, method: &str, args: Value) -> Option<K> {
+        self.calls.push(Call {
+            method: method.to_string(),
+            args,
+        });
+        self.failures.get(method).cloned()
+    }
+
+    fn fail(&mut self, method: &str, kind: K) {
+        self.failures.insert(method.to_string(), kind);
+    }
+
+    fn recover(&mut self, method: &str) {
+        self.failures.remove(method);
+    }
+
+    fn calls(&self, method: &str) -> Vec<Value> {
+        self.calls
+            .iter()
+            .filter(|call| call.method == method)
+            .map(|call| call.args.clone())
+            .collect()
+    }
+}
+
+impl<K> Default for Script<K> {
+    fn default() -> Self {
+        Script {
+            calls: Vec::new(),
+            failures: HashMap::new(),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+
+use super::Script;
+use models::*;
+use prelude::*;
+use rabbit::{Error, ErrorKind, TransactionPublisher};
+
+/// Fake of rabbit publisher, keeping published messages in memory
+#[derive(Clone, Default)]
+pub struct TransactionPublisherMock {
+    state: Arc<Mutex<PublisherState>>,
+}
+
+#[derive(Default)]
+struct PublisherState {
+    script: Script<ErrorKind>,
+    transactions: Vec<BlockchainTransaction>,
+    backfill_transactions: Vec<BlockchainTransaction>,
+    broadcast_statuses: Vec<Broadcast>,
+}
+
+impl TransactionPublisherMock {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Transactions published with `publish`
+    pub fn transactions(&self) -> Vec<BlockchainTransaction> {
+        self.state.lock().unwrap().transactions.clone()
+    }
+
+    /// Transactions published with `publish_backfill`
+    pub fn backfill_transactions(&self) -> Vec<BlockchainTransaction> {
+        self.state.lock().unwrap().backfill_transactions.clone()
+    }
+
+    pub fn broadcast_statuses(&self) -> Vec<Broadcast> {
+        self.state.lock().unwrap().broadcast_statuses.clone()
+    }
+
+    /// Calls of `method` fail with `kind` until it's recovered, failed messages are not kept
+    pub fn fail(&self, method: &str, kind: ErrorKind) {
+        self.state.lock().unwrap().script.fail(method, kind);
+    }
+
+    pub fn recover(&self, method: &str) {
+        self.state.lock().unwrap().script.recover(method);
+    }
+
+    /// Arguments of calls of `method`, including failed ones
+    pub fn calls(&self, method: &str) -> Vec<Value> {
+        self.state.lock().unwrap().script.calls(method)
+    }
+}
+
+impl TransactionPublisher for TransactionPublisherMock {
+    fn publish(&self, txs: Vec<BlockchainTransaction>) -> Box<Future<Item = (), Error = Error> + Send> {
+        let mut state = self.state.lock().unwrap();
+        let res = match state.script.call("publish", json!([txs])) {
+            Some(kind) => Err(kind.into()),
+            None => Ok(state.transactions.extend(txs)),
+        };
+        Box::new(res.into_future())
+    }
+
+    fn publish_backfill(&self, txs: Vec<BlockchainTransaction>) -> Box<Future<Item = (), Error = Error> + Send> {
+        let mut state = self.state.lock().unwrap();
+        let res = match state.script.call("publish_backfill", json!([txs])) {
+            Some(kind) => Err(kind.into()),
+            None => Ok(state.backfill_transactions.extend(txs)),
+        };
+        Box::new(res.into_future())
+    }
+
+    fn publish_broadcast_status(&self, broadcast: Broadcast) -> Box<Future<Item = (), Error = Error> + Send> {
+        let mut state = self.state.lock().unwrap();
+        let res = match state.script.call("publish_broadcast_status", json!([broadcast])) {
+            Some(kind) => Err(kind.into()),
+            None => Ok(state.broadcast_statuses.push(broadcast)),
+        };
+        Box::new(res.into_future())
+    }
+    fn check_channel(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        let mut state = self.state.lock().unwrap();
+        let res = match state.script.call("check_channel", json!([])) {
+            Some(kind) => Err(kind.into()),
+            None => Ok(()),
+        };
+        Box::new(res.into_future())
+    }
+}
@@ -23,6 +23,10 @@ impl FromStr for BitcoinAddress {
 pub struct EthereumAddress(String);
 
 impl EthereumAddress {
+    pub fn new(address: String) -> Self {
+        EthereumAddress(address)
+    }
+
     pub fn into_inner(self) -> String {
         self.0
     }
@@ -3,6 +3,8 @@ use std::fmt;
 use std::fmt::LowerHex;
 use std::mem::transmute;
 
+use schemars::JsonSchema;
+
 /// This is a wrapper for monetary amounts in blockchain.
 /// You have to be careful that it has a limited amount of 38 significant digits
 /// So make sure that total monetary supply of a coin (in satoshis, wei, etc) does not exceed that.
@@ -11,7 +13,8 @@ use std::mem::transmute;
 /// that your db contains only limited precision numbers, i.e. no floating point and limited by u128 values.
 ///
 /// As a monetary amount it only implements checked_add and checked_sub
-#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[schemars(description = "Amount in minimal units of currency, i.e. satoshis or wei")]
 pub struct Amount(u128);
 
 impl Amount {
@@ -53,6 +56,48 @@ impl Amount {
     pub fn new(val: u128) -> Self {
         Amount(val)
     }
+
+    /// Parse decimal representation of amount, i.e. `0.00012345` with 8 decimals is 12345 satoshis.
+    /// Returns None if value is not a non-negative decimal number, has more than `decimals`
+    /// fractional digits or overflows.
+    pub fn from_decimal_str(s: &str, decimals: u32) -> Option<Self> {
+        let mut parts = s.splitn(2, '.');
+        let int = parts.next()?;
+        let fract = parts.next().unwrap_or("");
+        let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if int.is_empty() || !is_digits(int) || !is_digits(fract) || fract.len() > decimals as usize {
+            return None;
+        }
+        if s.ends_with('.') {
+            return None;
+        }
+        let mut digits = format!("{}{}", int, fract);
+        for _ in fract.len()..decimals as usize {
+            digits.push('0');
+        }
+        let digits = digits.trim_start_matches('0');
+        if digits.is_empty() {
+            return Some(Amount(0));
+        }
+        digits.parse().ok().map(Amount)
+    }
+
+    /// Format amount as decimal number with `decimals` fractional digits, i.e. 12345 satoshis
+    /// with 8 decimals is `0.00012345`. Trailing zeros are omitted, so 1 BTC is `1`.
+    pub fn to_decimal_string(&self, decimals: u32) -> String {
+        let decimals = decimals as usize;
+        let mut digits = self.0.to_string();
+        while digits.len() <= decimals {
+            digits.insert(0, '0');
+        }
+        let (int, fract) = digits.split_at(digits.len() - decimals);
+        let fract = fract.trim_end_matches('0');
+        if fract.is_empty() {
+            int.to_string()
+        } else {
+            format!("{}.{}", int, fract)
+        }
+    }
 }
 
 impl Ord for Amount {
@@ -76,6 +121,123 @@ impl LowerHex for Amount {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::prng::XorShiftRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_amount(rng: &mut XorShiftRng) -> u128 {
+        let value = ((rng.gen::<u64>() as u128) << 64) | rng.gen::<u64>() as u128;
+        // spread values over all magnitudes, not only the largest ones
+        value >> rng.gen_range(0, 128)
+    }
+
+    #[test]
+    fn test_decimal_conversions() {
+        let cases = [
+            ("0", 8, 0u128),
+            ("0.00012345", 8, 12345),
+            ("1", 8, 100000000),
+            ("1.5", 8, 150000000),
+            ("21000000", 8, 2100000000000000),
+            ("0.000000000000000001", 18, 1),
+            ("1.000000000000000001", 18, 1000000000000000001),
+            ("340282366920938463463374607431768211455", 0, u128::max_value()),
+            ("3.40282366920938463463374607431768211455", 38, u128::max_value()),
+            ("0.0000000000000000000000000000000000000001", 40, 1),
+        ];
+        for case in cases.iter() {
+            let (string, decimals, number) = case.clone();
+            assert_eq!(Amount::from_decimal_str(string, decimals), Some(Amount(number)), "Case: {}", string);
+            assert_eq!(Amount(number).to_decimal_string(decimals), string, "Case: {}", string);
+        }
+        assert_eq!(Amount::from_decimal_str("0.10", 8), Some(Amount(10000000)));
+        assert_eq!(Amount::from_decimal_str("007", 0), Some(Amount(7)));
+    }
+
+    #[test]
+    fn test_decimal_error_conversions() {
+        let error_cases = [
+            ("", 8),
+            (".", 8),
+            (".1", 8),
+            ("1.", 8),
+            ("-1", 8),
+            ("+1", 8),
+            ("1e8", 8),
+            ("1.2.3", 8),
+            (" 1", 8),
+            ("0.123456789", 8),
+            ("0.1", 0),
+            // u128::max_value + 1
+            ("340282366920938463463374607431768211456", 0),
+            ("3.40282366920938463463374607431768211456", 38),
+            ("340282366920938463463374607431768211455", 1),
+        ];
+        for case in error_cases.iter() {
+            let (string, decimals) = case.clone();
+            assert_eq!(Amount::from_decimal_str(string, decimals), None, "Case: {}", string);
+        }
+    }
+
+    #[test]
+    fn test_decimal_round_trip_property() {
+        let mut rng = XorShiftRng::from_seed([7; 16]);
+        for decimals in 0..=40 {
+            for value in [0, 1, 9, 10, u64::max_value() as u128, u128::max_value() - 1, u128::max_value()].iter() {
+                let amount = Amount(*value);
+                let string = amount.to_decimal_string(decimals);
+                assert_eq!(
+                    Amount::from_decimal_str(&string, decimals),
+                    Some(amount),
+                    "Case: {} {}",
+                    string,
+                    decimals
+                );
+            }
+            for _ in 0..1000 {
+                let amount = Amount(random_amount(&mut rng));
+                let string = amount.to_decimal_string(decimals);
+                assert_eq!(
+                    Amount::from_decimal_str(&string, decimals),
+                    Some(amount),
+                    "Case: {} {}",
+                    string,
+                    decimals
+                );
+                // formatting is canonical: no leading or trailing zeros
+                assert!(!string.ends_with('0') || !string.contains('.'), "Case: {}", string);
+                assert!(
+                    !string.starts_with('0') || string == "0" || string.starts_with("0."),
+                    "Case: {}",
+                    string
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_decimal_parse_property() {
+        let mut rng = XorShiftRng::from_seed([13; 16]);
+        for _ in 0..10000 {
+            let decimals = rng.gen_range(0, 40);
+            let int: String = (0..rng.gen_range(1, 20)).map(|_| rng.gen_range(0, 10).to_string()).collect();
+            let fract: String = (0..rng.gen_range(0, decimals + 1))
+                .map(|_| rng.gen_range(0, 10).to_string())
+                .collect();
+            let significant_int_digits = int.trim_start_matches('0').len() as u32;
+            let string = if fract.is_empty() { int } else { format!("{}.{}", int, fract) };
+            match Amount::from_decimal_str(&string, decimals) {
+                Some(amount) => assert_eq!(
+                    Amount::from_decimal_str(&amount.to_decimal_string(decimals), decimals),
+                    Some(amount),
+                    "Case: {} {}",
+                    string,
+                    decimals
+                ),
+                // anything below 10^38 fits into u128, so parsing may only fail for larger values
+                None => assert!(significant_int_digits + decimals > 38, "Case: {} {}", string, decimals),
+            }
+        }
+    }
 
     #[test]
     fn test_serde_conversions() {
@@ -0,0 +1,81 @@
+use std::fmt::{self, Display};
+
+use chrono::{DateTime, Utc};
+
+use super::currency::Currency;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum BackfillStatus {
+    Running,
+    Completed,
+    /// Stopped by error, the next run of the range resumes from its checkpoint
+    Failed,
+    /// Stopped through admin API, the next run of the range resumes from its checkpoint
+    Cancelled,
+}
+
+impl Display for BackfillStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackfillStatus::Running => f.write_str("running"),
+            BackfillStatus::Completed => f.write_str("completed"),
+            BackfillStatus::Failed => f.write_str("failed"),
+            BackfillStatus::Cancelled => f.write_str("cancelled"),
+        }
+    }
+}
+
+/// Publishing of transactions of blocks from `from_height` to `to_height` inclusive, also used as its checkpoint
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Backfill {
+    /// `{currency}-{from_height}-{to_height}`, runs of the same range share checkpoint
+    pub id: String,
+    pub currency: Currency,
+    pub from_height: u64,
+    pub to_height: u64,
+    /// Transactions of blocks below this height are published
+    pub next_height: u64,
+    pub published_transactions: u64,
+    pub status: BackfillStatus,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Backfill {
+    pub fn new(currency: Currency, from_height: u64, to_height: u64) -> Self {
+        let now = Utc::now();
+        Backfill {
+            id: Self::id(currency, from_height, to_height),
+            currency,
+            from_height,
+            to_height,
+            next_height: from_height,
+            published_transactions: 0,
+            status: BackfillStatus::Running,
+            error: None,
+            started_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn id(currency: Currency, from_height: u64, to_height: u64) -> String {
+        format!("{}-{}-{}", currency, from_height, to_height)
+    }
+
+    /// Continues run stopped halfway, completed run starts over
+    pub fn resume(self) -> Self {
+        if self.status == BackfillStatus::Completed {
+            return Self::new(self.currency, self.from_height, self.to_height);
+        }
+        Backfill {
+            status: BackfillStatus::Running,
+            error: None,
+            started_at: Utc::now(),
+            updated_at: Utc::now(),
+            ..self
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use super::amount::Amount;
+
+/// Balance of address in a batch, `Err` with a message if it couldn't be queried
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressBalance {
+    pub address: String,
+    pub balance: Result<Amount, String>,
+}
+
+/// Balances of many addresses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Balances {
+    /// Block all balances are queried at, ethereum only
+    pub block_number: Option<u64>,
+    pub balances: Vec<AddressBalance>,
+}
@@ -0,0 +1,86 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer};
+
+use super::currency::Currency;
+use super::transaction::BlockchainTransaction;
+
+/// Block which state is queried from node
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BlockTag {
+    /// The latest mined block
+    Latest,
+    /// The latest mined block with transactions from node's mempool applied on top of it
+    Pending,
+    /// Block at height
+    Number(u64),
+}
+
+impl Default for BlockTag {
+    fn default() -> Self {
+        BlockTag::Latest
+    }
+}
+
+/// Formatted as JSON-RPC block parameter
+impl Display for BlockTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockTag::Latest => f.write_str("latest"),
+            BlockTag::Pending => f.write_str("pending"),
+            BlockTag::Number(number) => write!(f, "0x{:x}", number),
+        }
+    }
+}
+
+impl FromStr for BlockTag {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "latest" => Ok(BlockTag::Latest),
+            "pending" => Ok(BlockTag::Pending),
+            number => number.parse().map(BlockTag::Number).map_err(|_| ()),
+        }
+    }
+}
+
+/// Either `latest`, `pending` or block number, as integer or decimal string
+impl<'de> Deserialize<'de> for BlockTag {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RawBlockTag {
+            Number(u64),
+            Tag(String),
+        }
+        match RawBlockTag::deserialize(deserializer)? {
+            RawBlockTag::Number(number) => Ok(BlockTag::Number(number)),
+            RawBlockTag::Tag(tag) => tag
+                .parse()
+                .map_err(|_| ::serde::de::Error::custom(format!("invalid block: {}", tag))),
+        }
+    }
+}
+
+/// The latest block known by node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainTip {
+    pub height: u64,
+    pub hash: String,
+}
+
+/// Header of block scanned by poller, with transactions published from it
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScannedBlock {
+    pub currency: Currency,
+    pub height: u64,
+    pub hash: String,
+    pub time: Option<DateTime<Utc>>,
+    pub transactions: Vec<BlockchainTransaction>,
+}
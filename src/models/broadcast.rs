@@ -0,0 +1,105 @@
+use std::fmt::{self, Display};
+
+use chrono::{DateTime, Utc};
+
+use super::address::EthereumAddress;
+use super::currency::Currency;
+use super::transaction::*;
+
+/// Status of a transaction posted through the gateway
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum BroadcastStatus {
+    /// Transaction is in mempool or is being rebroadcast after it fell out of it
+    Pending,
+    /// Transaction is included in a block
+    Mined,
+    /// Transaction was missing from mempool after max number of rebroadcasts
+    Dropped,
+    /// Another transaction spending the same outputs or nonce was mined
+    Replaced,
+}
+
+impl BroadcastStatus {
+    /// Transactions in final status are no longer tracked
+    pub fn is_final(&self) -> bool {
+        *self != BroadcastStatus::Pending
+    }
+}
+
+impl Display for BroadcastStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BroadcastStatus::Pending => f.write_str("pending"),
+            BroadcastStatus::Mined => f.write_str("mined"),
+            BroadcastStatus::Dropped => f.write_str("dropped"),
+            BroadcastStatus::Replaced => f.write_str("replaced"),
+        }
+    }
+}
+
+/// Signed transaction, with what it spends, so that its replacement can be detected
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum BroadcastTransaction {
+    Bitcoin {
+        raw: RawBitcoinTransaction,
+        inputs: Vec<BitcoinTransactionInput>,
+    },
+    Ethereum {
+        raw: RawEthereumTransaction,
+        from: EthereumAddress,
+        nonce: u64,
+    },
+}
+
+/// Transaction posted through the gateway and tracked until it's mined, dropped or replaced
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Broadcast {
+    pub hash: TxHash,
+    pub currency: Currency,
+    pub status: BroadcastStatus,
+    /// Transaction is pending for longer than expected, most likely because of low fee
+    pub stuck: bool,
+    /// Number of times transaction was sent again after it fell out of mempool
+    pub rebroadcasts: u32,
+    pub submitted_at: DateTime<Utc>,
+    /// Time of the last change of status or stuck flag
+    pub updated_at: DateTime<Utc>,
+    #[serde(skip)]
+    pub transaction: BroadcastTransaction,
+}
+
+impl Broadcast {
+    pub fn bitcoin(raw: RawBitcoinTransaction, tx: &DecodedBitcoinTransaction) -> Self {
+        let transaction = BroadcastTransaction::Bitcoin {
+            raw,
+            inputs: tx.inputs.clone(),
+        };
+        Self::new(tx.txid.clone(), Currency::Btc, transaction)
+    }
+
+    pub fn ethereum(raw: RawEthereumTransaction, tx: &DecodedEthereumTransaction) -> Self {
+        let transaction = BroadcastTransaction::Ethereum {
+            raw,
+            from: tx.from.clone(),
+            nonce: tx.nonce,
+        };
+        Self::new(tx.hash.clone(), Currency::Eth, transaction)
+    }
+
+    fn new(hash: TxHash, currency: Currency, transaction: BroadcastTransaction) -> Self {
+        let now = Utc::now();
+        Broadcast {
+            hash,
+            currency,
+            status: BroadcastStatus::Pending,
+            stuck: false,
+            rebroadcasts: 0,
+            submitted_at: now,
+            updated_at: now,
+            transaction,
+        }
+    }
+}
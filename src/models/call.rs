@@ -0,0 +1,31 @@
+use super::address::EthereumAddress;
+use super::amount::Amount;
+
+/// Message call executed by node without creating a transaction
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EthereumCall {
+    pub from: Option<EthereumAddress>,
+    /// `None` for contract deployments
+    pub to: Option<EthereumAddress>,
+    pub value: Option<Amount>,
+    /// Hex encoded call data
+    pub data: Option<String>,
+}
+
+/// Call rejected by node, e.g. because its execution reverted or it runs out of gas
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ExecutionError {
+    /// JSON-RPC error code
+    pub code: i64,
+    pub message: String,
+}
+
+/// Gas estimate of ERC-20 transfer with its call data, so that signer can use both
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Erc20TransferEstimate {
+    pub gas: Amount,
+    /// Hex encoded call data of `transfer(address,uint256)`
+    pub data: String,
+}
@@ -1,6 +1,10 @@
 use std::fmt::{self, Display};
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+use schemars::JsonSchema;
+
+use super::amount::Amount;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum Currency {
     Eth,
@@ -17,3 +21,94 @@ impl Display for Currency {
         }
     }
 }
+
+impl Currency {
+    /// Ticker used in human readable amounts, e.g. `0.00012345 BTC`
+    pub fn ticker(&self) -> &'static str {
+        match self {
+            Currency::Eth => "ETH",
+            Currency::Stq => "STQ",
+            Currency::Btc => "BTC",
+        }
+    }
+
+    pub fn from_ticker(ticker: &str) -> Option<Self> {
+        match ticker.to_uppercase().as_ref() {
+            "ETH" => Some(Currency::Eth),
+            "STQ" => Some(Currency::Stq),
+            "BTC" => Some(Currency::Btc),
+            _ => None,
+        }
+    }
+}
+
+/// Number of decimal places of each currency, i.e. 1 BTC = 10^8 satoshis.
+/// Decimals of native coins are fixed, decimals of ERC-20 tokens are defined by contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CurrencyDecimals {
+    pub stq: u32,
+}
+
+impl CurrencyDecimals {
+    pub const BTC: u32 = 8;
+    pub const ETH: u32 = 18;
+
+    pub fn new(stq: u32) -> Self {
+        CurrencyDecimals { stq }
+    }
+
+    pub fn get(&self, currency: Currency) -> u32 {
+        match currency {
+            Currency::Btc => Self::BTC,
+            Currency::Eth => Self::ETH,
+            Currency::Stq => self.stq,
+        }
+    }
+
+    /// Format amount in minimal units as e.g. `0.00012345 BTC`
+    pub fn format(&self, amount: Amount, currency: Currency) -> String {
+        format!("{} {}", amount.to_decimal_string(self.get(currency)), currency.ticker())
+    }
+
+    /// Parse amount in format of `format`, e.g. `0.00012345 BTC`, into minimal units
+    pub fn parse(&self, s: &str) -> Option<(Amount, Currency)> {
+        let mut parts = s.split(' ');
+        let value = parts.next()?;
+        let currency = Currency::from_ticker(parts.next()?)?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Amount::from_decimal_str(value, self.get(currency)).map(|amount| (amount, currency))
+    }
+}
+
+impl Default for CurrencyDecimals {
+    fn default() -> Self {
+        CurrencyDecimals { stq: 18 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_and_parse() {
+        let decimals = CurrencyDecimals::new(6);
+        let cases = [
+            ("0.00012345 BTC", Amount::new(12345), Currency::Btc),
+            ("1 ETH", Amount::new(1000000000000000000), Currency::Eth),
+            ("1.5 STQ", Amount::new(1500000), Currency::Stq),
+        ];
+        for case in cases.iter() {
+            let (string, amount, currency) = case.clone();
+            assert_eq!(decimals.format(amount, currency), string);
+            assert_eq!(decimals.parse(string), Some((amount, currency)));
+        }
+        assert_eq!(decimals.parse("0.00012345 btc"), Some((Amount::new(12345), Currency::Btc)));
+        assert_eq!(decimals.parse("0.000000001 BTC"), None);
+        assert_eq!(decimals.parse("1 XRP"), None);
+        assert_eq!(decimals.parse("1"), None);
+        assert_eq!(decimals.parse("1 BTC BTC"), None);
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+/// State of blockchain node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeStatus {
+    pub block_number: u64,
+    /// Node is still catching up with the network, so its data is outdated
+    pub syncing: bool,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+/// Result of checking a single dependency
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyHealth {
+    pub status: HealthStatus,
+    /// Service is not ready if any critical dependency is down
+    pub critical: bool,
+    pub details: Value,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: BTreeMap<String, DependencyHealth>,
+}
+
+impl Readiness {
+    pub fn new(checks: BTreeMap<String, DependencyHealth>) -> Self {
+        let ready = checks.values().all(|check| !check.critical || check.status == HealthStatus::Up);
+        Readiness { ready, checks }
+    }
+}
@@ -1,11 +1,25 @@
 mod address;
 mod amount;
+mod backfill;
+mod balance;
+mod block;
+mod broadcast;
+mod call;
 mod currency;
+mod health;
+mod nonce;
 mod transaction;
 mod utxo;
 
 pub use self::address::*;
 pub use self::amount::*;
+pub use self::backfill::*;
+pub use self::balance::*;
+pub use self::block::*;
+pub use self::broadcast::*;
+pub use self::call::*;
 pub use self::currency::*;
+pub use self::health::*;
+pub use self::nonce::*;
 pub use self::transaction::*;
 pub use self::utxo::*;
@@ -0,0 +1,21 @@
+use super::address::EthereumAddress;
+
+/// Nonces of address tracked by gateway, compared with chain state
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NonceState {
+    pub address: EthereumAddress,
+    /// Number of mined transactions of the address
+    pub latest: u64,
+    /// Number of transactions of the address, including the ones in node's mempool
+    pub pending: u64,
+    /// Nonce handed out by the next reservation
+    pub next: u64,
+    /// Nonces handed out, but not yet used by posted transactions
+    pub reserved: Vec<u64>,
+    /// Nonces of transactions posted through API, that are not yet mined
+    pub posted: Vec<u64>,
+    /// Nonces below `next` that are neither in node's mempool, nor reserved or posted.
+    /// Transactions with higher nonces are not mined until these are used
+    pub gaps: Vec<u64>,
+}
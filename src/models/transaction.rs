@@ -1,5 +1,10 @@
 use std::fmt::{self, Display};
+use std::str::FromStr;
 
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+
+use super::address::EthereumAddress;
 use super::amount::Amount;
 use super::currency::Currency;
 
@@ -23,7 +28,7 @@ impl Display for RawEthereumTransaction {
     }
 }
 
-/// Hex encoded hash of a transaction
+/// Hex encoded hash of a transaction, in lower case and without `0x` prefix
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct TxHash(String);
 
@@ -31,20 +36,156 @@ impl TxHash {
     pub fn new(hash: String) -> Self {
         TxHash(hash)
     }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl Display for TxHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Parses 32 hex encoded bytes with optional `0x` prefix
+impl FromStr for TxHash {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = if s.starts_with("0x") || s.starts_with("0X") { &s[2..] } else { s };
+        if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(());
+        }
+        Ok(TxHash(hex.to_lowercase()))
+    }
+}
+
+/// Envelope of signed ethereum transaction
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum EthereumTransactionType {
+    Legacy,
+    /// Typed transaction with access list, type `0x01`
+    Eip2930,
+    /// Typed transaction with priority fee, type `0x02`
+    Eip1559,
+}
+
+/// Ethereum transaction decoded from its raw signed form, before it's sent to blockchain
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedEthereumTransaction {
+    pub hash: TxHash,
+    #[serde(rename = "type")]
+    pub tx_type: EthereumTransactionType,
+    /// `None` for legacy transactions signed without replay protection
+    pub chain_id: Option<u64>,
+    pub nonce: u64,
+    /// Recovered from signature
+    pub from: EthereumAddress,
+    /// `None` for contract deployments
+    pub to: Option<EthereumAddress>,
+    pub value: Amount,
+    pub gas_limit: Amount,
+    /// Only set for legacy and EIP-2930 transactions
+    pub gas_price: Option<Amount>,
+    /// Only set for EIP-1559 transactions
+    pub max_fee_per_gas: Option<Amount>,
+    /// Only set for EIP-1559 transactions
+    pub max_priority_fee_per_gas: Option<Amount>,
+    /// Hex encoded call data
+    pub data: String,
+}
+
+impl DecodedEthereumTransaction {
+    /// Max amount of wei the sender can be charged, i.e. value + gas limit * max gas price. None on overflow
+    pub fn max_cost(&self) -> Option<Amount> {
+        let gas_price = self.max_fee_per_gas.or(self.gas_price).unwrap_or(Amount::new(0));
+        self.gas_limit.checked_mul(gas_price).and_then(|fee| fee.checked_add(self.value))
+    }
+}
+
+/// Bitcoin transaction decoded from its raw form, before it's sent to blockchain
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedBitcoinTransaction {
+    pub txid: TxHash,
+    /// Hash including witness data, equal to `txid` for non segwit transactions
+    pub wtxid: String,
+    pub version: i32,
+    pub locktime: u32,
+    /// Size in bytes
+    pub size: usize,
+    /// Size in virtual bytes, i.e. weight / 4 rounded up
+    pub vsize: usize,
+    pub weight: usize,
+    pub inputs: Vec<BitcoinTransactionInput>,
+    pub outputs: Vec<BitcoinTransactionOutput>,
+    /// Sum of inputs minus sum of outputs, `None` until values of spent outputs are fetched
+    pub fee: Option<Amount>,
+    /// Satoshis per virtual byte, `None` until values of spent outputs are fetched
+    pub fee_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BitcoinTransactionInput {
+    /// Transaction of spent output
+    pub txid: String,
+    /// Index of spent output
+    pub index: u32,
+    pub sequence: u32,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BitcoinTransactionOutput {
+    pub value: Amount,
+    /// Hex encoded locking script
+    pub script_pub_key: String,
+}
+
+impl BitcoinTransactionOutput {
+    /// `OP_RETURN` outputs carry data and are provably unspendable, so they are exempt from dust limits
+    pub fn is_null_data(&self) -> bool {
+        self.script_pub_key.starts_with("6a")
+    }
+}
+
+/// Result of checking whether node would accept transaction to its mempool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolAcceptance {
+    pub allowed: bool,
+    pub reject_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct BlockchainTransactionEntry {
     pub address: String,
     pub value: Amount,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Erc20OperationKind {
     Approve,
     TransferFrom,
+    /// Transfer from the zero address, i.e. new tokens were issued
+    Mint,
+    /// Transfer to the zero address, i.e. tokens were destroyed
+    Burn,
+}
+
+/// Execution status of a transaction
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionStatus {
+    /// Transaction is not yet included in a block
+    Pending,
+    Success,
+    /// Transaction is included in a block, but was reverted
+    Failed,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -53,9 +194,24 @@ pub struct BlockchainTransaction {
     pub hash: String,
     pub from: Vec<String>,
     pub to: Vec<BlockchainTransactionEntry>,
-    pub block_number: u64,
+    /// `None` for transactions that are not yet included in a block
+    pub block_number: Option<u64>,
+    /// `None` for transactions that are not yet included in a block
+    pub block_hash: Option<String>,
+    /// `None` for transactions that are not yet included in a block
+    pub block_time: Option<DateTime<Utc>>,
     pub currency: Currency,
     pub fee: Amount,
     pub confirmations: usize,
     pub erc20_operation_kind: Option<Erc20OperationKind>,
+    /// Ethereum transaction that created a contract. In this case `to` contains the address of the created contract
+    pub contract_deployment: bool,
+}
+
+/// Transactions of address from history index
+#[derive(Debug, Clone)]
+pub struct TransactionsPage {
+    pub transactions: Vec<BlockchainTransaction>,
+    /// Hex encoded position of the next page, `None` if this page is the last one
+    pub next_cursor: Option<String>,
 }
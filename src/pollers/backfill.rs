@@ -0,0 +1,284 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::stream;
+
+use super::error::*;
+use client::{BitcoinClient, EthereumClient};
+use config;
+use history::HistoryIndex;
+use models::*;
+use prelude::*;
+use rabbit::TransactionPublisher;
+use serde_json;
+use tokio::timer::Interval;
+use utils::log_error;
+
+/// Backfills started since the start of the process, shared with backfill service
+#[derive(Debug, Clone, Default)]
+pub struct BackfillRegistry {
+    backfills: Arc<Mutex<HashMap<String, Backfill>>>,
+    /// Running backfills that stop before the next block
+    cancelled: Arc<Mutex<HashSet<String>>>,
+}
+
+impl BackfillRegistry {
+    /// Registers backfill, returns false if the same range is already running
+    pub fn start(&self, backfill: Backfill) -> bool {
+        let mut backfills = self.backfills.lock().unwrap();
+        if backfills.get(&backfill.id).map(|b| b.status) == Some(BackfillStatus::Running) {
+            return false;
+        }
+        self.cancelled.lock().unwrap().remove(&backfill.id);
+        backfills.insert(backfill.id.clone(), backfill);
+        true
+    }
+
+    pub fn get(&self, id: &str) -> Option<Backfill> {
+        self.backfills.lock().unwrap().get(id).cloned()
+    }
+
+    /// Backfills, the most recently started first
+    pub fn list(&self) -> Vec<Backfill> {
+        let mut backfills: Vec<_> = self.backfills.lock().unwrap().values().cloned().collect();
+        backfills.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+        backfills
+    }
+
+    pub fn update(&self, backfill: Backfill) {
+        self.backfills.lock().unwrap().insert(backfill.id.clone(), backfill);
+    }
+
+    /// Stops running backfill once blocks already fetched are published, it stays running until then
+    pub fn cancel(&self, id: &str) -> Option<Backfill> {
+        let backfill = self.get(id)?;
+        if backfill.status == BackfillStatus::Running {
+            self.cancelled.lock().unwrap().insert(id.to_string());
+        }
+        Some(backfill)
+    }
+
+    fn is_cancelled(&self, id: &str) -> bool {
+        self.cancelled.lock().unwrap().contains(id)
+    }
+}
+
+/// Publishes transactions of a range of blocks again with backfill routing key, writing checkpoint after each block
+#[derive(Clone)]
+pub struct BackfillRunner {
+    config: config::Backfill,
+    bitcoin_client: Arc<BitcoinClient>,
+    ethereum_client: Arc<EthereumClient>,
+    publisher: Arc<TransactionPublisher>,
+    registry: BackfillRegistry,
+    /// Index of transactions by address, filled with published transactions if it's configured
+    history: Option<Arc<HistoryIndex>>,
+}
+
+impl BackfillRunner {
+    pub fn new(
+        config: config::Backfill,
+        bitcoin_client: Arc<BitcoinClient>,
+        ethereum_client: Arc<EthereumClient>,
+        publisher: Arc<TransactionPublisher>,
+        registry: BackfillRegistry,
+        history: Option<Arc<HistoryIndex>>,
+    ) -> Self {
+        Self {
+            config,
+            bitcoin_client,
+            ethereum_client,
+            publisher,
+            registry,
+            history,
+        }
+    }
+
+    /// Height of the latest block of currency, blocks above it can't be backfilled
+    pub fn head(&self, currency: Currency) -> impl Future<Item = u64, Error = Error> + Send {
+        let status = match currency {
+            Currency::Btc => self.bitcoin_client.get_node_status(),
+            Currency::Eth | Currency::Stq => self.ethereum_client.get_node_status(),
+        };
+        status
+            .map(|status| status.block_number)
+            .map_err(ectx!(ErrorSource::Client, ErrorKind::Internal => currency))
+    }
+
+    /// Registers backfill of range, resuming it from its checkpoint if the previous run stopped halfway.
+    /// Returns `None` if the range is already running
+    pub fn prepare(&self, currency: Currency, from_height: u64, to_height: u64) -> Result<Option<Backfill>, Error> {
+        let id = Backfill::id(currency, from_height, to_height);
+        let backfill = match self.read_checkpoint(&id)? {
+            Some(checkpoint) => checkpoint.resume(),
+            None => Backfill::new(currency, from_height, to_height),
+        };
+        if !self.registry.start(backfill.clone()) {
+            return Ok(None);
+        }
+        self.write_checkpoint(&backfill)?;
+        Ok(Some(backfill))
+    }
+
+    /// Publishes transactions of blocks from `backfill.next_height` to `backfill.to_height` in order.
+    /// Blocks are fetched with bounded concurrency and started no faster than configured rate
+    pub fn run(&self, backfill: Backfill) -> impl Future<Item = Backfill, Error = Error> + Send {
+        let self_clone = self.clone();
+        let self_clone2 = self.clone();
+        let self_clone3 = self.clone();
+        let registry = self.registry.clone();
+        let id = backfill.id.clone();
+        let currency = backfill.currency;
+        let heights = stream::iter_ok::<_, Error>(backfill.next_height..=backfill.to_height);
+        let heights: Box<Stream<Item = u64, Error = Error> + Send> = if self.config.blocks_per_sec > 0.0 {
+            let period = Duration::from_nanos((1_000_000_000.0 / self.config.blocks_per_sec) as u64);
+            let ticks = Interval::new_interval(period).map_err(ectx!(ErrorSource::Timer, ErrorKind::Internal));
+            Box::new(heights.zip(ticks).map(|(height, _)| height))
+        } else {
+            Box::new(heights)
+        };
+        let backfill_clone = backfill.clone();
+        heights
+            .take_while(move |_| Ok(!registry.is_cancelled(&id)))
+            .map(move |height| self_clone.block_transactions(currency, height).map(move |txs| (height, txs)))
+            .buffered(self.config.concurrency.max(1))
+            .fold(backfill, move |backfill, (height, txs)| {
+                self_clone2.publish_block(backfill, height, txs)
+            })
+            .then(move |res| self_clone3.finish(backfill_clone, res))
+    }
+
+    fn block_transactions(&self, currency: Currency, height: u64) -> impl Future<Item = Vec<BlockchainTransaction>, Error = Error> + Send {
+        let txs = match currency {
+            Currency::Btc => self.bitcoin_client.get_block_transactions(height),
+            Currency::Eth => self.ethereum_client.get_eth_block_transactions(height),
+            Currency::Stq => self.ethereum_client.get_stq_block_transactions(height),
+        };
+        txs.collect()
+            .map_err(ectx!(ErrorSource::Client, ErrorKind::Internal => currency, height))
+    }
+
+    fn publish_block(
+        &self,
+        backfill: Backfill,
+        height: u64,
+        txs: Vec<BlockchainTransaction>,
+    ) -> impl Future<Item = Backfill, Error = Error> + Send {
+        let self_clone = self.clone();
+        let count = txs.len() as u64;
+        let txs_clone = txs.clone();
+        let id = backfill.id.clone();
+        self.publisher
+            .publish_backfill(txs.clone())
+            .map_err(ectx!(ErrorSource::Publisher, ErrorKind::Internal => id, height))
+            .and_then(move |_| {
+                if let Some(ref history) = self_clone.history {
+                    for tx in &txs_clone {
+                        // index lagging behind is not worth stopping the backfill
+                        if let Err(e) = history.index(tx) {
+                            let e: Error = ectx!(err e, ErrorSource::History, ErrorKind::Internal => tx.hash);
+                            log_error(&e);
+                        }
+                    }
+                }
+                let backfill = Backfill {
+                    next_height: height + 1,
+                    published_transactions: backfill.published_transactions + count,
+                    updated_at: Utc::now(),
+                    ..backfill
+                };
+                self_clone.registry.update(backfill.clone());
+                self_clone.write_checkpoint(&backfill).map(|_| backfill)
+            })
+    }
+
+    /// Records final status of run, so that stopped run can be resumed
+    fn finish(&self, backfill: Backfill, res: Result<Backfill, Error>) -> Result<Backfill, Error> {
+        let (mut finished, error) = match res {
+            Ok(finished) => (finished, None),
+            Err(e) => {
+                let current = self.registry.get(&backfill.id).unwrap_or(backfill);
+                (current, Some(e))
+            }
+        };
+        finished.status = match error {
+            Some(_) => BackfillStatus::Failed,
+            None if finished.next_height > finished.to_height => BackfillStatus::Completed,
+            None => BackfillStatus::Cancelled,
+        };
+        finished.error = error.as_ref().map(|e| e.to_string());
+        finished.updated_at = Utc::now();
+        self.registry.update(finished.clone());
+        self.write_checkpoint(&finished)?;
+        match error {
+            Some(e) => Err(e),
+            None => Ok(finished),
+        }
+    }
+
+    fn checkpoint_path(&self, id: &str) -> PathBuf {
+        PathBuf::from(&self.config.checkpoint_dir).join(format!("{}.json", id))
+    }
+
+    fn read_checkpoint(&self, id: &str) -> Result<Option<Backfill>, Error> {
+        let path = self.checkpoint_path(id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let path_clone = path.clone();
+        let bytes = fs::read(&path).map_err(ectx!(try ErrorContext::Checkpoint, ErrorKind::Internal => path))?;
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(ectx!(ErrorContext::Checkpoint, ErrorKind::Internal => path_clone))
+    }
+
+    /// Writes checkpoint to temporary file first, so that crash doesn't leave it half written
+    fn write_checkpoint(&self, backfill: &Backfill) -> Result<(), Error> {
+        let path = self.checkpoint_path(&backfill.id);
+        let tmp_path = path.with_extension("json.tmp");
+        let (path_clone, tmp_path_clone) = (path.clone(), tmp_path.clone());
+        fs::create_dir_all(&self.config.checkpoint_dir)
+            .map_err(ectx!(try ErrorContext::Checkpoint, ErrorKind::Internal => self.config.checkpoint_dir.clone()))?;
+        let bytes =
+            serde_json::to_vec_pretty(backfill).map_err(ectx!(try ErrorContext::Checkpoint, ErrorKind::Internal => backfill.id.clone()))?;
+        fs::write(&tmp_path, bytes).map_err(ectx!(try ErrorContext::Checkpoint, ErrorKind::Internal => tmp_path))?;
+        fs::rename(&tmp_path_clone, &path).map_err(ectx!(ErrorContext::Checkpoint, ErrorKind::Internal => path_clone))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let registry = BackfillRegistry::default();
+        let backfill = Backfill::new(Currency::Btc, 100, 200);
+        assert!(registry.start(backfill.clone()));
+        assert!(!registry.start(backfill.clone()), "range is already running");
+
+        registry.cancel(&backfill.id);
+        assert!(registry.is_cancelled(&backfill.id));
+        let cancelled = Backfill {
+            next_height: 150,
+            status: BackfillStatus::Cancelled,
+            ..backfill.clone()
+        };
+        registry.update(cancelled.clone());
+
+        let resumed = cancelled.resume();
+        assert_eq!((resumed.status, resumed.next_height), (BackfillStatus::Running, 150));
+        assert!(registry.start(resumed));
+        assert!(!registry.is_cancelled(&backfill.id));
+        let completed = Backfill {
+            status: BackfillStatus::Completed,
+            next_height: 201,
+            ..backfill
+        };
+        assert_eq!(completed.resume().next_height, 100, "completed range starts over");
+    }
+}
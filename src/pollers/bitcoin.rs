@@ -1,10 +1,19 @@
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use futures::stream;
+
+use super::broadcasts::BroadcastRegistry;
 use super::error::*;
-use client::BitcoinClient;
+use super::persistence;
+use super::status::PollerStatus;
+use client::{BitcoinClient, Error as ClientError};
+use history::HistoryIndex;
+use metrics::{self, POLLER_CHAIN_TIP, POLLER_LAG_BLOCKS, POLLER_TICK_DURATION};
+use models::*;
 use prelude::*;
 use rabbit::TransactionPublisher;
+use storage::Storage;
 use tokio;
 use tokio::timer::Interval;
 use utils::log_error;
@@ -15,6 +24,15 @@ pub struct BitcoinPollerService {
     client: Arc<BitcoinClient>,
     publisher: Arc<TransactionPublisher>,
     number_of_tracked_confirmations: usize,
+    /// Max number of blocks mined while poller was stopped, that are scanned by one tick after restart
+    max_catch_up_blocks: usize,
+    status: PollerStatus,
+    /// Index of transactions by address, filled with published transactions if it's configured
+    history: Option<Arc<HistoryIndex>>,
+    /// Database of published transactions and poller cursors, if it's configured
+    storage: Option<Arc<Storage>>,
+    /// Transactions posted through API, found in scanned blocks if broadcast tracker runs
+    broadcasts: Option<BroadcastRegistry>,
 }
 
 impl BitcoinPollerService {
@@ -23,16 +41,27 @@ impl BitcoinPollerService {
         client: Arc<BitcoinClient>,
         publisher: Arc<TransactionPublisher>,
         number_of_tracked_confirmations: usize,
+        max_catch_up_blocks: usize,
+        status: PollerStatus,
+        history: Option<Arc<HistoryIndex>>,
+        storage: Option<Arc<Storage>>,
+        broadcasts: Option<BroadcastRegistry>,
     ) -> Self {
         Self {
             interval,
             client,
             publisher,
             number_of_tracked_confirmations,
+            max_catch_up_blocks,
+            status,
+            history,
+            storage,
+            broadcasts,
         }
     }
 
     pub fn start(&self) -> impl Future<Item = (), Error = ()> {
+        self.status.register(Currency::Btc, self.interval);
         let self_clone = self.clone();
         let interval = Interval::new_interval(self.interval).for_each(move |_| {
             self_clone.tick();
@@ -45,21 +74,110 @@ impl BitcoinPollerService {
         &self,
         start_block_hash: Option<String>,
         blocks_count: u64,
-    ) -> impl Future<Item = (), Error = Error> + Send {
-        let publisher = self.publisher.clone();
-        self.client
+    ) -> impl Future<Item = Option<u64>, Error = Error> + Send {
+        let txs = self
+            .client
             .last_transactions(start_block_hash.clone(), blocks_count.clone())
-            .map_err(ectx!(ErrorSource::Client, ErrorKind::Internal => start_block_hash, blocks_count))
-            .and_then(move |tx| {
-                publisher
-                    .publish(vec![tx.clone()])
-                    .map_err(ectx!(ErrorSource::Publisher, ErrorKind::Internal => tx))
-            })
-            .for_each(|_| Ok(()))
+            .map_err(ectx!(ErrorSource::Client, ErrorKind::Internal => start_block_hash, blocks_count));
+        self.publish(txs)
+    }
+
+    /// Publishes scanned transactions, resolves to the latest block with published transactions
+    fn publish<S>(&self, txs: S) -> impl Future<Item = Option<u64>, Error = Error> + Send
+    where
+        S: Stream<Item = BlockchainTransaction, Error = Error> + Send,
+    {
+        let publisher = self.publisher.clone();
+        let history = self.history.clone();
+        let storage = self.storage.clone();
+        let broadcasts = self.broadcasts.clone();
+        txs.and_then(move |tx| {
+            let block_number = tx.block_number;
+            if let Some(ref broadcasts) = broadcasts {
+                broadcasts.record_mined(&tx.hash);
+            }
+            let history = history.clone();
+            let storage = storage.clone();
+            let tx_clone = tx.clone();
+            let tx_clone2 = tx.clone();
+            publisher
+                .publish(vec![tx.clone()])
+                .map_err(ectx!(ErrorSource::Publisher, ErrorKind::Internal => tx_clone))
+                .and_then(move |_| persistence::save_transaction(&storage, tx_clone2))
+                .map(move |_| {
+                    if let Some(history) = history {
+                        // index lagging behind is not worth stopping the poller
+                        if let Err(e) = history.index(&tx) {
+                            let e: Error = ectx!(err e, ErrorSource::History, ErrorKind::Internal => tx);
+                            log_error(&e);
+                        }
+                    }
+                    block_number
+                })
+        })
+        // the latest block with published transactions
+        .fold(None, |last_block, block_number| Ok::<_, Error>(last_block.max(block_number)))
     }
 
     fn tick(&self) {
-        let f = self.publish_transactions(None, self.number_of_tracked_confirmations as u64);
+        let self_clone = self.clone();
+        let status = self.status.clone();
+        let started_at = Instant::now();
+        let currency = Currency::Btc.to_string();
+        let currency_clone = currency.clone();
+        let f = self
+            .client
+            .get_chain_tip()
+            .map_err(ectx!(ErrorSource::Client, ErrorKind::Internal))
+            .and_then(move |tip| {
+                let head = tip.height;
+                POLLER_CHAIN_TIP.with_label_values(&[&currency]).set(head as i64);
+                persistence::blocks_to_scan(
+                    &self_clone.storage,
+                    Currency::Btc,
+                    head,
+                    self_clone.number_of_tracked_confirmations as u64,
+                    self_clone.max_catch_up_blocks as u64,
+                )
+                .and_then(move |(scanned_to, blocks_count)| {
+                    let broadcasts = self_clone.broadcasts.clone();
+                    let txs: Box<Stream<Item = BlockchainTransaction, Error = ClientError> + Send> = if scanned_to == head {
+                        self_clone.client.last_transactions(Some(tip.hash), blocks_count)
+                    } else {
+                        // catching up after restart, blocks are walked by height as the hash of the last one is unknown
+                        let client = self_clone.client.clone();
+                        let heights = (scanned_to + 1).saturating_sub(blocks_count)..(scanned_to + 1);
+                        Box::new(
+                            stream::iter_ok::<_, ClientError>(heights)
+                                .map(move |height| client.get_block_transactions(height))
+                                .flatten(),
+                        )
+                    };
+                    let txs = txs.map_err(ectx!(ErrorSource::Client, ErrorKind::Internal => scanned_to, blocks_count));
+                    self_clone
+                        .publish(txs)
+                        .map(move |last_block| {
+                            if let Some(last_block) = last_block {
+                                POLLER_LAG_BLOCKS
+                                    .with_label_values(&[&currency])
+                                    .set(head.saturating_sub(last_block) as i64);
+                            }
+                            if let Some(ref broadcasts) = broadcasts {
+                                broadcasts.record_scanned(scanned_to);
+                            }
+                        })
+                        .and_then(move |_| persistence::save_cursor(&self_clone.storage, Currency::Btc, scanned_to))
+                })
+            })
+            .then(move |res| {
+                POLLER_TICK_DURATION
+                    .with_label_values(&[&currency_clone, metrics::result_label(&res)])
+                    .observe(metrics::duration_secs(started_at));
+                if res.is_ok() {
+                    status.record_success(Currency::Btc);
+                }
+                res
+            });
         tokio::spawn(f.map_err(|e| {
             log_error(&e);
         }));
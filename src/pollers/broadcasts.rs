@@ -0,0 +1,490 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{self, DateTime, Utc};
+use futures::future::{self, Either};
+use futures::stream;
+
+use super::error::*;
+use super::persistence;
+use client::{BitcoinClient, ErrorKind as ClientErrorKind, EthereumClient};
+use config::BroadcastTracker;
+use metrics::{self, BROADCAST_STATUS_CHANGES, REBROADCASTS};
+use models::*;
+use prelude::*;
+use rabbit::TransactionPublisher;
+use storage::Storage;
+use tokio;
+use tokio::timer::Interval;
+use utils::{log_error, log_warn};
+
+/// Max number of tracked transactions checked at once
+const CONCURRENT_CHECKS: usize = 8;
+
+/// Bitcoin blocks scanned by poller. Node finds transactions out of mempool only with `-txindex`,
+/// so mined transactions are found in blocks instead
+#[derive(Debug, Default)]
+struct ScannedBlocks {
+    /// The last block scanned by poller since start, blocks before it are scanned as well
+    height: Option<u64>,
+    /// Hashes of tracked transactions found in blocks scanned since start. The ones found before restart
+    /// are among transactions saved to storage
+    mined: HashSet<String>,
+}
+
+/// Transactions posted through API, shared with broadcast service and bitcoin poller.
+/// They are saved to storage if it's configured, so that they are tracked after restart
+#[derive(Clone, Default)]
+pub struct BroadcastRegistry {
+    broadcasts: Arc<Mutex<HashMap<String, Broadcast>>>,
+    scanned: Arc<Mutex<ScannedBlocks>>,
+    storage: Option<Arc<Storage>>,
+}
+
+impl BroadcastRegistry {
+    pub fn new(storage: Option<Arc<Storage>>) -> Self {
+        Self {
+            storage,
+            ..Default::default()
+        }
+    }
+
+    /// Loads transactions saved before restart, except for the ones that reached final status before `time`
+    pub fn load(&self, time: DateTime<Utc>) -> Box<Future<Item = (), Error = Error> + Send> {
+        let storage = match self.storage {
+            Some(ref storage) => storage.clone(),
+            None => return Box::new(future::ok(())),
+        };
+        let broadcasts = self.broadcasts.clone();
+        Box::new(
+            storage
+                .get_broadcasts(time)
+                .map_err(ectx!(ErrorSource::Storage, ErrorKind::Internal => time))
+                .map(move |loaded| {
+                    let mut broadcasts = broadcasts.lock().unwrap();
+                    for broadcast in loaded {
+                        broadcasts.insert(broadcast.hash.to_string(), broadcast);
+                    }
+                }),
+        )
+    }
+
+    /// Starts tracking transaction, resolves to `false` if it's already tracked.
+    /// Transaction posted again keeps its original state
+    pub fn track(&self, broadcast: Broadcast) -> Box<Future<Item = bool, Error = Error> + Send> {
+        {
+            let mut broadcasts = self.broadcasts.lock().unwrap();
+            if broadcasts.contains_key(&broadcast.hash.to_string()) {
+                return Box::new(future::ok(false));
+            }
+            broadcasts.insert(broadcast.hash.to_string(), broadcast.clone());
+        }
+        Box::new(persistence::save_broadcast(&self.storage, broadcast).map(|_| true))
+    }
+
+    pub fn get(&self, hash: &TxHash) -> Option<Broadcast> {
+        self.broadcasts.lock().unwrap().get(&hash.to_string()).cloned()
+    }
+
+    pub fn update(&self, broadcast: Broadcast) -> Box<Future<Item = (), Error = Error> + Send> {
+        self.broadcasts
+            .lock()
+            .unwrap()
+            .insert(broadcast.hash.to_string(), broadcast.clone());
+        persistence::save_broadcast(&self.storage, broadcast)
+    }
+
+    pub fn pending(&self) -> Vec<Broadcast> {
+        self.broadcasts
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|broadcast| !broadcast.status.is_final())
+            .cloned()
+            .collect()
+    }
+
+    /// Forgets transactions that reached final status before `time`
+    pub fn purge(&self, time: DateTime<Utc>) -> Box<Future<Item = (), Error = Error> + Send> {
+        {
+            let mut broadcasts = self.broadcasts.lock().unwrap();
+            broadcasts.retain(|_, broadcast| !broadcast.status.is_final() || broadcast.updated_at >= time);
+            self.scanned
+                .lock()
+                .unwrap()
+                .mined
+                .retain(|hash| broadcasts.get(hash).map_or(false, |broadcast| !broadcast.status.is_final()));
+        }
+        persistence::delete_broadcasts(&self.storage, time)
+    }
+
+    /// Records transaction found in a bitcoin block scanned by poller, if it's tracked
+    pub fn record_mined(&self, hash: &str) {
+        if self.broadcasts.lock().unwrap().contains_key(hash) {
+            self.scanned.lock().unwrap().mined.insert(hash.to_string());
+        }
+    }
+
+    /// Records that poller scanned bitcoin blocks up to `height`, transactions found in them are recorded before
+    pub fn record_scanned(&self, height: u64) {
+        self.scanned.lock().unwrap().height = Some(height);
+    }
+
+    fn is_mined(&self, hash: &TxHash) -> bool {
+        self.scanned.lock().unwrap().mined.contains(&hash.to_string())
+    }
+
+    /// Whether bitcoin transaction is found in a scanned block, including the blocks scanned before restart
+    fn find_mined(&self, hash: &TxHash) -> Box<Future<Item = bool, Error = Error> + Send> {
+        if self.is_mined(hash) {
+            return Box::new(future::ok(true));
+        }
+        let hash = hash.to_string();
+        match self.storage {
+            Some(ref storage) => Box::new(
+                storage
+                    .has_transaction(Currency::Btc, hash.clone())
+                    .map_err(ectx!(ErrorSource::Storage, ErrorKind::Internal => hash)),
+            ),
+            None => Box::new(future::ok(false)),
+        }
+    }
+
+    fn scanned_height(&self) -> Option<u64> {
+        self.scanned.lock().unwrap().height
+    }
+}
+
+/// State of tracked transaction as seen by node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Observation {
+    Mined,
+    InMempool,
+    /// Node doesn't know the transaction, but another one spent its outputs or nonce
+    Replaced,
+    /// Node doesn't know the transaction, it was evicted from mempool or didn't propagate
+    Missing,
+    /// Outputs spent by the transaction are spent in a block that poller didn't scan yet
+    Unresolved,
+}
+
+/// Polls node for transactions posted through API until they are mined, rebroadcasts them
+/// if they fall out of mempool and publishes their status changes
+#[derive(Clone)]
+pub struct BroadcastTrackerService {
+    config: BroadcastTracker,
+    bitcoin_client: Arc<BitcoinClient>,
+    ethereum_client: Arc<EthereumClient>,
+    publisher: Arc<TransactionPublisher>,
+    registry: BroadcastRegistry,
+}
+
+impl BroadcastTrackerService {
+    pub fn new(
+        config: BroadcastTracker,
+        bitcoin_client: Arc<BitcoinClient>,
+        ethereum_client: Arc<EthereumClient>,
+        publisher: Arc<TransactionPublisher>,
+        registry: BroadcastRegistry,
+    ) -> Self {
+        Self {
+            config,
+            bitcoin_client,
+            ethereum_client,
+            publisher,
+            registry,
+        }
+    }
+
+    pub fn start(&self) -> impl Future<Item = (), Error = ()> {
+        let self_clone = self.clone();
+        let interval = Interval::new_interval(Duration::from_secs(self.config.interval_secs)).for_each(move |_| {
+            self_clone.tick();
+            Ok(())
+        });
+        interval.map_err(|_| ())
+    }
+
+    /// Starts tracking transaction posted through API and publishes its pending status
+    pub fn track(&self, broadcast: Broadcast) -> Box<Future<Item = (), Error = Error> + Send> {
+        let self_clone = self.clone();
+        Box::new(self.registry.track(broadcast.clone()).and_then(move |tracked| {
+            if tracked {
+                Either::A(self_clone.publish_status(broadcast))
+            } else {
+                Either::B(future::ok(()))
+            }
+        }))
+    }
+
+    fn tick(&self) {
+        tokio::spawn(self.check_pending().map_err(|e| {
+            log_error(&e);
+        }));
+    }
+
+    /// Checks pending transactions once and forgets the ones that reached final status before retention period.
+    /// Failed checks of single transactions are only logged
+    pub fn check_pending(&self) -> impl Future<Item = (), Error = Error> + Send {
+        let retention = chrono::Duration::seconds(self.config.retention_secs as i64);
+        let pending = self.registry.pending();
+        // a single snapshot of mempool is shared by checks of all bitcoin transactions
+        let mempool = if pending.iter().any(|broadcast| broadcast.currency == Currency::Btc) {
+            Either::A(
+                self.bitcoin_client
+                    .get_mempool_txids()
+                    .map(|txids| txids.into_iter().collect::<HashSet<_>>())
+                    .map_err(ectx!(ErrorSource::Client, ErrorKind::Internal)),
+            )
+        } else {
+            Either::B(future::ok(HashSet::new()))
+        };
+        let self_clone = self.clone();
+        self.registry
+            .purge(Utc::now() - retention)
+            .and_then(move |_| mempool)
+            .and_then(move |mempool| {
+                let mempool = Arc::new(mempool);
+                stream::iter_ok::<_, Error>(pending)
+                    .map(move |broadcast| {
+                        self_clone.check(broadcast, mempool.clone()).then(|res| {
+                            if let Err(e) = res {
+                                log_error(&e);
+                            }
+                            Ok(())
+                        })
+                    })
+                    .buffer_unordered(CONCURRENT_CHECKS)
+                    .for_each(|_| Ok(()))
+            })
+    }
+
+    fn check(&self, broadcast: Broadcast, mempool: Arc<HashSet<String>>) -> impl Future<Item = (), Error = Error> + Send {
+        let self_clone = self.clone();
+        self.observe(&broadcast, &mempool).and_then(move |observation| {
+            let mut broadcast_clone = broadcast.clone();
+            let rebroadcast = transition(&mut broadcast_clone, observation, Utc::now(), &self_clone.config);
+            let rebroadcast_f = if rebroadcast {
+                Either::A(self_clone.rebroadcast(&broadcast_clone))
+            } else {
+                Either::B(future::ok(()))
+            };
+            rebroadcast_f.and_then(move |_| {
+                let status_changed = (broadcast.status, broadcast.stuck) != (broadcast_clone.status, broadcast_clone.stuck);
+                if !status_changed && broadcast.rebroadcasts == broadcast_clone.rebroadcasts {
+                    return Either::A(future::ok(()));
+                }
+                let publish_f = if status_changed {
+                    Either::A(self_clone.publish_status(broadcast_clone.clone()))
+                } else {
+                    Either::B(future::ok(()))
+                };
+                Either::B(self_clone.registry.update(broadcast_clone).and_then(move |_| publish_f))
+            })
+        })
+    }
+
+    fn publish_status(&self, broadcast: Broadcast) -> impl Future<Item = (), Error = Error> + Send {
+        let status = if broadcast.stuck && !broadcast.status.is_final() {
+            "stuck".to_string()
+        } else {
+            broadcast.status.to_string()
+        };
+        BROADCAST_STATUS_CHANGES
+            .with_label_values(&[&broadcast.currency.to_string(), &status])
+            .inc();
+        self.publisher
+            .publish_broadcast_status(broadcast.clone())
+            .map_err(ectx!(ErrorSource::Publisher, ErrorKind::Internal => broadcast))
+    }
+
+    fn observe(&self, broadcast: &Broadcast, mempool: &HashSet<String>) -> Box<Future<Item = Observation, Error = Error> + Send> {
+        let hash = broadcast.hash.to_string();
+        let hash_clone = hash.clone();
+        match broadcast.transaction {
+            BroadcastTransaction::Bitcoin { ref inputs, .. } => {
+                if self.registry.is_mined(&broadcast.hash) {
+                    return Box::new(future::ok(Observation::Mined));
+                }
+                if mempool.contains(&hash) {
+                    return Box::new(future::ok(Observation::InMempool));
+                }
+                let client = self.bitcoin_client.clone();
+                let registry = self.registry.clone();
+                let tx_hash = broadcast.hash.clone();
+                // only spends in blocks count, transaction conflicting in mempool is not a replacement until it's mined
+                let outputs = inputs
+                    .iter()
+                    .map(|input| self.bitcoin_client.get_unspent_output_value(input.txid.clone(), input.index, false))
+                    .collect::<Vec<_>>();
+                Box::new(
+                    future::join_all(outputs)
+                        .and_then(move |values| {
+                            if values.iter().all(Option::is_some) {
+                                return Either::A(future::ok(None));
+                            }
+                            Either::B(client.get_chain_tip().map(Some))
+                        })
+                        .map_err(ectx!(ErrorSource::Client, ErrorKind::Internal => hash_clone))
+                        .and_then(move |tip| {
+                            let tip = match tip {
+                                Some(tip) => tip,
+                                None => return Either::A(future::ok(Observation::Missing)),
+                            };
+                            // the block spending outputs is at most the chain tip fetched after that, so the transaction
+                            // is replaced only if poller scanned up to the tip without finding it
+                            if registry.scanned_height().map_or(false, |height| height >= tip.height) {
+                                Either::B(registry.find_mined(&tx_hash).map(
+                                    |mined| {
+                                        if mined {
+                                            Observation::Mined
+                                        } else {
+                                            Observation::Replaced
+                                        }
+                                    },
+                                ))
+                            } else if registry.is_mined(&tx_hash) {
+                                Either::A(future::ok(Observation::Mined))
+                            } else {
+                                Either::A(future::ok(Observation::Unresolved))
+                            }
+                        }),
+                )
+            }
+            BroadcastTransaction::Ethereum { ref from, nonce, .. } => {
+                let client = self.ethereum_client.clone();
+                let from = from.clone();
+                let hash_clone2 = hash.clone();
+                Box::new(
+                    self.ethereum_client
+                        .get_eth_transaction_status(hash)
+                        .then(move |res| match res {
+                            Ok(TransactionStatus::Pending) => Either::A(future::ok(Observation::InMempool)),
+                            Ok(_) => Either::A(future::ok(Observation::Mined)),
+                            Err(ref e) if e.kind() == ClientErrorKind::NotFound => {
+                                let client_clone = client.clone();
+                                Either::B(client.get_nonce(from, BlockTag::Latest).and_then(move |next_nonce| {
+                                    if next_nonce <= nonce {
+                                        return Either::A(future::ok(Observation::Missing));
+                                    }
+                                    // the transaction could be mined after its status was fetched
+                                    Either::B(client_clone.get_eth_transaction_status(hash_clone2).then(|res| match res {
+                                        Ok(TransactionStatus::Pending) => Ok(Observation::InMempool),
+                                        Ok(_) => Ok(Observation::Mined),
+                                        Err(ref e) if e.kind() == ClientErrorKind::NotFound => Ok(Observation::Replaced),
+                                        Err(e) => Err(e),
+                                    }))
+                                }))
+                            }
+                            Err(e) => Either::A(future::err(e)),
+                        })
+                        .map_err(ectx!(ErrorSource::Client, ErrorKind::Internal => hash_clone)),
+                )
+            }
+        }
+    }
+
+    /// Sends transaction again. Failures are only logged, transaction is dropped after max number of attempts
+    fn rebroadcast(&self, broadcast: &Broadcast) -> Box<Future<Item = (), Error = Error> + Send> {
+        let currency = broadcast.currency.to_string();
+        let sent = match broadcast.transaction {
+            BroadcastTransaction::Bitcoin { ref raw, .. } => self.bitcoin_client.send_raw_tx(raw.clone()),
+            BroadcastTransaction::Ethereum { ref raw, .. } => self.ethereum_client.send_raw_tx(raw.clone()),
+        };
+        let hash = broadcast.hash.clone();
+        Box::new(sent.then(move |res| {
+            REBROADCASTS.with_label_values(&[&currency, metrics::result_label(&res)]).inc();
+            if let Err(e) = res {
+                let e: Error = ectx!(err e, ErrorSource::Client, ErrorKind::Internal => hash);
+                log_warn(&e);
+            }
+            Ok(())
+        }))
+    }
+}
+
+/// Applies observation to tracked transaction, returns true if the transaction has to be sent again
+fn transition(broadcast: &mut Broadcast, observation: Observation, now: DateTime<Utc>, config: &BroadcastTracker) -> bool {
+    let previous = (broadcast.status, broadcast.stuck);
+    let mut rebroadcast = false;
+    match observation {
+        Observation::Mined => broadcast.status = BroadcastStatus::Mined,
+        Observation::Replaced => broadcast.status = BroadcastStatus::Replaced,
+        Observation::InMempool => {
+            let stuck_after = chrono::Duration::seconds(config.stuck_after_secs as i64);
+            broadcast.stuck = now.signed_duration_since(broadcast.submitted_at) > stuck_after;
+        }
+        Observation::Missing if broadcast.rebroadcasts >= config.max_rebroadcasts => broadcast.status = BroadcastStatus::Dropped,
+        Observation::Missing => {
+            broadcast.rebroadcasts += 1;
+            rebroadcast = true;
+        }
+        Observation::Unresolved => (),
+    }
+    if (broadcast.status, broadcast.stuck) != previous {
+        broadcast.updated_at = now;
+    }
+    rebroadcast
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn test_transition() {
+        let config = BroadcastTracker {
+            stuck_after_secs: 600,
+            max_rebroadcasts: 1,
+            ..Default::default()
+        };
+        let raw: RawEthereumTransaction = serde_json::from_value(json!("f86b")).unwrap();
+        let mut broadcast = Broadcast {
+            hash: TxHash::new("5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060".to_string()),
+            currency: Currency::Eth,
+            status: BroadcastStatus::Pending,
+            stuck: false,
+            rebroadcasts: 0,
+            submitted_at: Utc::now(),
+            updated_at: Utc::now(),
+            transaction: BroadcastTransaction::Ethereum {
+                raw,
+                from: EthereumAddress::new("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f".to_string()),
+                nonce: 0,
+            },
+        };
+        let submitted_at = broadcast.submitted_at;
+
+        assert!(!transition(
+            &mut broadcast,
+            Observation::InMempool,
+            submitted_at + chrono::Duration::seconds(60),
+            &config
+        ));
+        assert_eq!((broadcast.status, broadcast.stuck), (BroadcastStatus::Pending, false));
+        assert!(!transition(
+            &mut broadcast,
+            Observation::InMempool,
+            submitted_at + chrono::Duration::seconds(601),
+            &config
+        ));
+        assert_eq!((broadcast.status, broadcast.stuck), (BroadcastStatus::Pending, true));
+        assert_eq!(broadcast.updated_at, submitted_at + chrono::Duration::seconds(601));
+
+        assert!(transition(&mut broadcast, Observation::Missing, submitted_at, &config));
+        assert_eq!((broadcast.status, broadcast.rebroadcasts), (BroadcastStatus::Pending, 1));
+        assert!(!transition(&mut broadcast, Observation::Missing, submitted_at, &config));
+        assert_eq!((broadcast.status, broadcast.rebroadcasts), (BroadcastStatus::Dropped, 1));
+
+        let mut replaced = broadcast.clone();
+        replaced.status = BroadcastStatus::Pending;
+        assert!(!transition(&mut replaced, Observation::Unresolved, submitted_at, &config));
+        assert_eq!((replaced.status, replaced.rebroadcasts), (BroadcastStatus::Pending, 1));
+        assert!(!transition(&mut replaced, Observation::Replaced, submitted_at, &config));
+        assert_eq!(replaced.status, BroadcastStatus::Replaced);
+        assert!(!transition(&mut broadcast, Observation::Mined, submitted_at, &config));
+        assert_eq!(broadcast.status, BroadcastStatus::Mined);
+    }
+}
@@ -24,6 +24,12 @@ pub enum ErrorSource {
     Client,
     #[fail(display = "service error source - rabbit publisher")]
     Publisher,
+    #[fail(display = "service error source - history index")]
+    History,
+    #[fail(display = "service error source - tokio timer")]
+    Timer,
+    #[fail(display = "service error source - storage")]
+    Storage,
 }
 
 #[allow(dead_code)]
@@ -37,6 +43,8 @@ pub enum ErrorContext {
     NoWallet,
     #[fail(display = "service error context - signing transaction")]
     SigningTransaction,
+    #[fail(display = "service error context - reading or writing backfill checkpoint")]
+    Checkpoint,
 }
 
 derive_error_impls!();
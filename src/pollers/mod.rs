@@ -1,9 +1,16 @@
+mod backfill;
 mod bitcoin;
+mod broadcasts;
 mod error;
 mod ethereum;
+mod persistence;
+mod status;
 mod storiqa;
 
+pub use self::backfill::*;
 pub use self::bitcoin::*;
+pub use self::broadcasts::*;
 pub use self::error::*;
 pub use self::ethereum::*;
+pub use self::status::*;
 pub use self::storiqa::*;
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use futures::future;
+
+use super::error::*;
+use models::*;
+use prelude::*;
+use storage::Storage;
+use utils::log_error;
+
+/// Blocks scanned by tick, as the last one and number of blocks up to it. Normally these are the latest
+/// `number_of_tracked_confirmations` blocks. After restart blocks mined since the saved cursor are scanned too,
+/// at most `max_catch_up_blocks` of them per tick, so that poller catches up in several ticks without gaps
+pub fn blocks_to_scan(
+    storage: &Option<Arc<Storage>>,
+    currency: Currency,
+    head: u64,
+    number_of_tracked_confirmations: u64,
+    max_catch_up_blocks: u64,
+) -> Box<Future<Item = (u64, u64), Error = Error> + Send> {
+    let storage = match storage {
+        Some(storage) => storage,
+        None => return Box::new(future::ok((head, number_of_tracked_confirmations))),
+    };
+    let max_catch_up_blocks = max_catch_up_blocks.max(1);
+    Box::new(storage.get_cursor(currency).then(move |res| {
+        let cursor = res.unwrap_or_else(|e| {
+            let e: Error = ectx!(err e, ErrorSource::Storage, ErrorKind::Internal => currency);
+            log_error(&e);
+            None
+        });
+        let (last_block, missed) = match cursor {
+            Some(cursor) if head.saturating_sub(cursor) > max_catch_up_blocks => {
+                let last_block = cursor + max_catch_up_blocks;
+                warn!(
+                    "{} poller is {} blocks behind, catching up with blocks {} to {}",
+                    currency,
+                    head - cursor,
+                    cursor + 1,
+                    last_block
+                );
+                (last_block, max_catch_up_blocks)
+            }
+            Some(cursor) => (head, head.saturating_sub(cursor)),
+            None => (head, 0),
+        };
+        Ok((last_block, number_of_tracked_confirmations.max(missed)))
+    }))
+}
+
+/// Saves published transaction, storage lagging behind is not worth stopping the poller
+pub fn save_transaction(storage: &Option<Arc<Storage>>, tx: BlockchainTransaction) -> Box<Future<Item = (), Error = Error> + Send> {
+    let storage = match storage {
+        Some(storage) => storage,
+        None => return Box::new(future::ok(())),
+    };
+    let hash = tx.hash.clone();
+    Box::new(storage.save_transactions(vec![tx]).then(move |res| {
+        if let Err(e) = res {
+            let e: Error = ectx!(err e, ErrorSource::Storage, ErrorKind::Internal => hash);
+            log_error(&e);
+        }
+        Ok(())
+    }))
+}
+
+/// Saves the latest block scanned by poller, so that blocks mined while it's stopped are scanned after restart
+pub fn save_cursor(storage: &Option<Arc<Storage>>, currency: Currency, block_number: u64) -> Box<Future<Item = (), Error = Error> + Send> {
+    let storage = match storage {
+        Some(storage) => storage,
+        None => return Box::new(future::ok(())),
+    };
+    Box::new(storage.set_cursor(currency, block_number).then(move |res| {
+        if let Err(e) = res {
+            let e: Error = ectx!(err e, ErrorSource::Storage, ErrorKind::Internal => currency, block_number);
+            log_error(&e);
+        }
+        Ok(())
+    }))
+}
+
+/// Saves tracked transaction, so that it's tracked again after restart. Until then it's tracked in memory,
+/// so failure is only logged
+pub fn save_broadcast(storage: &Option<Arc<Storage>>, broadcast: Broadcast) -> Box<Future<Item = (), Error = Error> + Send> {
+    let storage = match storage {
+        Some(storage) => storage,
+        None => return Box::new(future::ok(())),
+    };
+    let hash = broadcast.hash.clone();
+    Box::new(storage.save_broadcast(broadcast).then(move |res| {
+        if let Err(e) = res {
+            let e: Error = ectx!(err e, ErrorSource::Storage, ErrorKind::Internal => hash);
+            log_error(&e);
+        }
+        Ok(())
+    }))
+}
+
+/// Deletes tracked transactions that reached final status before `time`, they are deleted again by the next purge
+pub fn delete_broadcasts(storage: &Option<Arc<Storage>>, time: DateTime<Utc>) -> Box<Future<Item = (), Error = Error> + Send> {
+    let storage = match storage {
+        Some(storage) => storage,
+        None => return Box::new(future::ok(())),
+    };
+    Box::new(storage.delete_broadcasts(time).then(move |res| {
+        if let Err(e) = res {
+            let e: Error = ectx!(err e, ErrorSource::Storage, ErrorKind::Internal => time);
+            log_error(&e);
+        }
+        Ok(())
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Database;
+    use storage::DbStorage;
+
+    #[test]
+    fn test_blocks_to_scan() {
+        let storage = DbStorage::connect(&Database {
+            url: "sqlite://:memory:".to_string(),
+            thread_pool_size: 1,
+        })
+        .unwrap();
+        let storage = Some(Arc::new(storage) as Arc<Storage>);
+        assert_eq!(blocks_to_scan(&None, Currency::Btc, 500, 2, 100).wait().unwrap(), (500, 2));
+        assert_eq!(
+            blocks_to_scan(&storage, Currency::Btc, 500, 2, 100).wait().unwrap(),
+            (500, 2),
+            "no cursor yet"
+        );
+
+        storage.as_ref().unwrap().set_cursor(Currency::Btc, 450).wait().unwrap();
+        assert_eq!(blocks_to_scan(&storage, Currency::Btc, 500, 2, 100).wait().unwrap(), (500, 50));
+        assert_eq!(blocks_to_scan(&storage, Currency::Btc, 451, 2, 100).wait().unwrap(), (451, 2));
+
+        storage.as_ref().unwrap().set_cursor(Currency::Btc, 10).wait().unwrap();
+        assert_eq!(
+            blocks_to_scan(&storage, Currency::Btc, 500, 2, 100).wait().unwrap(),
+            (110, 100),
+            "blocks after the first 100 missed ones are scanned by the next ticks"
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use models::*;
+
+/// State of a running poller
+#[derive(Debug, Clone)]
+pub struct PollerState {
+    pub interval: Duration,
+    pub started_at: DateTime<Utc>,
+    /// Time of the last tick that published all transactions without errors
+    pub last_success: Option<DateTime<Utc>>,
+}
+
+/// Tracks ticks of running pollers, shared with readiness checks
+#[derive(Debug, Clone, Default)]
+pub struct PollerStatus {
+    pollers: Arc<Mutex<HashMap<Currency, PollerState>>>,
+}
+
+impl PollerStatus {
+    pub fn register(&self, currency: Currency, interval: Duration) {
+        let state = PollerState {
+            interval,
+            started_at: Utc::now(),
+            last_success: None,
+        };
+        self.pollers.lock().unwrap().insert(currency, state);
+    }
+
+    pub fn record_success(&self, currency: Currency) {
+        if let Some(state) = self.pollers.lock().unwrap().get_mut(&currency) {
+            state.last_success = Some(Utc::now());
+        }
+    }
+
+    pub fn pollers(&self) -> Vec<(Currency, PollerState)> {
+        let mut pollers: Vec<_> = self
+            .pollers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(currency, state)| (*currency, state.clone()))
+            .collect();
+        pollers.sort_by_key(|(currency, _)| currency.to_string());
+        pollers
+    }
+}
@@ -1,10 +1,16 @@
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use super::error::*;
+use super::persistence;
+use super::status::PollerStatus;
 use client::EthereumClient;
+use history::HistoryIndex;
+use metrics::{self, POLLER_CHAIN_TIP, POLLER_LAG_BLOCKS, POLLER_TICK_DURATION};
+use models::*;
 use prelude::*;
 use rabbit::TransactionPublisher;
+use storage::Storage;
 use tokio;
 use tokio::timer::Interval;
 use utils::log_error;
@@ -15,6 +21,13 @@ pub struct StoriqaPollerService {
     client: Arc<EthereumClient>,
     publisher: Arc<TransactionPublisher>,
     number_of_tracked_confirmations: usize,
+    /// Max number of blocks mined while poller was stopped, that are scanned by one tick after restart
+    max_catch_up_blocks: usize,
+    status: PollerStatus,
+    /// Index of transactions by address, filled with published transactions if it's configured
+    history: Option<Arc<HistoryIndex>>,
+    /// Database of published transactions and poller cursors, if it's configured
+    storage: Option<Arc<Storage>>,
 }
 
 impl StoriqaPollerService {
@@ -23,16 +36,25 @@ impl StoriqaPollerService {
         client: Arc<EthereumClient>,
         publisher: Arc<TransactionPublisher>,
         number_of_tracked_confirmations: usize,
+        max_catch_up_blocks: usize,
+        status: PollerStatus,
+        history: Option<Arc<HistoryIndex>>,
+        storage: Option<Arc<Storage>>,
     ) -> Self {
         Self {
             interval,
             client,
             publisher,
             number_of_tracked_confirmations,
+            max_catch_up_blocks,
+            status,
+            history,
+            storage,
         }
     }
 
     pub fn start(&self) -> impl Future<Item = (), Error = ()> {
+        self.status.register(Currency::Stq, self.interval);
         let self_clone = self.clone();
         let interval = Interval::new_interval(self.interval).for_each(move |_| {
             self_clone.tick();
@@ -45,21 +67,92 @@ impl StoriqaPollerService {
         &self,
         start_block_hash: Option<String>,
         blocks_count: u64,
-    ) -> impl Future<Item = (), Error = Error> + Send {
-        let publisher = self.publisher.clone();
-        self.client
+    ) -> impl Future<Item = Option<u64>, Error = Error> + Send {
+        let txs = self
+            .client
             .last_stq_transactions(start_block_hash.clone(), blocks_count.clone())
-            .map_err(ectx!(ErrorSource::Client, ErrorKind::Internal => start_block_hash, blocks_count))
-            .and_then(move |tx| {
-                publisher
-                    .publish(vec![tx.clone()])
-                    .map_err(ectx!(ErrorSource::Publisher, ErrorKind::Internal => tx.clone()))
-            })
-            .for_each(|_| Ok(()))
+            .map_err(ectx!(ErrorSource::Client, ErrorKind::Internal => start_block_hash, blocks_count));
+        self.publish(txs)
+    }
+
+    /// Publishes scanned transactions, resolves to the latest block with published transactions
+    fn publish<S>(&self, txs: S) -> impl Future<Item = Option<u64>, Error = Error> + Send
+    where
+        S: Stream<Item = BlockchainTransaction, Error = Error> + Send,
+    {
+        let publisher = self.publisher.clone();
+        let history = self.history.clone();
+        let storage = self.storage.clone();
+        txs.and_then(move |tx| {
+            let block_number = tx.block_number;
+            let history = history.clone();
+            let storage = storage.clone();
+            let tx_clone = tx.clone();
+            let tx_clone2 = tx.clone();
+            publisher
+                .publish(vec![tx.clone()])
+                .map_err(ectx!(ErrorSource::Publisher, ErrorKind::Internal => tx_clone))
+                .and_then(move |_| persistence::save_transaction(&storage, tx_clone2))
+                .map(move |_| {
+                    if let Some(history) = history {
+                        // index lagging behind is not worth stopping the poller
+                        if let Err(e) = history.index(&tx) {
+                            let e: Error = ectx!(err e, ErrorSource::History, ErrorKind::Internal => tx);
+                            log_error(&e);
+                        }
+                    }
+                    block_number
+                })
+        })
+        // the latest block with published transactions
+        .fold(None, |last_block, block_number| Ok::<_, Error>(last_block.max(block_number)))
     }
 
     fn tick(&self) {
-        let f = self.publish_transactions(None, self.number_of_tracked_confirmations as u64);
+        let self_clone = self.clone();
+        let status = self.status.clone();
+        let started_at = Instant::now();
+        let currency = Currency::Stq.to_string();
+        let currency_clone = currency.clone();
+        let f = self
+            .client
+            .get_block_number()
+            .map_err(ectx!(ErrorSource::Client, ErrorKind::Internal))
+            .and_then(move |head| {
+                POLLER_CHAIN_TIP.with_label_values(&[&currency]).set(head as i64);
+                persistence::blocks_to_scan(
+                    &self_clone.storage,
+                    Currency::Stq,
+                    head,
+                    self_clone.number_of_tracked_confirmations as u64,
+                    self_clone.max_catch_up_blocks as u64,
+                )
+                .and_then(move |(scanned_to, blocks_count)| {
+                    let txs = self_clone
+                        .client
+                        .last_stq_transactions_to(scanned_to, blocks_count)
+                        .map_err(ectx!(ErrorSource::Client, ErrorKind::Internal => scanned_to, blocks_count));
+                    self_clone
+                        .publish(txs)
+                        .map(move |last_block| {
+                            if let Some(last_block) = last_block {
+                                POLLER_LAG_BLOCKS
+                                    .with_label_values(&[&currency])
+                                    .set(head.saturating_sub(last_block) as i64);
+                            }
+                        })
+                        .and_then(move |_| persistence::save_cursor(&self_clone.storage, Currency::Stq, scanned_to))
+                })
+            })
+            .then(move |res| {
+                POLLER_TICK_DURATION
+                    .with_label_values(&[&currency_clone, metrics::result_label(&res)])
+                    .observe(metrics::duration_secs(started_at));
+                if res.is_ok() {
+                    status.record_success(Currency::Stq);
+                }
+                res
+            });
         tokio::spawn(f.map_err(|e| {
             log_error(&e);
         }));
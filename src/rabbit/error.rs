@@ -46,6 +46,12 @@ pub enum ErrorContext {
     AlreadyConnecting,
     #[fail(display = "rabbit error context - attempted to close the channel, but failed")]
     ChannelClose,
+    #[fail(display = "rabbit error context - invalid rabbit config")]
+    Config,
+    #[fail(display = "rabbit error context - error serializing message")]
+    Serialization,
+    #[fail(display = "rabbit error context - transaction is not included in a block")]
+    UnminedTransaction,
 }
 
 derive_error_impls!();
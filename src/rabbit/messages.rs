@@ -0,0 +1,231 @@
+use chrono::{DateTime, Utc};
+use config::Network;
+use models::*;
+use schemars::{self, JsonSchema};
+use serde_json::{self, Value};
+
+/// Value of AMQP `type` property and `eventType` envelope field for transaction messages
+pub const TRANSACTION_EVENT_TYPE: &str = "blockchain_transaction";
+
+/// Value of AMQP `type` property and `eventType` envelope field for status changes of posted transactions
+pub const BROADCAST_STATUS_EVENT_TYPE: &str = "broadcast_status";
+
+/// Envelope schema version, as published in `schemaVersion` field
+pub const ENVELOPE_SCHEMA_VERSION: u32 = 3;
+
+/// Transaction in the format of `SchemaVersion::V2`, also the payload of `V3` envelope. Only transactions included
+/// in a block are published, so unlike `BlockchainTransaction` its block fields are never null
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockchainTransactionV2 {
+    pub hash: String,
+    pub from: Vec<String>,
+    pub to: Vec<BlockchainTransactionEntry>,
+    pub block_number: u64,
+    pub block_hash: String,
+    pub block_time: DateTime<Utc>,
+    pub currency: Currency,
+    pub fee: Amount,
+    pub confirmations: usize,
+    pub erc20_operation_kind: Option<Erc20OperationKind>,
+    pub contract_deployment: bool,
+}
+
+impl BlockchainTransactionV2 {
+    /// Gives the transaction back if it's not included in a block yet
+    pub fn new(tx: BlockchainTransaction) -> Result<Self, BlockchainTransaction> {
+        let (block_number, block_hash, block_time) = match (tx.block_number, tx.block_hash.clone(), tx.block_time) {
+            (Some(block_number), Some(block_hash), Some(block_time)) => (block_number, block_hash, block_time),
+            _ => return Err(tx),
+        };
+        Ok(BlockchainTransactionV2 {
+            hash: tx.hash,
+            from: tx.from,
+            to: tx.to,
+            block_number,
+            block_hash,
+            block_time,
+            currency: tx.currency,
+            fee: tx.fee,
+            confirmations: tx.confirmations,
+            erc20_operation_kind: tx.erc20_operation_kind,
+            contract_deployment: tx.contract_deployment,
+        })
+    }
+}
+
+/// Transaction in the format of `SchemaVersion::V1`, kept for consumers that were not yet updated
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockchainTransactionV1 {
+    pub hash: String,
+    pub from: Vec<String>,
+    pub to: Vec<BlockchainTransactionEntry>,
+    pub block_number: u64,
+    pub currency: Currency,
+    pub fee: Amount,
+    pub confirmations: usize,
+    pub erc20_operation_kind: Option<Erc20OperationKind>,
+}
+
+impl From<BlockchainTransactionV2> for BlockchainTransactionV1 {
+    fn from(tx: BlockchainTransactionV2) -> Self {
+        // V1 consumers only know about approvals and transfers
+        let erc20_operation_kind = tx.erc20_operation_kind.map(|kind| match kind {
+            Erc20OperationKind::Mint | Erc20OperationKind::Burn => Erc20OperationKind::TransferFrom,
+            kind => kind,
+        });
+        BlockchainTransactionV1 {
+            hash: tx.hash,
+            from: tx.from,
+            to: tx.to,
+            block_number: tx.block_number,
+            currency: tx.currency,
+            fee: tx.fee,
+            confirmations: tx.confirmations,
+            erc20_operation_kind,
+        }
+    }
+}
+
+/// Message wrapper used since `SchemaVersion::V3`
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope<T> {
+    pub schema_version: u32,
+    pub event_type: String,
+    pub produced_at: DateTime<Utc>,
+    pub source: Source,
+    pub payload: T,
+}
+
+impl Envelope<BlockchainTransactionV2> {
+    pub fn transaction(tx: BlockchainTransactionV2, network: &Network) -> Self {
+        Envelope {
+            schema_version: ENVELOPE_SCHEMA_VERSION,
+            event_type: TRANSACTION_EVENT_TYPE.to_string(),
+            produced_at: Utc::now(),
+            source: Source::new(tx.currency, network),
+            payload: tx,
+        }
+    }
+}
+
+impl Envelope<Broadcast> {
+    pub fn broadcast_status(broadcast: Broadcast, network: &Network) -> Self {
+        Envelope {
+            schema_version: ENVELOPE_SCHEMA_VERSION,
+            event_type: BROADCAST_STATUS_EVENT_TYPE.to_string(),
+            produced_at: Utc::now(),
+            source: Source::new(broadcast.currency, network),
+            payload: broadcast,
+        }
+    }
+}
+
+/// Blockchain and network the message originates from
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Source {
+    pub chain: String,
+    pub network: String,
+}
+
+impl Source {
+    pub fn new(currency: Currency, network: &Network) -> Self {
+        match currency {
+            Currency::Btc => Source {
+                chain: "bitcoin".to_string(),
+                network: network.bitcoin.to_string(),
+            },
+            Currency::Eth | Currency::Stq => Source {
+                chain: "ethereum".to_string(),
+                network: network.ethereum.name.clone(),
+            },
+        }
+    }
+}
+
+/// JSON Schema of transaction envelope, generated from message types. Checked in as
+/// `docs/rabbit/blockchain_transaction.schema.json`, regenerate it with `print_message_schema` command after changing messages.
+pub fn transaction_envelope_schema() -> Value {
+    let schema = schema_for!(Envelope<BlockchainTransactionV2>);
+    serde_json::to_value(&schema).expect("schema is serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{BitcoinNetwork, EthereumNetwork};
+    use serde_json;
+
+    fn network(ethereum: &str, chain_id: u64) -> Network {
+        Network {
+            bitcoin: BitcoinNetwork::Testnet,
+            ethereum: EthereumNetwork {
+                chain_id,
+                name: ethereum.to_string(),
+                stq_contract_address: Some("0x1bf2092a42166b2ae19b7b23752e7d2dab5ba91a".to_string()),
+            },
+        }
+    }
+
+    fn transaction() -> BlockchainTransaction {
+        BlockchainTransaction {
+            hash: "a".to_string(),
+            from: vec!["0000000000000000000000000000000000000000".to_string()],
+            to: vec![BlockchainTransactionEntry {
+                address: "b".to_string(),
+                value: Amount::new(340282366920938463463374607431768211455),
+            }],
+            block_number: Some(1),
+            block_hash: Some("b".to_string()),
+            block_time: Some(Utc::now()),
+            currency: Currency::Stq,
+            fee: Amount::new(1),
+            confirmations: 1,
+            erc20_operation_kind: Some(Erc20OperationKind::Mint),
+            contract_deployment: false,
+        }
+    }
+
+    #[test]
+    fn test_v1_conversion() {
+        let tx = BlockchainTransactionV2::new(transaction()).unwrap();
+        let value = serde_json::to_value(&BlockchainTransactionV1::from(tx)).unwrap();
+        assert_eq!(value["erc20OperationKind"], "transfer_from");
+        assert_eq!(value["blockNumber"], 1);
+        assert!(value.get("blockHash").is_none());
+        assert!(value.get("contractDeployment").is_none());
+    }
+
+    #[test]
+    fn test_checked_in_schema_is_up_to_date() {
+        let checked_in: Value = serde_json::from_str(include_str!("../../docs/rabbit/blockchain_transaction.schema.json")).unwrap();
+        assert_eq!(
+            checked_in,
+            transaction_envelope_schema(),
+            "docs/rabbit/blockchain_transaction.schema.json is outdated, regenerate it with `print_message_schema` command"
+        );
+    }
+
+    #[test]
+    fn test_schema_describes_published_fields() {
+        let schema = transaction_envelope_schema();
+        let payload = BlockchainTransactionV2::new(transaction()).unwrap();
+        let envelope = serde_json::to_value(&Envelope::transaction(payload, &network("sepolia", 11_155_111))).unwrap();
+        let keys = |value: &Value| value.as_object().unwrap().keys().cloned().collect::<Vec<_>>();
+        assert_eq!(keys(&schema["properties"]), keys(&envelope));
+        assert_eq!(
+            keys(&schema["definitions"]["BlockchainTransactionV2"]["properties"]),
+            keys(&envelope["payload"])
+        );
+    }
+
+    #[test]
+    fn test_unmined_transaction_is_not_published() {
+        let mut tx = transaction();
+        tx.block_number = None;
+        assert!(BlockchainTransactionV2::new(tx).is_err());
+    }
+}
@@ -1,7 +1,10 @@
 mod error;
+mod messages;
+mod protobuf;
 mod r2d2;
 mod transactions;
 
 pub use self::error::*;
+pub use self::messages::*;
 pub use self::r2d2::*;
 pub use self::transactions::*;
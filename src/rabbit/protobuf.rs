@@ -0,0 +1,191 @@
+//! Protobuf encoding of transaction messages. The contract is checked in as
+//! `docs/rabbit/blockchain_transaction.proto`, field numbers here must stay in sync with it.
+
+use chrono::{DateTime, Utc};
+
+use super::messages::{BlockchainTransactionV2, Envelope, Source};
+use models::*;
+
+const WIRE_TYPE_VARINT: u64 = 0;
+const WIRE_TYPE_LENGTH_DELIMITED: u64 = 2;
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn key(&mut self, field: u64, wire_type: u64) {
+        self.varint((field << 3) | wire_type);
+    }
+
+    fn uint64(&mut self, field: u64, value: u64) {
+        self.key(field, WIRE_TYPE_VARINT);
+        self.varint(value);
+    }
+
+    fn int64(&mut self, field: u64, value: i64) {
+        // negative values take 10 bytes in two's complement, as defined by protobuf
+        self.uint64(field, value as u64);
+    }
+
+    fn bool(&mut self, field: u64, value: bool) {
+        self.uint64(field, value as u64);
+    }
+
+    fn bytes(&mut self, field: u64, value: &[u8]) {
+        self.key(field, WIRE_TYPE_LENGTH_DELIMITED);
+        self.varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    fn string(&mut self, field: u64, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    fn message(&mut self, field: u64, value: Writer) {
+        self.bytes(field, &value.buf);
+    }
+}
+
+/// Encodes `Envelope` message
+pub fn encode_transaction_envelope(envelope: &Envelope<BlockchainTransactionV2>) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.uint64(1, envelope.schema_version as u64);
+    w.string(2, &envelope.event_type);
+    w.message(3, timestamp(&envelope.produced_at));
+    w.message(4, source(&envelope.source));
+    w.message(5, transaction(&envelope.payload));
+    w.buf
+}
+
+/// `google.protobuf.Timestamp` message
+fn timestamp(time: &DateTime<Utc>) -> Writer {
+    let mut w = Writer::default();
+    w.int64(1, time.timestamp());
+    w.uint64(2, time.timestamp_subsec_nanos() as u64);
+    w
+}
+
+fn source(source: &Source) -> Writer {
+    let mut w = Writer::default();
+    w.string(1, &source.chain);
+    w.string(2, &source.network);
+    w
+}
+
+fn transaction(tx: &BlockchainTransactionV2) -> Writer {
+    let mut w = Writer::default();
+    w.string(1, &tx.hash);
+    for address in &tx.from {
+        w.string(2, address);
+    }
+    for entry in &tx.to {
+        let mut e = Writer::default();
+        e.string(1, &entry.address);
+        // u128 doesn't fit any protobuf scalar, so amounts are decimal strings
+        e.string(2, &entry.value.inner().to_string());
+        w.message(3, e);
+    }
+    w.uint64(4, tx.block_number);
+    w.string(5, &tx.block_hash);
+    w.message(6, timestamp(&tx.block_time));
+    w.uint64(7, currency(tx.currency));
+    w.string(8, &tx.fee.inner().to_string());
+    w.uint64(9, tx.confirmations as u64);
+    if let Some(kind) = tx.erc20_operation_kind {
+        w.uint64(10, erc20_operation_kind(kind));
+    }
+    w.bool(11, tx.contract_deployment);
+    w
+}
+
+fn currency(currency: Currency) -> u64 {
+    match currency {
+        Currency::Btc => 1,
+        Currency::Eth => 2,
+        Currency::Stq => 3,
+    }
+}
+
+fn erc20_operation_kind(kind: Erc20OperationKind) -> u64 {
+    match kind {
+        Erc20OperationKind::Approve => 1,
+        Erc20OperationKind::TransferFrom => 2,
+        Erc20OperationKind::Mint => 3,
+        Erc20OperationKind::Burn => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_varint() {
+        let cases = [(0u64, vec![0x00u8]), (1, vec![0x01]), (127, vec![0x7f]), (300, vec![0xac, 0x02])];
+        for (value, expected) in cases.iter() {
+            let mut w = Writer::default();
+            w.varint(*value);
+            assert_eq!(&w.buf, expected, "Case: {}", value);
+        }
+        let mut w = Writer::default();
+        w.int64(1, -1);
+        assert_eq!(w.buf, vec![0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+    }
+
+    #[test]
+    fn test_encode_transaction_envelope() {
+        let envelope = Envelope {
+            schema_version: 3,
+            event_type: "e".to_string(),
+            produced_at: Utc.timestamp_opt(1, 0).unwrap(),
+            source: Source {
+                chain: "c".to_string(),
+                network: "n".to_string(),
+            },
+            payload: BlockchainTransactionV2 {
+                hash: "h".to_string(),
+                from: vec![],
+                to: vec![BlockchainTransactionEntry {
+                    address: "a".to_string(),
+                    value: Amount::new(10),
+                }],
+                block_number: 7,
+                block_hash: "b".to_string(),
+                block_time: Utc.timestamp_opt(1, 0).unwrap(),
+                currency: Currency::Eth,
+                fee: Amount::new(0),
+                confirmations: 2,
+                erc20_operation_kind: None,
+                contract_deployment: true,
+            },
+        };
+        let expected: Vec<u8> = vec![
+            0x08, 0x03, // schema_version
+            0x12, 0x01, b'e', // event_type
+            0x1a, 0x04, 0x08, 0x01, 0x10, 0x00, // produced_at
+            0x22, 0x06, 0x0a, 0x01, b'c', 0x12, 0x01, b'n', // source
+            0x2a, 0x20, // payload
+            0x0a, 0x01, b'h', // hash
+            0x1a, 0x07, 0x0a, 0x01, b'a', 0x12, 0x02, b'1', b'0', // to
+            0x20, 0x07, // block_number
+            0x2a, 0x01, b'b', // block_hash
+            0x32, 0x04, 0x08, 0x01, 0x10, 0x00, // block_time
+            0x38, 0x02, // currency
+            0x42, 0x01, b'0', // fee
+            0x48, 0x02, // confirmations
+            0x58, 0x01, // contract_deployment
+        ];
+        assert_eq!(encode_transaction_envelope(&envelope), expected);
+    }
+}
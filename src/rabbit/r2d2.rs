@@ -1,5 +1,6 @@
 use std::fmt::{self, Debug};
 use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -27,6 +28,8 @@ pub struct RabbitConnectionManager {
     connection_timeout: Duration,
     connection_options: ConnectionOptions,
     address: SocketAddr,
+    // false after heartbeat failure, i.e. connection to rabbit is lost
+    healthy: Arc<AtomicBool>,
 }
 
 impl Debug for RabbitConnectionManager {
@@ -59,14 +62,16 @@ impl RabbitConnectionManager {
             .into_future()
             .and_then(move |(options, address)| {
                 let options_clone = options.clone();
+                let healthy = Arc::new(AtomicBool::new(true));
                 Timeout::new(
-                    RabbitConnectionManager::establish_client(address, options).map(move |(client, hearbeat_handle)| {
+                    RabbitConnectionManager::establish_client(address, options, healthy.clone()).map(move |(client, hearbeat_handle)| {
                         RabbitConnectionManager {
                             client: Arc::new(Mutex::new(client)),
                             heartbeat_handle: Arc::new(Mutex::new(hearbeat_handle)),
                             connection_options: options_clone,
                             connection_timeout,
                             address,
+                            healthy,
                         }
                     }),
                     connection_timeout,
@@ -108,6 +113,7 @@ impl RabbitConnectionManager {
     fn establish_client(
         address: SocketAddr,
         options: ConnectionOptions,
+        healthy: Arc<AtomicBool>,
     ) -> impl Future<Item = (Client<TcpStream>, RabbitHeartbeatHandle), Error = Error> {
         let address_clone2 = address.clone();
         let address_clone3 = address.clone();
@@ -123,6 +129,7 @@ impl RabbitConnectionManager {
                 info!("Connected to rabbit");
                 let handle = heartbeat.handle();
                 tokio::spawn(heartbeat.map_err(move |e| {
+                    healthy.store(false, Ordering::SeqCst);
                     let e: Error = ectx!(err e, ErrorContext::Heartbeat, ErrorKind::Internal);
                     log_error(&e);
                 }));
@@ -132,6 +139,11 @@ impl RabbitConnectionManager {
             })
     }
 
+    /// Returns false if connection to rabbit was lost
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::SeqCst)
+    }
+
     pub fn get_channel(&self) -> Result<Channel<TcpStream>, Error> {
         trace!("Creating rabbit channel...");
         let cli = self.client.lock().unwrap();
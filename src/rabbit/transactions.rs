@@ -1,9 +1,13 @@
 use std::sync::Arc;
 
 use super::error::*;
+use super::messages::*;
+use super::protobuf::encode_transaction_envelope;
+use config::{Backfill, Config, MessageEncoding, Network, SchemaVersion};
 use futures::future;
-use lapin_futures::channel::{Channel, ExchangeDeclareOptions, QueueDeclareOptions};
+use lapin_futures::channel::{BasicProperties, Channel, ExchangeDeclareOptions, QueueDeclareOptions};
 use lapin_futures::error::Error as LapinError;
+use metrics::{self, PUBLISHED_MESSAGES};
 use models::*;
 use prelude::*;
 use serde_json;
@@ -11,15 +15,34 @@ use tokio::net::tcp::TcpStream;
 
 pub trait TransactionPublisher: Send + Sync + 'static {
     fn publish(&self, txs: Vec<BlockchainTransaction>) -> Box<Future<Item = (), Error = Error> + Send>;
+    /// Same as `publish`, but with routing key of replayed transactions, see `config::Backfill`
+    fn publish_backfill(&self, txs: Vec<BlockchainTransaction>) -> Box<Future<Item = (), Error = Error> + Send>;
+    /// Publishes status change of posted transaction to `broadcast_status` queue, always as JSON envelope
+    fn publish_broadcast_status(&self, broadcast: Broadcast) -> Box<Future<Item = (), Error = Error> + Send>;
+    /// Round trip to broker on the publishing channel, fails if the channel or connection is closed
+    fn check_channel(&self) -> Box<Future<Item = (), Error = Error> + Send>;
 }
 
 #[derive(Clone)]
 pub struct TransactionPublisherImpl {
     channel: Arc<Channel<TcpStream>>,
+    schema_version: SchemaVersion,
+    encoding: MessageEncoding,
+    network: Network,
+    backfill: Backfill,
 }
 
 impl TransactionPublisherImpl {
-    pub fn init(channel: Arc<Channel<TcpStream>>) -> impl Future<Item = Self, Error = Error> + Send {
+    pub fn init(channel: Arc<Channel<TcpStream>>, config: &Config) -> Box<Future<Item = Self, Error = Error> + Send> {
+        let schema_version = config.rabbit.schema_version;
+        let encoding = config.rabbit.encoding;
+        let network = config.network.clone();
+        let backfill = config.backfill.clone();
+        if encoding == MessageEncoding::Protobuf && schema_version != SchemaVersion::V3 {
+            return Box::new(future::err(
+                ectx!(err ErrorContext::Config, ErrorKind::Internal => schema_version, encoding),
+            ));
+        }
         let f1: Box<Future<Item = (), Error = LapinError> + Send> = Box::new(channel.exchange_declare(
             "blockchain_transactions",
             "direct",
@@ -110,25 +133,126 @@ impl TransactionPublisherImpl {
             Default::default(),
             Default::default(),
         ));
-        future::join_all(vec![f1, f2, f3, f4, f5, f6, f7, f8, f9, f10, f11])
-            .map(|_| Self { channel })
-            .map_err(ectx!(ErrorSource::Lapin, ErrorKind::Internal))
+        let f12 = Box::new(
+            channel
+                .queue_declare(
+                    "broadcast_status",
+                    QueueDeclareOptions {
+                        durable: true,
+                        ..Default::default()
+                    },
+                    Default::default(),
+                )
+                .map(|_| ()),
+        );
+        let f13 = Box::new(channel.queue_bind(
+            "broadcast_status",
+            "blockchain_transactions",
+            "broadcast_status",
+            Default::default(),
+            Default::default(),
+        ));
+        let mut fs = vec![f1, f2, f3, f4, f5, f6, f7, f8, f9, f10, f11, f12, f13];
+        for currency in &[Currency::Btc, Currency::Eth, Currency::Stq] {
+            let routing_key = backfill.routing_key(*currency);
+            let channel_clone = channel.clone();
+            let routing_key_clone = routing_key.clone();
+            fs.push(Box::new(
+                channel
+                    .queue_declare(
+                        &routing_key,
+                        QueueDeclareOptions {
+                            durable: true,
+                            ..Default::default()
+                        },
+                        Default::default(),
+                    )
+                    .and_then(move |_| {
+                        channel_clone.queue_bind(
+                            &routing_key_clone,
+                            "blockchain_transactions",
+                            &routing_key_clone,
+                            Default::default(),
+                            Default::default(),
+                        )
+                    }),
+            ));
+        }
+        Box::new(
+            future::join_all(fs)
+                .map(move |_| Self {
+                    channel,
+                    schema_version,
+                    encoding,
+                    network,
+                    backfill,
+                })
+                .map_err(ectx!(ErrorSource::Lapin, ErrorKind::Internal)),
+        )
     }
 }
 
-impl TransactionPublisher for TransactionPublisherImpl {
-    fn publish(&self, txs: Vec<BlockchainTransaction>) -> Box<Future<Item = (), Error = Error> + Send> {
+impl TransactionPublisherImpl {
+    fn serialize(&self, tx: BlockchainTransaction) -> Result<Vec<u8>, Error> {
+        let tx =
+            BlockchainTransactionV2::new(tx).map_err(|tx| ectx!(try err ErrorContext::UnminedTransaction, ErrorKind::Internal => tx))?;
+        let payload = match self.schema_version {
+            SchemaVersion::V1 => serde_json::to_vec(&BlockchainTransactionV1::from(tx)),
+            SchemaVersion::V2 => serde_json::to_vec(&tx),
+            SchemaVersion::V3 => {
+                let envelope = Envelope::transaction(tx, &self.network);
+                match self.encoding {
+                    MessageEncoding::Json => serde_json::to_vec(&envelope),
+                    MessageEncoding::Protobuf => Ok(encode_transaction_envelope(&envelope)),
+                }
+            }
+        };
+        payload.map_err(ectx!(ErrorContext::Serialization, ErrorKind::Internal))
+    }
+
+    fn properties(&self) -> BasicProperties {
+        let content_type = match self.encoding {
+            MessageEncoding::Json => "application/json",
+            MessageEncoding::Protobuf => "application/x-protobuf",
+        };
+        BasicProperties::default()
+            .with_content_type(content_type.to_string())
+            .with_type(TRANSACTION_EVENT_TYPE.to_string())
+    }
+
+    fn publish_with_routing_key<F>(&self, txs: Vec<BlockchainTransaction>, routing_key: F) -> Box<Future<Item = (), Error = Error> + Send>
+    where
+        F: Fn(Currency) -> String,
+    {
+        let payloads: Result<Vec<_>, _> = txs
+            .into_iter()
+            .map(|tx| {
+                let routing_key = routing_key(tx.currency);
+                self.serialize(tx).map(|payload| (routing_key, payload))
+            })
+            .collect();
+        let payloads = match payloads {
+            Ok(payloads) => payloads,
+            Err(e) => return Box::new(future::err(e)),
+        };
         let channel = self.channel.clone();
-        let futures = txs.into_iter().map(move |tx| {
-            let routing_key = format!("{}_transactions", tx.currency);
-            let payload = serde_json::to_string(&tx).unwrap().into_bytes();
-            channel.clone().basic_publish(
-                "blockchain_transactions",
-                &routing_key,
-                payload,
-                Default::default(),
-                Default::default(),
-            )
+        let properties = self.properties();
+        let futures = payloads.into_iter().map(move |(routing_key, payload)| {
+            channel
+                .clone()
+                .basic_publish(
+                    "blockchain_transactions",
+                    &routing_key,
+                    payload,
+                    Default::default(),
+                    properties.clone(),
+                )
+                .then(move |res| {
+                    PUBLISHED_MESSAGES
+                        .with_label_values(&[&routing_key, metrics::result_label(&res)])
+                        .inc();
+                    res
+                })
         });
         Box::new(
             future::join_all(futures)
@@ -140,3 +264,62 @@ impl TransactionPublisher for TransactionPublisherImpl {
         )
     }
 }
+
+impl TransactionPublisher for TransactionPublisherImpl {
+    fn publish(&self, txs: Vec<BlockchainTransaction>) -> Box<Future<Item = (), Error = Error> + Send> {
+        self.publish_with_routing_key(txs, |currency| format!("{}_transactions", currency))
+    }
+
+    fn publish_backfill(&self, txs: Vec<BlockchainTransaction>) -> Box<Future<Item = (), Error = Error> + Send> {
+        let backfill = self.backfill.clone();
+        self.publish_with_routing_key(txs, move |currency| backfill.routing_key(currency))
+    }
+
+    fn publish_broadcast_status(&self, broadcast: Broadcast) -> Box<Future<Item = (), Error = Error> + Send> {
+        let envelope = Envelope::broadcast_status(broadcast, &self.network);
+        let channel = self.channel.clone();
+        let properties = BasicProperties::default()
+            .with_content_type("application/json".to_string())
+            .with_type(BROADCAST_STATUS_EVENT_TYPE.to_string());
+        Box::new(
+            serde_json::to_vec(&envelope)
+                .map_err(ectx!(ErrorContext::Serialization, ErrorKind::Internal => envelope))
+                .into_future()
+                .and_then(move |payload| {
+                    channel
+                        .basic_publish(
+                            "blockchain_transactions",
+                            "broadcast_status",
+                            payload,
+                            Default::default(),
+                            properties,
+                        )
+                        .then(|res| {
+                            PUBLISHED_MESSAGES
+                                .with_label_values(&["broadcast_status", metrics::result_label(&res)])
+                                .inc();
+                            res
+                        })
+                        .map_err(ectx!(ErrorSource::Lapin, ErrorKind::Internal))
+                })
+                .map(|_| ()),
+        )
+    }
+    fn check_channel(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        // passive declare doesn't change anything, but needs an answer from broker on this channel
+        Box::new(
+            self.channel
+                .exchange_declare(
+                    "blockchain_transactions",
+                    "direct",
+                    ExchangeDeclareOptions {
+                        passive: true,
+                        durable: true,
+                        ..Default::default()
+                    },
+                    Default::default(),
+                )
+                .map_err(ectx!(ErrorSource::Lapin, ErrorContext::RabbitChannel, ErrorKind::Internal)),
+        )
+    }
+}
@@ -1,3 +1,4 @@
+use config::Mode;
 use sentry;
 
 #[derive(Debug, Deserialize, Clone)]
@@ -5,13 +6,14 @@ pub struct SentryConfig {
     pub dsn: String,
 }
 
-pub fn init(sentry_config: Option<&SentryConfig>) -> Option<sentry::internals::ClientInitGuard> {
+pub fn init(sentry_config: Option<&SentryConfig>, mode: &Mode) -> Option<sentry::internals::ClientInitGuard> {
     sentry_config.map(|config_sentry| {
         info!("initialization support with sentry");
         let result = sentry::init((
             config_sentry.dsn.clone(),
             sentry::ClientOptions {
                 release: sentry_crate_release!(),
+                environment: Some(mode.to_string().into()),
                 ..Default::default()
             },
         ));
@@ -0,0 +1,112 @@
+use futures::future;
+use validator::ValidationErrors;
+
+use super::error::*;
+use models::*;
+use pollers::{BackfillRegistry, BackfillRunner};
+use prelude::*;
+use utils::log_error;
+
+pub trait BackfillService: Send + Sync + 'static {
+    /// Starts publishing transactions of blocks from `from_height` to `to_height` inclusive in background.
+    /// Run of a range that stopped halfway resumes from its checkpoint
+    fn start_backfill(&self, currency: Currency, from_height: u64, to_height: u64) -> Box<Future<Item = Backfill, Error = Error> + Send>;
+    /// Backfills started since the start of the process
+    fn get_backfills(&self) -> Box<Future<Item = Vec<Backfill>, Error = Error> + Send>;
+    fn get_backfill(&self, id: String) -> Box<Future<Item = Backfill, Error = Error> + Send>;
+    /// Stops running backfill, its status changes once blocks already fetched are published
+    fn cancel_backfill(&self, id: String) -> Box<Future<Item = Backfill, Error = Error> + Send>;
+}
+
+#[derive(Clone)]
+pub struct BackfillServiceImpl {
+    // backfills publish with publisher of pollers, they can't be started without them
+    runner: Option<BackfillRunner>,
+    registry: BackfillRegistry,
+}
+
+impl BackfillServiceImpl {
+    pub fn new(runner: Option<BackfillRunner>, registry: BackfillRegistry) -> Self {
+        Self { runner, registry }
+    }
+}
+
+impl BackfillService for BackfillServiceImpl {
+    fn start_backfill(&self, currency: Currency, from_height: u64, to_height: u64) -> Box<Future<Item = Backfill, Error = Error> + Send> {
+        let runner = match self.runner {
+            Some(ref runner) => runner.clone(),
+            None => return Box::new(future::err(ectx!(err ErrorContext::Backfill, ErrorKind::NotFound => currency))),
+        };
+        let runner_clone = runner.clone();
+        Box::new(
+            runner
+                .head(currency)
+                .map_err(ectx!(ErrorSource::Backfill, ErrorKind::Internal => currency))
+                .and_then(move |head| {
+                    validate_backfill_range(from_height, to_height, head).map_err(
+                        |errors| ectx!(err ErrorContext::Backfill, ErrorKind::InvalidInput(errors) => from_height, to_height, head),
+                    )
+                })
+                .and_then(move |_| {
+                    runner_clone
+                        .prepare(currency, from_height, to_height)
+                        .map_err(ectx!(ErrorSource::Backfill, ErrorKind::Internal => currency, from_height, to_height))
+                        .and_then(|backfill| {
+                            backfill.ok_or_else(|| {
+                                let mut errors = ValidationErrors::new();
+                                let message = "backfill of this range is already running".to_string();
+                                errors.add("range", validation_error("already_running", message));
+                                ectx!(err ErrorContext::Backfill, ErrorKind::InvalidInput(errors) => currency, from_height, to_height)
+                            })
+                        })
+                        .map(move |backfill| {
+                            ::tokio::spawn(runner_clone.run(backfill.clone()).then(|res| {
+                                if let Err(e) = res {
+                                    log_error(&e);
+                                }
+                                Ok(())
+                            }));
+                            backfill
+                        })
+                }),
+        )
+    }
+
+    fn get_backfills(&self) -> Box<Future<Item = Vec<Backfill>, Error = Error> + Send> {
+        Box::new(future::ok(self.registry.list()))
+    }
+
+    fn get_backfill(&self, id: String) -> Box<Future<Item = Backfill, Error = Error> + Send> {
+        Box::new(future::result(
+            self.registry
+                .get(&id)
+                .ok_or_else(|| ectx!(err ErrorContext::Backfill, ErrorKind::NotFound => id)),
+        ))
+    }
+
+    fn cancel_backfill(&self, id: String) -> Box<Future<Item = Backfill, Error = Error> + Send> {
+        Box::new(future::result(
+            self.registry
+                .cancel(&id)
+                .ok_or_else(|| ectx!(err ErrorContext::Backfill, ErrorKind::NotFound => id)),
+        ))
+    }
+}
+
+/// Checks that range is not empty and its blocks are mined
+pub fn validate_backfill_range(from_height: u64, to_height: u64, head: u64) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    if from_height > to_height {
+        let message = format!("from height {} is above to height {}", from_height, to_height);
+        errors.add("fromHeight", validation_error("invalid_range", message));
+    }
+    if to_height > head {
+        let message = format!("block {} is not mined yet, the latest block is {}", to_height, head);
+        errors.add("toHeight", validation_error("block_not_mined", message));
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
@@ -1,24 +1,40 @@
 use std::sync::Arc;
 
+use failure::Fail;
+use futures::future;
+use futures::stream;
+use validator::ValidationErrors;
+
+use super::bitcoin_transaction;
 use super::error::*;
 use client::BitcoinClient;
+use config::{BalanceBatch, BitcoinBroadcast};
 use models::*;
 use prelude::*;
+use utils::log_error;
 
 pub trait BitcoinService: Send + Sync + 'static {
     fn get_balance(&self, address: BitcoinAddress) -> Box<Future<Item = Amount, Error = Error> + Send>;
+    /// Balances of many addresses, addresses that couldn't be queried have errors instead of balances
+    fn get_balances(&self, addresses: Vec<BitcoinAddress>) -> Box<Future<Item = Balances, Error = Error> + Send>;
     fn get_utxos(&self, address: BitcoinAddress) -> Box<Future<Item = Vec<Utxo>, Error = Error> + Send>;
     fn send_raw_tx(&self, tx: RawBitcoinTransaction) -> Box<Future<Item = TxHash, Error = Error> + Send>;
+    /// Decodes raw transaction, checks that node accepts it, its inputs are unspent and it doesn't break
+    /// fee rate and dust limits. Fails with `ErrorKind::InvalidInput` otherwise, so that it's not sent to blockchain
+    fn validate_raw_tx(&self, tx: RawBitcoinTransaction) -> Box<Future<Item = DecodedBitcoinTransaction, Error = Error> + Send>;
+    fn get_transaction(&self, hash: TxHash) -> Box<Future<Item = BlockchainTransaction, Error = Error> + Send>;
 }
 
 #[derive(Clone)]
 pub struct BitcoinServiceImpl {
     client: Arc<BitcoinClient>,
+    limits: BitcoinBroadcast,
+    balances: BalanceBatch,
 }
 
 impl BitcoinServiceImpl {
-    pub fn new(client: Arc<BitcoinClient>) -> Self {
-        Self { client }
+    pub fn new(client: Arc<BitcoinClient>, limits: BitcoinBroadcast, balances: BalanceBatch) -> Self {
+        Self { client, limits, balances }
     }
 }
 
@@ -27,6 +43,35 @@ impl BitcoinService for BitcoinServiceImpl {
         let address_clone = address.clone();
         Box::new(self.client.get_balance(address).map_err(ectx!(convert => address_clone)))
     }
+
+    fn get_balances(&self, addresses: Vec<BitcoinAddress>) -> Box<Future<Item = Balances, Error = Error> + Send> {
+        if addresses.len() > self.balances.max_addresses {
+            return Box::new(future::err(too_many_addresses(addresses.len(), self.balances.max_addresses)));
+        }
+        let client = self.client.clone();
+        Box::new(
+            stream::iter_ok::<_, Error>(addresses)
+                .map(move |address| {
+                    client.get_balance(address.clone()).then(move |res| {
+                        let balance = res.map_err(|e| {
+                            let e: Error = ectx!(err e, ErrorSource::Client, ErrorKind::Internal => address);
+                            log_error(&e);
+                            "failed to query balance".to_string()
+                        });
+                        Ok(AddressBalance {
+                            address: address.to_string(),
+                            balance,
+                        })
+                    })
+                })
+                .buffered(self.balances.concurrency.max(1))
+                .collect()
+                .map(|balances| Balances {
+                    block_number: None,
+                    balances,
+                }),
+        )
+    }
     fn get_utxos(&self, address: BitcoinAddress) -> Box<Future<Item = Vec<Utxo>, Error = Error> + Send> {
         let address_clone = address.clone();
         Box::new(self.client.get_utxos(address).map_err(ectx!(convert => address_clone)))
@@ -36,4 +81,91 @@ impl BitcoinService for BitcoinServiceImpl {
         let tx_clone = tx.clone();
         Box::new(self.client.send_raw_tx(tx).map_err(ectx!(convert => tx_clone)))
     }
+
+    fn validate_raw_tx(&self, tx: RawBitcoinTransaction) -> Box<Future<Item = DecodedBitcoinTransaction, Error = Error> + Send> {
+        let mut decoded = match bitcoin_transaction::decode(&tx) {
+            Ok(decoded) => decoded,
+            Err(message) => return Box::new(future::err(invalid_tx(tx, "raw", "invalid_transaction", message))),
+        };
+        let dust_limit = self.limits.dust_limit;
+        let dust: Vec<_> = decoded
+            .outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| !output.is_null_data() && output.value < Amount::new(u128::from(dust_limit)))
+            .map(|(index, _)| index.to_string())
+            .collect();
+        if !dust.is_empty() {
+            let message = format!("outputs {} are below dust limit of {} satoshis", dust.join(", "), dust_limit);
+            return Box::new(future::err(invalid_tx(tx, "outputs", "dust", message)));
+        }
+        let max_fee_rate = self.limits.max_fee_rate;
+        let client = self.client.clone();
+        let input_values = future::join_all(
+            decoded
+                .inputs
+                .iter()
+                .map(move |input| client.get_unspent_output_value(input.txid.clone(), input.index, true))
+                .collect::<Vec<_>>(),
+        );
+        let tx_clone = tx.clone();
+        Box::new(
+            self.client
+                .test_mempool_accept(tx.clone())
+                .join(input_values)
+                .map_err(ectx!(convert => tx_clone))
+                .and_then(move |(acceptance, input_values)| {
+                    let mut errors = ValidationErrors::new();
+                    if !acceptance.allowed {
+                        let reason = acceptance.reject_reason.unwrap_or_else(|| "unknown".to_string());
+                        errors.add(
+                            "raw",
+                            validation_error("rejected", format!("transaction is rejected by node: {}", reason)),
+                        );
+                    }
+                    let spent: Vec<_> = decoded
+                        .inputs
+                        .iter()
+                        .zip(input_values.iter())
+                        .filter(|(_, value)| value.is_none())
+                        .map(|(input, _)| format!("{}:{}", input.txid, input.index))
+                        .collect();
+                    if !spent.is_empty() {
+                        let message = format!("inputs {} are spent or don't exist", spent.join(", "));
+                        errors.add("inputs", validation_error("spent", message));
+                    } else {
+                        let inputs_sum = sum(input_values.into_iter().filter_map(|value| value));
+                        let outputs_sum = sum(decoded.outputs.iter().map(|output| output.value));
+                        match (inputs_sum, outputs_sum) {
+                            (Some(inputs_sum), Some(outputs_sum)) if inputs_sum >= outputs_sum => {
+                                let fee = inputs_sum.checked_sub(outputs_sum).unwrap_or(Amount::new(0));
+                                let fee_rate = fee.inner() as f64 / decoded.vsize as f64;
+                                if fee.inner() > u128::from(max_fee_rate) * decoded.vsize as u128 {
+                                    let message = format!("fee rate {:.2} sat/vB exceeds max fee rate {} sat/vB", fee_rate, max_fee_rate);
+                                    errors.add("fee", validation_error("fee_rate_too_high", message));
+                                }
+                                decoded.fee = Some(fee);
+                                decoded.fee_rate = Some(fee_rate);
+                            }
+                            _ => errors.add("fee", validation_error("negative_fee", "outputs exceed inputs".to_string())),
+                        }
+                    }
+                    if errors.is_empty() {
+                        Ok(decoded)
+                    } else {
+                        Err(ectx!(err ErrorContext::TransactionValidation, ErrorKind::InvalidInput(errors) => tx))
+                    }
+                }),
+        )
+    }
+
+    fn get_transaction(&self, hash: TxHash) -> Box<Future<Item = BlockchainTransaction, Error = Error> + Send> {
+        let hash_clone = hash.clone();
+        Box::new(self.client.get_transaction(hash.into_inner()).map_err(ectx!(convert => hash_clone)))
+    }
+}
+
+/// Sum of amounts, `None` on overflow
+fn sum(amounts: impl Iterator<Item = Amount>) -> Option<Amount> {
+    amounts.fold(Some(Amount::new(0)), |acc, amount| acc.and_then(|acc| acc.checked_add(amount)))
 }
@@ -0,0 +1,193 @@
+//! Decoding of raw bitcoin transactions, both legacy and segwit (BIP-144) serializations.
+
+use openssl::sha::sha256;
+
+use client::bytes_to_hex;
+use models::*;
+use utils::parse_hex;
+
+/// Decodes hex encoded transaction. Error describes why the transaction is invalid
+pub fn decode(raw: &RawBitcoinTransaction) -> Result<DecodedBitcoinTransaction, String> {
+    let bytes = parse_hex(&raw.to_string()).ok_or_else(|| "transaction is not a hex string".to_string())?;
+    let mut reader = Reader { bytes: &bytes, pos: 0 };
+    let version = reader.u32()? as i32;
+    // segwit serialization has marker 0x00 in place of inputs count, followed by flag 0x01
+    let segwit = reader.peek(2) == Some(&[0x00, 0x01][..]);
+    if segwit {
+        reader.take(2)?;
+    }
+    let body_start = reader.pos;
+    let inputs_count = reader.var_int()?;
+    if inputs_count == 0 {
+        return Err("transaction has no inputs".to_string());
+    }
+    let mut inputs = vec![];
+    for _ in 0..inputs_count {
+        let mut txid = reader.take(32)?.to_vec();
+        // hashes are displayed in reversed byte order
+        txid.reverse();
+        let index = reader.u32()?;
+        reader.var_bytes()?;
+        let sequence = reader.u32()?;
+        inputs.push(BitcoinTransactionInput {
+            txid: bytes_to_hex(&txid),
+            index,
+            sequence,
+        });
+    }
+    let outputs_count = reader.var_int()?;
+    if outputs_count == 0 {
+        return Err("transaction has no outputs".to_string());
+    }
+    let mut outputs = vec![];
+    for _ in 0..outputs_count {
+        let value = reader.u64()?;
+        let script_pub_key = bytes_to_hex(reader.var_bytes()?);
+        outputs.push(BitcoinTransactionOutput {
+            value: Amount::new(u128::from(value)),
+            script_pub_key,
+        });
+    }
+    let body_end = reader.pos;
+    if segwit {
+        for _ in 0..inputs_count {
+            for _ in 0..reader.var_int()? {
+                reader.var_bytes()?;
+            }
+        }
+    }
+    let locktime = reader.u32()?;
+    if reader.pos != bytes.len() {
+        return Err(format!("transaction has {} trailing bytes", bytes.len() - reader.pos));
+    }
+    // txid doesn't commit to witness data, so it's a hash of legacy serialization
+    let mut base = bytes[..4].to_vec();
+    base.extend_from_slice(&bytes[body_start..body_end]);
+    base.extend_from_slice(&bytes[bytes.len() - 4..]);
+    let weight = base.len() * 3 + bytes.len();
+    Ok(DecodedBitcoinTransaction {
+        txid: TxHash::new(double_sha256_hex(&base)),
+        wtxid: double_sha256_hex(&bytes),
+        version,
+        locktime,
+        size: bytes.len(),
+        vsize: (weight + 3) / 4,
+        weight,
+        inputs,
+        outputs,
+        fee: None,
+        fee_rate: None,
+    })
+}
+
+fn double_sha256_hex(bytes: &[u8]) -> String {
+    let mut hash = sha256(&sha256(bytes));
+    hash.reverse();
+    bytes_to_hex(&hash)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn peek(&self, len: usize) -> Option<&'a [u8]> {
+        self.bytes.get(self.pos..self.pos + len)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .peek(len)
+            .ok_or_else(|| format!("transaction is truncated at byte {}", self.pos))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn uint(&mut self, len: usize) -> Result<u64, String> {
+        // little endian
+        let bytes = self.take(len)?;
+        Ok(bytes.iter().rev().fold(0, |acc, byte| (acc << 8) | u64::from(*byte)))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        self.uint(4).map(|value| value as u32)
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        self.uint(8)
+    }
+
+    fn var_int(&mut self) -> Result<u64, String> {
+        match self.uint(1)? {
+            0xfd => self.uint(2),
+            0xfe => self.uint(4),
+            0xff => self.uint(8),
+            value => Ok(value),
+        }
+    }
+
+    fn var_bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.var_int()?;
+        if len > self.bytes.len() as u64 {
+            return Err(format!("transaction is truncated at byte {}", self.pos));
+        }
+        self.take(len as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    fn raw(hex: &str) -> RawBitcoinTransaction {
+        serde_json::from_value(json!(hex)).unwrap()
+    }
+
+    const SEGWIT_TX: &str = "02000000000101aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa010100000000fdffffff03a08601000000000016001411111111111111111111111111111111111111110000000000000000066a04deadbeeff4010000000000001976a914222222222222222222222222222222222222222288ac02480000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002100000000000000000000000000000000000000000000000000000000000000000000000000";
+
+    #[test]
+    fn test_decode() {
+        let tx = decode(&raw(SEGWIT_TX)).unwrap();
+        assert_eq!(
+            tx.txid,
+            TxHash::new("050d1c68e3092b8da9c70000a0719522c5a37e5a907dffba994be291e9763c8e".to_string())
+        );
+        assert_eq!(tx.wtxid, "f9a94f7b2411c678b46320fc084c43c1f91aed594dac2116aa01fbd5e179ea3a");
+        assert_eq!((tx.version, tx.locktime), (2, 0));
+        assert_eq!((tx.size, tx.weight, tx.vsize), (241, 634, 159));
+        assert_eq!(
+            tx.inputs,
+            vec![BitcoinTransactionInput {
+                txid: format!("01{}", "aa".repeat(31)),
+                index: 1,
+                sequence: 0xffff_fffd,
+            }]
+        );
+        let values: Vec<_> = tx.outputs.iter().map(|output| output.value.inner()).collect();
+        assert_eq!(values, vec![100_000, 0, 500]);
+        assert!(tx.outputs[1].is_null_data());
+
+        // the same transaction without witness
+        let legacy = "0200000001aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa010100000000fdffffff03a08601000000000016001411111111111111111111111111111111111111110000000000000000066a04deadbeeff4010000000000001976a914222222222222222222222222222222222222222288ac00000000";
+        let tx = decode(&raw(legacy)).unwrap();
+        assert_eq!(
+            tx.txid,
+            TxHash::new("050d1c68e3092b8da9c70000a0719522c5a37e5a907dffba994be291e9763c8e".to_string())
+        );
+        assert_eq!(tx.wtxid, tx.txid.to_string());
+        assert_eq!((tx.size, tx.vsize), (131, 131));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(decode(&raw("0x0200")).unwrap_err(), "transaction is not a hex string");
+        assert_eq!(decode(&raw("0200000000")).unwrap_err(), "transaction has no inputs");
+        assert_eq!(decode(&raw(&SEGWIT_TX[..100])).unwrap_err(), "transaction is truncated at byte 49");
+        assert_eq!(
+            decode(&raw(&format!("{}00", SEGWIT_TX))).unwrap_err(),
+            "transaction has 1 trailing bytes"
+        );
+    }
+}
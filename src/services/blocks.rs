@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use futures::future;
+
+use super::error::*;
+use models::*;
+use prelude::*;
+use storage::Storage;
+
+pub trait BlockService: Send + Sync + 'static {
+    /// Header of block scanned by poller with its published transactions.
+    /// Fails with `ErrorKind::NotFound` if database is not configured or block is not saved
+    fn get_block(&self, currency: Currency, height: u64) -> Box<Future<Item = ScannedBlock, Error = Error> + Send>;
+}
+
+#[derive(Clone)]
+pub struct BlockServiceImpl {
+    storage: Option<Arc<Storage>>,
+}
+
+impl BlockServiceImpl {
+    pub fn new(storage: Option<Arc<Storage>>) -> Self {
+        Self { storage }
+    }
+}
+
+impl BlockService for BlockServiceImpl {
+    fn get_block(&self, currency: Currency, height: u64) -> Box<Future<Item = ScannedBlock, Error = Error> + Send> {
+        let storage = match self.storage {
+            Some(ref storage) => storage.clone(),
+            None => return Box::new(future::err(ectx!(err ErrorContext::Block, ErrorKind::NotFound => currency, height))),
+        };
+        Box::new(
+            storage
+                .get_block(currency, height)
+                .map_err(ectx!(ErrorSource::Storage, ErrorKind::Internal => currency, height))
+                .and_then(move |block| block.ok_or_else(|| ectx!(err ErrorContext::Block, ErrorKind::NotFound => currency, height))),
+        )
+    }
+}
@@ -0,0 +1,54 @@
+use failure::Fail;
+use futures::future;
+
+use super::error::*;
+use models::*;
+use pollers::{BroadcastRegistry, BroadcastTrackerService};
+use prelude::*;
+use utils::log_error;
+
+pub trait BroadcastService: Send + Sync + 'static {
+    /// Starts tracking of posted transaction and publishes its pending status.
+    /// Transaction is already sent at this point, so failures are only logged
+    fn track(&self, broadcast: Broadcast) -> Box<Future<Item = (), Error = Error> + Send>;
+    /// Status of transaction posted through API, fails with `ErrorKind::NotFound` if it's not tracked
+    fn get_broadcast(&self, hash: TxHash) -> Box<Future<Item = Broadcast, Error = Error> + Send>;
+}
+
+#[derive(Clone)]
+pub struct BroadcastServiceImpl {
+    // broadcast tracker runs together with pollers, transactions are not tracked without it
+    tracker: Option<BroadcastTrackerService>,
+    registry: BroadcastRegistry,
+}
+
+impl BroadcastServiceImpl {
+    pub fn new(tracker: Option<BroadcastTrackerService>, registry: BroadcastRegistry) -> Self {
+        Self { tracker, registry }
+    }
+}
+
+impl BroadcastService for BroadcastServiceImpl {
+    fn track(&self, broadcast: Broadcast) -> Box<Future<Item = (), Error = Error> + Send> {
+        let tracker = match self.tracker {
+            Some(ref tracker) => tracker,
+            None => return Box::new(future::ok(())),
+        };
+        let hash = broadcast.hash.clone();
+        Box::new(tracker.track(broadcast).then(move |res| {
+            if let Err(e) = res {
+                let e: Error = ectx!(err e, ErrorSource::BroadcastTracker, ErrorKind::Internal => hash);
+                log_error(&e);
+            }
+            Ok(())
+        }))
+    }
+
+    fn get_broadcast(&self, hash: TxHash) -> Box<Future<Item = Broadcast, Error = Error> + Send> {
+        Box::new(future::result(
+            self.registry
+                .get(&hash)
+                .ok_or_else(|| ectx!(err ErrorContext::Broadcast, ErrorKind::NotFound => hash)),
+        ))
+    }
+}
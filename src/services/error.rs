@@ -1,9 +1,11 @@
+use std::borrow::Cow;
 use std::fmt;
-use std::fmt::Display;
+use std::fmt::{Debug, Display};
 
 use client::ErrorKind as ClientErrorKind;
 use failure::{Backtrace, Context, Fail};
-use validator::ValidationErrors;
+use models::ExecutionError;
+use validator::{ValidationError, ValidationErrors};
 
 #[derive(Debug)]
 pub struct Error {
@@ -34,6 +36,14 @@ pub enum ErrorSource {
     Client,
     #[fail(display = "service error source - rabbit publisher")]
     Publisher,
+    #[fail(display = "service error source - history index")]
+    History,
+    #[fail(display = "service error source - backfill runner")]
+    Backfill,
+    #[fail(display = "service error source - storage")]
+    Storage,
+    #[fail(display = "service error source - broadcast tracker")]
+    BroadcastTracker,
 }
 
 #[allow(dead_code)]
@@ -47,6 +57,20 @@ pub enum ErrorContext {
     NoWallet,
     #[fail(display = "service error context - signing transaction")]
     SigningTransaction,
+    #[fail(display = "service error context - transaction is rejected before sending it to blockchain")]
+    TransactionValidation,
+    #[fail(display = "service error context - transaction is not tracked by broadcast tracker")]
+    Broadcast,
+    #[fail(display = "service error context - call is rejected by node")]
+    Execution,
+    #[fail(display = "service error context - balances can't be queried at block")]
+    Balance,
+    #[fail(display = "service error context - history index is not configured")]
+    History,
+    #[fail(display = "service error context - backfill is not found or can't be started")]
+    Backfill,
+    #[fail(display = "service error context - database is not configured or block is not saved")]
+    Block,
 }
 
 derive_error_impls!();
@@ -67,3 +91,32 @@ impl From<ClientErrorKind> for ErrorKind {
         }
     }
 }
+
+/// Validation error with a human readable message, rendered in 422 responses
+pub fn validation_error(code: &'static str, message: String) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::from(message));
+    error
+}
+
+/// Call rejected by node, rendered as validation error of `call` field
+pub fn execution_error<T: Debug>(call: T, error: ExecutionError) -> Error {
+    let mut errors = ValidationErrors::new();
+    errors.add("call", validation_error("execution_error", error.message.clone()));
+    ectx!(err ErrorContext::Execution, ErrorKind::InvalidInput(errors) => call, error)
+}
+
+/// Rejection of batch request with more than `max` addresses
+pub fn too_many_addresses(count: usize, max: usize) -> Error {
+    let mut errors = ValidationErrors::new();
+    let message = format!("at most {} addresses are allowed, got {}", max, count);
+    errors.add("addresses", validation_error("too_many_addresses", message));
+    ectx!(err ErrorContext::Balance, ErrorKind::InvalidInput(errors) => count, max)
+}
+
+/// Rejection of raw transaction with a single validation error
+pub fn invalid_tx<T: Debug>(tx: T, field: &'static str, code: &'static str, message: String) -> Error {
+    let mut errors = ValidationErrors::new();
+    errors.add(field, validation_error(code, message));
+    ectx!(err ErrorContext::TransactionValidation, ErrorKind::InvalidInput(errors) => tx)
+}
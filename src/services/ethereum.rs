@@ -1,46 +1,419 @@
 use std::sync::Arc;
 
+use failure::Fail;
+use futures::future;
+use futures::stream;
+use validator::ValidationErrors;
+
 use super::error::*;
-use client::EthereumClient;
+use super::ethereum_transaction;
+use client::{bytes_to_hex, EthereumClient};
+use config::BalanceBatch;
 use models::*;
 use prelude::*;
+use utils::{log_error, parse_hex};
+
+/// Selector of ERC-20 `transfer(address,uint256)`
+const ERC20_TRANSFER_SELECTOR: &str = "a9059cbb";
 
 pub trait EthereumService: Send + Sync + 'static {
-    fn get_nonce(&self, address: EthereumAddress) -> Box<Future<Item = u64, Error = Error> + Send>;
-    fn get_eth_balance(&self, address: EthereumAddress) -> Box<Future<Item = Amount, Error = Error> + Send>;
-    fn get_stq_balance(&self, address: EthereumAddress) -> Box<Future<Item = Amount, Error = Error> + Send>;
+    fn get_nonce(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = u64, Error = Error> + Send>;
+    /// Balance at block, fails with `ErrorKind::InvalidInput` if block is not mined yet
+    fn get_eth_balance(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = Amount, Error = Error> + Send>;
+    fn get_stq_balance(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = Amount, Error = Error> + Send>;
+    /// Eth or stq balances of addresses at a single block, `BlockTag::Latest` is resolved to the current block number.
+    /// Addresses that couldn't be queried have errors instead of balances
+    fn get_balances(
+        &self,
+        currency: Currency,
+        addresses: Vec<EthereumAddress>,
+        block: BlockTag,
+    ) -> Box<Future<Item = Balances, Error = Error> + Send>;
     fn send_raw_tx(&self, tx: RawEthereumTransaction) -> Box<Future<Item = TxHash, Error = Error> + Send>;
+    /// Decodes raw transaction and checks that it can be mined: chain id, signature, nonce and balance of sender.
+    /// Fails with `ErrorKind::InvalidInput` otherwise, so that the transaction is not sent to blockchain
+    fn validate_raw_tx(&self, tx: RawEthereumTransaction) -> Box<Future<Item = DecodedEthereumTransaction, Error = Error> + Send>;
+    fn get_transaction(&self, hash: TxHash) -> Box<Future<Item = BlockchainTransaction, Error = Error> + Send>;
+    /// Gas used by call, fails with `ErrorKind::InvalidInput` if node rejects it, e.g. because execution reverts
+    fn estimate_gas(&self, call: EthereumCall) -> Box<Future<Item = Amount, Error = Error> + Send>;
+    /// Gas used by transfer of ERC-20 `token`, with call data of the transfer
+    fn estimate_erc20_transfer_gas(
+        &self,
+        token: EthereumAddress,
+        from: EthereumAddress,
+        to: EthereumAddress,
+        value: Amount,
+    ) -> Box<Future<Item = Erc20TransferEstimate, Error = Error> + Send>;
+    /// Hex encoded return data of read-only call, fails with `ErrorKind::InvalidInput` if node rejects it
+    fn call(&self, call: EthereumCall, block: BlockTag) -> Box<Future<Item = String, Error = Error> + Send>;
+    fn get_transaction_status(&self, hash: TxHash) -> Box<Future<Item = TransactionStatus, Error = Error> + Send>;
+    fn get_erc20_transfers(&self, hash: TxHash) -> Box<Future<Item = Vec<BlockchainTransaction>, Error = Error> + Send>;
 }
 
 #[derive(Clone)]
 pub struct EthereumServiceImpl {
     client: Arc<EthereumClient>,
+    chain_id: u64,
+    balances: BalanceBatch,
 }
 
 impl EthereumServiceImpl {
-    pub fn new(client: Arc<EthereumClient>) -> Self {
-        Self { client }
+    pub fn new(client: Arc<EthereumClient>, chain_id: u64, balances: BalanceBatch) -> Self {
+        Self {
+            client,
+            chain_id,
+            balances,
+        }
+    }
+
+    /// Number of block balances are queried at. Node fails on blocks that are not mined yet
+    /// and state at `pending` block changes between requests, so both are rejected
+    fn resolve_block(&self, block: BlockTag) -> Box<Future<Item = u64, Error = Error> + Send> {
+        if block == BlockTag::Pending {
+            let message = "balances can't be queried at pending block, use latest or block number".to_string();
+            return Box::new(future::err(invalid_block(block, "pending_block", message)));
+        }
+        Box::new(
+            self.client
+                .get_block_number()
+                .map_err(ectx!(convert => block))
+                .and_then(move |current| match block {
+                    BlockTag::Number(number) if number > current => {
+                        let message = format!("block {} is not mined yet, the latest block is {}", number, current);
+                        Err(invalid_block(block, "block_not_mined", message))
+                    }
+                    BlockTag::Number(number) => Ok(number),
+                    _ => Ok(current),
+                }),
+        )
+    }
+
+    fn get_balance(
+        &self,
+        currency: Currency,
+        address: EthereumAddress,
+        block: BlockTag,
+    ) -> Box<Future<Item = Amount, Error = Error> + Send> {
+        let balance = self.query_balance(currency, address, block);
+        match block {
+            BlockTag::Number(_) => Box::new(self.resolve_block(block).and_then(|_| balance)),
+            _ => balance,
+        }
+    }
+
+    /// Balance at block without checking that the block is mined
+    fn query_balance(
+        &self,
+        currency: Currency,
+        address: EthereumAddress,
+        block: BlockTag,
+    ) -> Box<Future<Item = Amount, Error = Error> + Send> {
+        let balance = match currency {
+            Currency::Eth => self.client.get_eth_balance(address.clone(), block),
+            Currency::Stq => self.client.get_stq_balance(address.clone(), block),
+            Currency::Btc => {
+                return Box::new(future::err(
+                    ectx!(err ErrorContext::Balance, ErrorKind::Internal => currency, address, block),
+                ))
+            }
+        };
+        Box::new(balance.map_err(ectx!(convert => currency, address, block)))
     }
 }
 
 impl EthereumService for EthereumServiceImpl {
-    fn get_eth_balance(&self, address: EthereumAddress) -> Box<Future<Item = Amount, Error = Error> + Send> {
-        let address_clone = address.clone();
-        Box::new(self.client.get_eth_balance(address).map_err(ectx!(convert => address_clone)))
+    fn get_eth_balance(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = Amount, Error = Error> + Send> {
+        self.get_balance(Currency::Eth, address, block)
     }
 
-    fn get_stq_balance(&self, address: EthereumAddress) -> Box<Future<Item = Amount, Error = Error> + Send> {
-        let address_clone = address.clone();
-        Box::new(self.client.get_stq_balance(address).map_err(ectx!(convert => address_clone)))
+    fn get_stq_balance(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = Amount, Error = Error> + Send> {
+        self.get_balance(Currency::Stq, address, block)
+    }
+
+    fn get_balances(
+        &self,
+        currency: Currency,
+        addresses: Vec<EthereumAddress>,
+        block: BlockTag,
+    ) -> Box<Future<Item = Balances, Error = Error> + Send> {
+        if addresses.len() > self.balances.max_addresses {
+            return Box::new(future::err(too_many_addresses(addresses.len(), self.balances.max_addresses)));
+        }
+        let client = self.client.clone();
+        let concurrency = self.balances.concurrency.max(1);
+        let chunks: Vec<Vec<EthereumAddress>> = addresses
+            .chunks(self.balances.rpc_batch_size.max(1))
+            .map(|chunk| chunk.to_vec())
+            .collect();
+        Box::new(self.resolve_block(block).and_then(move |block_number| {
+            let block = BlockTag::Number(block_number);
+            stream::iter_ok::<_, Error>(chunks)
+                .map(move |chunk| {
+                    let balances = match currency {
+                        Currency::Stq => client.get_stq_balances(chunk.clone(), block),
+                        _ => client.get_eth_balances(chunk.clone(), block),
+                    };
+                    balances.then(move |res| {
+                        let results = match res {
+                            Ok(results) => results,
+                            // failed batch fails all of its addresses, but not the other batches
+                            Err(e) => {
+                                let e: Error = ectx!(err e, ErrorSource::Client, ErrorKind::Internal => currency, block);
+                                log_error(&e);
+                                vec![Err("failed to query node".to_string()); chunk.len()]
+                            }
+                        };
+                        let balances: Vec<_> = chunk
+                            .into_iter()
+                            .zip(results)
+                            .map(|(address, balance)| AddressBalance {
+                                address: address.into_inner(),
+                                balance,
+                            })
+                            .collect();
+                        Ok(balances)
+                    })
+                })
+                .buffered(concurrency)
+                .concat2()
+                .map(move |balances| Balances {
+                    block_number: Some(block_number),
+                    balances,
+                })
+        }))
     }
 
-    fn get_nonce(&self, address: EthereumAddress) -> Box<Future<Item = u64, Error = Error> + Send> {
+    fn get_nonce(&self, address: EthereumAddress, block: BlockTag) -> Box<Future<Item = u64, Error = Error> + Send> {
         let address_clone = address.clone();
-        Box::new(self.client.get_nonce(address).map_err(ectx!(convert => address_clone)))
+        Box::new(
+            self.client
+                .get_nonce(address, block)
+                .map_err(ectx!(convert => address_clone, block)),
+        )
     }
 
     fn send_raw_tx(&self, tx: RawEthereumTransaction) -> Box<Future<Item = TxHash, Error = Error> + Send> {
         let tx_clone = tx.clone();
         Box::new(self.client.send_raw_tx(tx).map_err(ectx!(convert => tx_clone)))
     }
+
+    fn validate_raw_tx(&self, tx: RawEthereumTransaction) -> Box<Future<Item = DecodedEthereumTransaction, Error = Error> + Send> {
+        let decoded = match ethereum_transaction::decode(&tx) {
+            Ok(decoded) => decoded,
+            Err(message) => return Box::new(future::err(invalid_tx(tx, "raw", "invalid_transaction", message))),
+        };
+        match decoded.chain_id {
+            Some(chain_id) if chain_id == self.chain_id => (),
+            Some(chain_id) => {
+                let message = format!("transaction is signed for chain {}, expected {}", chain_id, self.chain_id);
+                return Box::new(future::err(invalid_tx(tx, "chainId", "chain_id_mismatch", message)));
+            }
+            None => {
+                let message = format!("transaction is signed without replay protection, expected chain {}", self.chain_id);
+                return Box::new(future::err(invalid_tx(tx, "chainId", "chain_id_missing", message)));
+            }
+        }
+        let max_cost = match decoded.max_cost() {
+            Some(max_cost) => max_cost,
+            None => {
+                return Box::new(future::err(invalid_tx(
+                    tx,
+                    "value",
+                    "overflow",
+                    "value + max fee overflows".to_string(),
+                )))
+            }
+        };
+        let tx_clone = tx.clone();
+        Box::new(
+            self.client
+                // latest state, so that transactions in node's mempool can be replaced with higher fees.
+                // Node itself checks fee bump of replacement and total cost of transactions in its mempool
+                .get_nonce(decoded.from.clone(), BlockTag::Latest)
+                .join(self.client.get_eth_balance(decoded.from.clone(), BlockTag::Latest))
+                .map_err(ectx!(convert => tx_clone))
+                .and_then(move |(nonce, balance)| {
+                    let mut errors = ValidationErrors::new();
+                    if decoded.nonce < nonce {
+                        let message = format!(
+                            "nonce {} is already used by a mined transaction, next nonce of {} is {}",
+                            decoded.nonce, decoded.from, nonce
+                        );
+                        errors.add("nonce", validation_error("nonce_too_low", message));
+                    }
+                    if balance < max_cost {
+                        let message = format!(
+                            "balance {} of {} is lower than value + max fee {}",
+                            balance.inner(),
+                            decoded.from,
+                            max_cost.inner()
+                        );
+                        errors.add("balance", validation_error("insufficient_funds", message));
+                    }
+                    if errors.is_empty() {
+                        Ok(decoded)
+                    } else {
+                        Err(ectx!(err ErrorContext::TransactionValidation, ErrorKind::InvalidInput(errors) => tx))
+                    }
+                }),
+        )
+    }
+
+    fn get_transaction(&self, hash: TxHash) -> Box<Future<Item = BlockchainTransaction, Error = Error> + Send> {
+        let hash_clone = hash.clone();
+        Box::new(
+            self.client
+                .get_eth_transaction(hash.into_inner())
+                .map_err(ectx!(convert => hash_clone)),
+        )
+    }
+
+    fn estimate_gas(&self, call: EthereumCall) -> Box<Future<Item = Amount, Error = Error> + Send> {
+        if let Err(e) = validate_call(&call) {
+            return Box::new(future::err(e));
+        }
+        let call_clone = call.clone();
+        Box::new(
+            self.client
+                .estimate_gas(call.clone())
+                .map_err(ectx!(convert => call_clone))
+                .and_then(move |res| res.map_err(|e| execution_error(call, e))),
+        )
+    }
+
+    fn estimate_erc20_transfer_gas(
+        &self,
+        token: EthereumAddress,
+        from: EthereumAddress,
+        to: EthereumAddress,
+        value: Amount,
+    ) -> Box<Future<Item = Erc20TransferEstimate, Error = Error> + Send> {
+        let data = match erc20_transfer_data(&to, value) {
+            Some(data) => data,
+            None => return Box::new(future::err(invalid_address("to", to))),
+        };
+        let call = EthereumCall {
+            from: Some(from),
+            to: Some(token),
+            value: None,
+            data: Some(data.clone()),
+        };
+        Box::new(self.estimate_gas(call).map(|gas| Erc20TransferEstimate { gas, data }))
+    }
+
+    fn call(&self, call: EthereumCall, block: BlockTag) -> Box<Future<Item = String, Error = Error> + Send> {
+        if let Err(e) = validate_call(&call) {
+            return Box::new(future::err(e));
+        }
+        let call_clone = call.clone();
+        Box::new(
+            self.client
+                .call(call.clone(), block)
+                .map_err(ectx!(convert => call_clone, block))
+                .and_then(move |res| res.map_err(|e| execution_error(call, e))),
+        )
+    }
+
+    fn get_transaction_status(&self, hash: TxHash) -> Box<Future<Item = TransactionStatus, Error = Error> + Send> {
+        let hash_clone = hash.clone();
+        Box::new(
+            self.client
+                .get_eth_transaction_status(hash.into_inner())
+                .map_err(ectx!(convert => hash_clone)),
+        )
+    }
+
+    fn get_erc20_transfers(&self, hash: TxHash) -> Box<Future<Item = Vec<BlockchainTransaction>, Error = Error> + Send> {
+        let hash_clone = hash.clone();
+        Box::new(
+            self.client
+                .get_stq_transactions(hash.into_inner())
+                .collect()
+                .map_err(ectx!(convert => hash_clone)),
+        )
+    }
+}
+
+/// Checks that call data is hex, other fields are checked by node
+fn validate_call(call: &EthereumCall) -> Result<(), Error> {
+    match call.data {
+        Some(ref data) if parse_hex(data).is_none() => {
+            let message = "data is not a hex string without 0x prefix".to_string();
+            let mut errors = ValidationErrors::new();
+            errors.add("data", validation_error("invalid_hex", message));
+            Err(ectx!(err ErrorContext::Execution, ErrorKind::InvalidInput(errors) => call))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn invalid_block(block: BlockTag, code: &'static str, message: String) -> Error {
+    let mut errors = ValidationErrors::new();
+    errors.add("block", validation_error(code, message));
+    ectx!(err ErrorContext::Balance, ErrorKind::InvalidInput(errors) => block)
+}
+
+fn invalid_address(field: &'static str, address: EthereumAddress) -> Error {
+    let mut errors = ValidationErrors::new();
+    errors.add(
+        field,
+        validation_error("invalid_address", format!("{} is not a hex encoded address", address)),
+    );
+    ectx!(err ErrorContext::Execution, ErrorKind::InvalidInput(errors) => address)
+}
+
+/// ABI encoded call of ERC-20 `transfer(address,uint256)`, `None` if address is not valid
+fn erc20_transfer_data(to: &EthereumAddress, value: Amount) -> Option<String> {
+    let address = parse_hex(&to.to_string()).filter(|bytes| bytes.len() == 20)?;
+    Some(format!(
+        "{}{:0>64}{:064x}",
+        ERC20_TRANSFER_SELECTOR,
+        bytes_to_hex(&address),
+        value.inner()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mocks::EthereumClientMock;
+    use serde_json;
+
+    #[test]
+    fn test_erc20_transfer_data() {
+        let to = EthereumAddress::new("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f".to_string());
+        assert_eq!(
+            erc20_transfer_data(&to, Amount::new(1_000_000_000_000_000_000)).unwrap(),
+            "a9059cbb0000000000000000000000009d8a62f656a8d1615c1294fd71e9cfb3e4855a4f\
+             0000000000000000000000000000000000000000000000000de0b6b3a7640000"
+        );
+        assert_eq!(
+            erc20_transfer_data(&EthereumAddress::new("0x9d8a".to_string()), Amount::new(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_validate_raw_tx_nonce() {
+        // example of EIP-155 with nonce 9, https://eips.ethereum.org/EIPS/eip-155
+        let raw: RawEthereumTransaction = serde_json::from_value(json!(
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        )).unwrap();
+        let sender = EthereumAddress::new("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f".to_string());
+        let client = EthereumClientMock::new();
+        client.set_balance(Currency::Eth, sender.clone(), Amount::new(2_000_000_000_000_000_000));
+        let service = EthereumServiceImpl::new(Arc::new(client.clone()), 1, Default::default());
+
+        // transaction with the same nonce may be in mempool, it's replaced if the fee is high enough for node
+        client.set_nonce(sender.clone(), 9);
+        assert_eq!(service.validate_raw_tx(raw.clone()).wait().unwrap().nonce, 9);
+        assert_eq!(client.calls("get_nonce"), vec![json!([sender, "latest"])]);
+
+        client.set_nonce(sender, 10);
+        let err = service.validate_raw_tx(raw).wait().unwrap_err();
+        match err.kind() {
+            ErrorKind::InvalidInput(errors) => assert!(errors.field_errors().contains_key("nonce")),
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
 }
@@ -0,0 +1,365 @@
+//! Decoding of raw signed ethereum transactions: legacy (with or without EIP-155 replay protection),
+//! EIP-2930 and EIP-1559 typed transactions.
+
+use std::iter;
+
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcPoint, PointConversionForm};
+use openssl::error::ErrorStack;
+use openssl::nid::Nid;
+use rlp::{RlpStream, UntrustedRlp};
+use tiny_keccak::keccak256;
+
+use client::bytes_to_hex;
+use models::*;
+use utils::parse_hex;
+
+const EIP2930_TX_TYPE: u8 = 0x01;
+const EIP1559_TX_TYPE: u8 = 0x02;
+
+/// Decodes hex encoded signed transaction and recovers its sender. Error describes why the transaction is invalid
+pub fn decode(raw: &RawEthereumTransaction) -> Result<DecodedEthereumTransaction, String> {
+    let bytes = parse_hex(&raw.to_string()).ok_or_else(|| "transaction is not a hex string".to_string())?;
+    let hash = TxHash::new(bytes_to_hex(&keccak256(&bytes)));
+    match bytes.first() {
+        None => Err("transaction is empty".to_string()),
+        Some(&EIP2930_TX_TYPE) => decode_typed(EthereumTransactionType::Eip2930, &bytes, hash),
+        Some(&EIP1559_TX_TYPE) => decode_typed(EthereumTransactionType::Eip1559, &bytes, hash),
+        // legacy transactions are rlp lists
+        Some(&first) if first >= 0xc0 => decode_legacy(&bytes, hash),
+        Some(first) => Err(format!("unsupported transaction type {}", first)),
+    }
+}
+
+/// `rlp([nonce, gasPrice, gasLimit, to, value, data, v, r, s])`
+fn decode_legacy(bytes: &[u8], hash: TxHash) -> Result<DecodedEthereumTransaction, String> {
+    let rlp = UntrustedRlp::new(bytes);
+    check_list(&rlp, 9)?;
+    // EIP-155: v = chainId * 2 + 35 + parity, otherwise v = 27 + parity
+    let (chain_id, parity) = match u64_at(&rlp, 6, "v")? {
+        v @ 27 | v @ 28 => (None, v - 27),
+        v if v >= 35 => (Some((v - 35) / 2), (v - 35) % 2),
+        v => return Err(format!("invalid signature v {}", v)),
+    };
+    let mut stream = RlpStream::new_list(if chain_id.is_some() { 9 } else { 6 });
+    for index in 0..6 {
+        stream.append_raw(field(&rlp, index)?.as_raw(), 1);
+    }
+    if let Some(chain_id) = chain_id {
+        stream.append(&chain_id).append_empty_data().append_empty_data();
+    }
+    let signing_hash = keccak256(&stream.out());
+    let from = recover_sender(&signing_hash, parity as u8, bytes_at(&rlp, 7, "r")?, bytes_at(&rlp, 8, "s")?)?;
+    Ok(DecodedEthereumTransaction {
+        hash,
+        tx_type: EthereumTransactionType::Legacy,
+        chain_id,
+        nonce: u64_at(&rlp, 0, "nonce")?,
+        from,
+        to: address_at(&rlp, 3, "to")?,
+        value: amount_at(&rlp, 4, "value")?,
+        gas_limit: amount_at(&rlp, 2, "gasLimit")?,
+        gas_price: Some(amount_at(&rlp, 1, "gasPrice")?),
+        max_fee_per_gas: None,
+        max_priority_fee_per_gas: None,
+        data: bytes_to_hex(bytes_at(&rlp, 5, "data")?),
+    })
+}
+
+/// EIP-2930: `0x01 || rlp([chainId, nonce, gasPrice, gasLimit, to, value, data, accessList, yParity, r, s])`
+///
+/// EIP-1559: `0x02 || rlp([chainId, nonce, maxPriorityFeePerGas, maxFeePerGas, gasLimit, to, value, data, accessList, yParity, r, s])`
+fn decode_typed(tx_type: EthereumTransactionType, bytes: &[u8], hash: TxHash) -> Result<DecodedEthereumTransaction, String> {
+    let rlp = UntrustedRlp::new(&bytes[1..]);
+    let fee_fields = if tx_type == EthereumTransactionType::Eip1559 { 2 } else { 1 };
+    let len = 10 + fee_fields;
+    check_list(&rlp, len)?;
+    if !field(&rlp, len - 4)?.is_list() {
+        return Err("accessList is not a list".to_string());
+    }
+    // signature covers type and all fields except for the signature itself
+    let mut stream = RlpStream::new_list(len - 3);
+    for index in 0..len - 3 {
+        stream.append_raw(field(&rlp, index)?.as_raw(), 1);
+    }
+    let mut payload = vec![bytes[0]];
+    payload.extend_from_slice(&stream.out());
+    let parity = u64_at(&rlp, len - 3, "yParity")?;
+    if parity > 1 {
+        return Err(format!("invalid signature yParity {}", parity));
+    }
+    let from = recover_sender(
+        &keccak256(&payload),
+        parity as u8,
+        bytes_at(&rlp, len - 2, "r")?,
+        bytes_at(&rlp, len - 1, "s")?,
+    )?;
+    let (gas_price, max_priority_fee_per_gas, max_fee_per_gas) = if tx_type == EthereumTransactionType::Eip1559 {
+        (
+            None,
+            Some(amount_at(&rlp, 2, "maxPriorityFeePerGas")?),
+            Some(amount_at(&rlp, 3, "maxFeePerGas")?),
+        )
+    } else {
+        (Some(amount_at(&rlp, 2, "gasPrice")?), None, None)
+    };
+    // index of gasLimit, the rest of fields are the same for both types
+    let gas_limit = 2 + fee_fields;
+    Ok(DecodedEthereumTransaction {
+        hash,
+        tx_type,
+        chain_id: Some(u64_at(&rlp, 0, "chainId")?),
+        nonce: u64_at(&rlp, 1, "nonce")?,
+        from,
+        to: address_at(&rlp, gas_limit + 1, "to")?,
+        value: amount_at(&rlp, gas_limit + 2, "value")?,
+        gas_limit: amount_at(&rlp, gas_limit, "gasLimit")?,
+        gas_price,
+        max_fee_per_gas,
+        max_priority_fee_per_gas,
+        data: bytes_to_hex(bytes_at(&rlp, gas_limit + 3, "data")?),
+    })
+}
+
+fn check_list(rlp: &UntrustedRlp, len: usize) -> Result<(), String> {
+    if !rlp.is_list() {
+        return Err("transaction is not an rlp list".to_string());
+    }
+    match rlp.item_count() {
+        Ok(count) if count == len => Ok(()),
+        Ok(count) => Err(format!("transaction has {} fields, expected {}", count, len)),
+        Err(e) => Err(format!("invalid rlp: {}", e)),
+    }
+}
+
+fn field<'a>(rlp: &UntrustedRlp<'a>, index: usize) -> Result<UntrustedRlp<'a>, String> {
+    rlp.at(index).map_err(|e| format!("invalid rlp: {}", e))
+}
+
+fn bytes_at<'a>(rlp: &UntrustedRlp<'a>, index: usize, name: &str) -> Result<&'a [u8], String> {
+    field(rlp, index)?.data().map_err(|_| format!("{} is not a byte string", name))
+}
+
+fn uint_at(rlp: &UntrustedRlp, index: usize, name: &str) -> Result<u128, String> {
+    let bytes = bytes_at(rlp, index, name)?;
+    if bytes.first() == Some(&0) {
+        return Err(format!("{} has leading zeros", name));
+    }
+    if bytes.len() > 16 {
+        return Err(format!("{} is too large", name));
+    }
+    Ok(bytes.iter().fold(0, |acc, byte| (acc << 8) | u128::from(*byte)))
+}
+
+fn u64_at(rlp: &UntrustedRlp, index: usize, name: &str) -> Result<u64, String> {
+    let value = uint_at(rlp, index, name)?;
+    if value > u128::from(u64::max_value()) {
+        return Err(format!("{} is too large", name));
+    }
+    Ok(value as u64)
+}
+
+fn amount_at(rlp: &UntrustedRlp, index: usize, name: &str) -> Result<Amount, String> {
+    uint_at(rlp, index, name).map(Amount::new)
+}
+
+fn address_at(rlp: &UntrustedRlp, index: usize, name: &str) -> Result<Option<EthereumAddress>, String> {
+    match bytes_at(rlp, index, name)? {
+        bytes if bytes.is_empty() => Ok(None),
+        bytes if bytes.len() == 20 => Ok(Some(EthereumAddress::new(bytes_to_hex(bytes)))),
+        _ => Err(format!("{} is not an address", name)),
+    }
+}
+
+/// Address of the key that signed `hash`, i.e. the last 20 bytes of keccak256 of its uncompressed public key
+fn recover_sender(hash: &[u8; 32], parity: u8, r: &[u8], s: &[u8]) -> Result<EthereumAddress, String> {
+    let public_key = recover_public_key(hash, parity, r, s)
+        .map_err(|e| format!("invalid signature: {}", e))?
+        .ok_or_else(|| "invalid signature".to_string())?;
+    // uncompressed key is prefixed with 0x04
+    Ok(EthereumAddress::new(bytes_to_hex(&keccak256(&public_key[1..])[12..])))
+}
+
+/// ECDSA public key recovery on secp256k1: `Q = r⁻¹(sR - eG)`, where `R` is the point with x = `r` and y of `parity`
+fn recover_public_key(hash: &[u8; 32], parity: u8, r: &[u8], s: &[u8]) -> Result<Option<Vec<u8>>, ErrorStack> {
+    let group = EcGroup::from_curve_name(Nid::SECP256K1)?;
+    let mut ctx = BigNumContext::new()?;
+    let mut order = BigNum::new()?;
+    group.order(&mut order, &mut ctx)?;
+    let mut half_order = BigNum::new()?;
+    half_order.rshift1(&order)?;
+    let zero = BigNum::new()?;
+    let r_num = BigNum::from_slice(r)?;
+    let s_num = BigNum::from_slice(s)?;
+    // high s values are forbidden since homestead, see EIP-2
+    if r.len() > 32 || r_num <= zero || r_num >= order || s_num <= zero || s_num > half_order {
+        return Ok(None);
+    }
+    let mut compressed = vec![0x02 | parity];
+    compressed.extend(iter::repeat(0).take(32 - r.len()));
+    compressed.extend_from_slice(r);
+    let point_r = match EcPoint::from_bytes(&group, &compressed, &mut ctx) {
+        Ok(point) => point,
+        // r is not an x coordinate of any point on the curve
+        Err(_) => return Ok(None),
+    };
+    let e = BigNum::from_slice(hash)?;
+    let mut r_inv = BigNum::new()?;
+    r_inv.mod_inverse(&r_num, &order, &mut ctx)?;
+    let mut e_neg = BigNum::new()?;
+    e_neg.mod_sub(&zero, &e, &order, &mut ctx)?;
+    let mut u1 = BigNum::new()?;
+    u1.mod_mul(&e_neg, &r_inv, &order, &mut ctx)?;
+    let mut u2 = BigNum::new()?;
+    u2.mod_mul(&s_num, &r_inv, &order, &mut ctx)?;
+    // Q = u1 * G + u2 * R
+    let mut public_key = EcPoint::new(&group)?;
+    public_key.mul_full(&group, &u1, &point_r, &u2, &mut ctx)?;
+    if public_key.is_infinity(&group) {
+        return Ok(None);
+    }
+    public_key.to_bytes(&group, PointConversionForm::UNCOMPRESSED, &mut ctx).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::ec::EcKey;
+    use openssl::ecdsa::EcdsaSig;
+    use serde_json;
+
+    /// Private key of the example in EIP-155, https://eips.ethereum.org/EIPS/eip-155
+    const EIP155_PRIVATE_KEY: &str = "4646464646464646464646464646464646464646464646464646464646464646";
+    /// Sender of the example in EIP-155
+    const EIP155_SENDER: &str = "9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f";
+
+    fn raw(hex: &str) -> RawEthereumTransaction {
+        serde_json::from_value(json!(hex)).unwrap()
+    }
+
+    /// Signs typed transaction with the key of EIP-155 example. EIP-2930 and EIP-1559 don't publish signed examples,
+    /// so these transactions are checked by recovering the sender of a published key instead of fixed hex strings.
+    /// `append_fields` appends `fields_count` fields of the transaction without the signature.
+    fn sign_typed<F: Fn(&mut RlpStream)>(tx_type: u8, fields_count: usize, append_fields: F) -> RawEthereumTransaction {
+        let mut unsigned = RlpStream::new_list(fields_count);
+        append_fields(&mut unsigned);
+        let mut payload = vec![tx_type];
+        payload.extend_from_slice(&unsigned.out());
+        let hash = keccak256(&payload);
+
+        let group = EcGroup::from_curve_name(Nid::SECP256K1).unwrap();
+        let mut ctx = BigNumContext::new().unwrap();
+        let private_key = BigNum::from_hex_str(EIP155_PRIVATE_KEY).unwrap();
+        let mut public_key = EcPoint::new(&group).unwrap();
+        public_key.mul_generator(&group, &private_key, &ctx).unwrap();
+        let public_key_bytes = public_key.to_bytes(&group, PointConversionForm::UNCOMPRESSED, &mut ctx).unwrap();
+        let key = EcKey::from_private_components(&group, &private_key, &public_key).unwrap();
+        let signature = EcdsaSig::sign(&hash, &key).unwrap();
+        // signatures with high s are invalid, see EIP-2
+        let mut order = BigNum::new().unwrap();
+        group.order(&mut order, &mut ctx).unwrap();
+        let mut half_order = BigNum::new().unwrap();
+        half_order.rshift1(&order).unwrap();
+        let mut low_s = BigNum::new().unwrap();
+        low_s.checked_sub(&order, signature.s()).unwrap();
+        let s = if signature.s() > &*half_order {
+            low_s.to_vec()
+        } else {
+            signature.s().to_vec()
+        };
+        let r = signature.r().to_vec();
+        let parity = (0..2u8)
+            .find(|&parity| recover_public_key(&hash, parity, &r, &s).unwrap() == Some(public_key_bytes.clone()))
+            .unwrap();
+
+        let mut signed = RlpStream::new_list(fields_count + 3);
+        append_fields(&mut signed);
+        signed.append(&(parity as u64)).append(&r).append(&s);
+        let mut bytes = vec![tx_type];
+        bytes.extend_from_slice(&signed.out());
+        raw(&bytes_to_hex(&bytes))
+    }
+
+    #[test]
+    fn test_decode() {
+        // example of EIP-155, https://eips.ethereum.org/EIPS/eip-155
+        let tx = decode(&raw("f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83")).unwrap();
+        assert_eq!(tx.tx_type, EthereumTransactionType::Legacy);
+        assert_eq!(
+            tx.hash,
+            TxHash::new("33469b22e9f636356c4160a87eb19df52b7412e8eac32a4a55ffe88ea8350788".to_string())
+        );
+        assert_eq!(tx.chain_id, Some(1));
+        assert_eq!(tx.nonce, 9);
+        assert_eq!(tx.from, EthereumAddress::new(EIP155_SENDER.to_string()));
+        assert_eq!(tx.to, Some(EthereumAddress::new("35".repeat(20))));
+        assert_eq!(tx.gas_price, Some(Amount::new(20_000_000_000)));
+        assert_eq!(tx.max_cost(), Some(Amount::new(1_000_420_000_000_000_000)));
+
+        let raw_tx = sign_typed(EIP1559_TX_TYPE, 9, |stream| {
+            // [chainId, nonce, maxPriorityFeePerGas, maxFeePerGas, gasLimit, to, value, data, accessList]
+            stream
+                .append(&11_155_111u64)
+                .append(&7u64)
+                .append(&1_000_000_000u64)
+                .append(&2_000_000_000u64)
+                .append(&21_000u64)
+                .append(&vec![0x35u8; 20])
+                .append(&1_000_000_000_000_000_000u64)
+                .append(&Vec::<u8>::new())
+                .append_raw(&RlpStream::new_list(0).out(), 1);
+        });
+        let tx = decode(&raw_tx).unwrap();
+        assert_eq!(tx.tx_type, EthereumTransactionType::Eip1559);
+        assert_eq!(
+            tx.hash,
+            TxHash::new(bytes_to_hex(&keccak256(&parse_hex(&raw_tx.to_string()).unwrap())))
+        );
+        assert_eq!(tx.chain_id, Some(11_155_111));
+        assert_eq!(tx.nonce, 7);
+        assert_eq!(tx.from, EthereumAddress::new(EIP155_SENDER.to_string()));
+        assert_eq!(tx.to, Some(EthereumAddress::new("35".repeat(20))));
+        assert_eq!(tx.gas_price, None);
+        assert_eq!(tx.max_priority_fee_per_gas, Some(Amount::new(1_000_000_000)));
+        assert_eq!(tx.max_fee_per_gas, Some(Amount::new(2_000_000_000)));
+        assert_eq!(tx.max_cost(), Some(Amount::new(1_000_042_000_000_000_000)));
+
+        // [[address, [storageKey]]]
+        let mut storage_keys = RlpStream::new_list(1);
+        storage_keys.append(&vec![0u8; 32]);
+        let mut entry = RlpStream::new_list(2);
+        entry.append(&vec![0x11u8; 20]).append_raw(&storage_keys.out(), 1);
+        let mut access_list = RlpStream::new_list(1);
+        access_list.append_raw(&entry.out(), 1);
+        let access_list = access_list.out();
+        let tx = decode(&sign_typed(EIP2930_TX_TYPE, 8, |stream| {
+            // [chainId, nonce, gasPrice, gasLimit, to, value, data, accessList]
+            stream
+                .append(&11_155_111u64)
+                .append(&0u64)
+                .append(&3_000_000_000u64)
+                .append(&100_000u64)
+                .append(&Vec::<u8>::new())
+                .append(&0u64)
+                .append(&vec![0x60u8, 0x80])
+                .append_raw(&access_list, 1);
+        }))
+        .unwrap();
+        assert_eq!(tx.tx_type, EthereumTransactionType::Eip2930);
+        assert_eq!(tx.chain_id, Some(11_155_111));
+        assert_eq!(tx.from, EthereumAddress::new(EIP155_SENDER.to_string()));
+        assert_eq!(tx.to, None, "contract deployment");
+        assert_eq!(tx.data, "6080");
+        assert_eq!(tx.gas_price, Some(Amount::new(3_000_000_000)));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(decode(&raw("0xf86c")).unwrap_err(), "transaction is not a hex string");
+        assert_eq!(decode(&raw("03c0")).unwrap_err(), "unsupported transaction type 3");
+        assert_eq!(decode(&raw("c3010203")).unwrap_err(), "transaction has 3 fields, expected 9");
+        // s of EIP-155 example replaced with order - s
+        let high_s = "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a098341627668089e51348fccfb4c7ff31c55912f2d2e47ef09652acf665fad3be";
+        assert_eq!(decode(&raw(high_s)).unwrap_err(), "invalid signature");
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{self, Utc};
+use futures::future;
+use tokio::timer::Timeout;
+
+use super::error::*;
+use client::{BitcoinClient, Error as ClientError, EthereumClient};
+use models::*;
+use pollers::PollerStatus;
+use prelude::*;
+use rabbit::{RabbitConnectionManager, TransactionPublisher};
+
+/// Timeout for a single node check, so that readiness probe answers before its own timeout
+const NODE_CHECK_TIMEOUT_SECS: u64 = 5;
+/// Poller is considered stale if it had no successful ticks during this number of intervals
+const STALE_POLLER_INTERVALS: u32 = 3;
+
+pub trait HealthService: Send + Sync + 'static {
+    /// Check all upstream dependencies. Never fails, failed checks are reported as `HealthStatus::Down`
+    fn readiness(&self) -> Box<Future<Item = Readiness, Error = Error> + Send>;
+}
+
+#[derive(Clone)]
+pub struct HealthServiceImpl {
+    bitcoin_client: Arc<BitcoinClient>,
+    ethereum_client: Arc<EthereumClient>,
+    rabbit_connection_manager: RabbitConnectionManager,
+    // present only when pollers are enabled
+    publisher: Option<Arc<TransactionPublisher>>,
+    poller_status: PollerStatus,
+}
+
+impl HealthServiceImpl {
+    pub fn new(
+        bitcoin_client: Arc<BitcoinClient>,
+        ethereum_client: Arc<EthereumClient>,
+        rabbit_connection_manager: RabbitConnectionManager,
+        publisher: Option<Arc<TransactionPublisher>>,
+        poller_status: PollerStatus,
+    ) -> Self {
+        Self {
+            bitcoin_client,
+            ethereum_client,
+            rabbit_connection_manager,
+            publisher,
+            poller_status,
+        }
+    }
+
+    fn rabbit_health(&self) -> Box<Future<Item = DependencyHealth, Error = Error> + Send> {
+        let connected = self.rabbit_connection_manager.is_healthy();
+        let publisher = match self.publisher {
+            Some(ref publisher) if connected => publisher.clone(),
+            // rabbit is used only for publishing transactions found by pollers
+            _ => {
+                return Box::new(future::ok(DependencyHealth {
+                    status: if connected { HealthStatus::Up } else { HealthStatus::Down },
+                    critical: self.publisher.is_some(),
+                    details: json!({ "connected": connected }),
+                }))
+            }
+        };
+        // heartbeat flag doesn't notice a channel closed by broker, e.g. after a failed publish
+        Box::new(
+            Timeout::new(publisher.check_channel(), Duration::from_secs(NODE_CHECK_TIMEOUT_SECS)).then(|res| {
+                let health = match res {
+                    Ok(()) => DependencyHealth {
+                        status: HealthStatus::Up,
+                        critical: true,
+                        details: json!({ "connected": true, "channelOpen": true }),
+                    },
+                    Err(e) => {
+                        let error = match e.into_inner() {
+                            Some(e) => format!("{}", e),
+                            None => format!("no response in {} seconds", NODE_CHECK_TIMEOUT_SECS),
+                        };
+                        DependencyHealth {
+                            status: HealthStatus::Down,
+                            critical: true,
+                            details: json!({ "connected": true, "channelOpen": false, "error": error }),
+                        }
+                    }
+                };
+                Ok(health)
+            }),
+        )
+    }
+
+    fn pollers_health(&self) -> Vec<(String, DependencyHealth)> {
+        let now = Utc::now();
+        self.poller_status
+            .pollers()
+            .into_iter()
+            .map(|(currency, state)| {
+                let idle = now.signed_duration_since(state.last_success.unwrap_or(state.started_at));
+                let max_idle = chrono::Duration::from_std(state.interval * STALE_POLLER_INTERVALS).unwrap_or(chrono::Duration::max_value());
+                let health = DependencyHealth {
+                    status: if idle <= max_idle { HealthStatus::Up } else { HealthStatus::Down },
+                    critical: true,
+                    details: json!({
+                        "lastSuccessAt": state.last_success,
+                        "secondsSinceLastSuccess": idle.num_seconds(),
+                        "intervalSecs": state.interval.as_secs(),
+                    }),
+                };
+                (format!("{}_poller", currency), health)
+            })
+            .collect()
+    }
+}
+
+impl HealthService for HealthServiceImpl {
+    fn readiness(&self) -> Box<Future<Item = Readiness, Error = Error> + Send> {
+        let pollers = self.pollers_health();
+        Box::new(
+            node_health(self.bitcoin_client.get_node_status())
+                .join3(node_health(self.ethereum_client.get_node_status()), self.rabbit_health())
+                .map(move |(bitcoin, ethereum, rabbit)| {
+                    let mut checks = BTreeMap::new();
+                    checks.insert("bitcoind".to_string(), bitcoin);
+                    checks.insert("ethereum".to_string(), ethereum);
+                    checks.insert("rabbitmq".to_string(), rabbit);
+                    checks.extend(pollers);
+                    Readiness::new(checks)
+                }),
+        )
+    }
+}
+
+fn node_health<F>(status: F) -> impl Future<Item = DependencyHealth, Error = Error> + Send
+where
+    F: Future<Item = NodeStatus, Error = ClientError> + Send,
+{
+    Timeout::new(status, Duration::from_secs(NODE_CHECK_TIMEOUT_SECS)).then(|res| {
+        let health = match res {
+            Ok(status) => DependencyHealth {
+                // syncing node serves outdated data
+                status: if status.syncing { HealthStatus::Down } else { HealthStatus::Up },
+                critical: true,
+                details: json!({
+                    "blockNumber": status.block_number,
+                    "syncing": status.syncing,
+                }),
+            },
+            Err(e) => {
+                let error = match e.into_inner() {
+                    Some(e) => format!("{}", e),
+                    None => format!("no response in {} seconds", NODE_CHECK_TIMEOUT_SECS),
+                };
+                DependencyHealth {
+                    status: HealthStatus::Down,
+                    critical: true,
+                    details: json!({ "error": error }),
+                }
+            }
+        };
+        Ok(health)
+    })
+}
@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use failure::Fail;
+use futures::future;
+use validator::ValidationErrors;
+
+use super::error::*;
+use client::{bytes_to_hex, BitcoinClient, EthereumClient};
+use history::HistoryIndex;
+use models::*;
+use prelude::*;
+use utils::parse_hex;
+
+/// Number of transactions in a page if limit is not requested
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+pub trait HistoryService: Send + Sync + 'static {
+    /// Page of transactions of address, the newest first, with confirmations counted from the current chain tip.
+    /// Fails with `ErrorKind::NotFound` if index is not configured
+    fn get_transactions(
+        &self,
+        currency: Currency,
+        address: String,
+        cursor: Option<String>,
+        limit: Option<usize>,
+    ) -> Box<Future<Item = TransactionsPage, Error = Error> + Send>;
+}
+
+#[derive(Clone)]
+pub struct HistoryServiceImpl {
+    index: Option<Arc<HistoryIndex>>,
+    bitcoin_client: Arc<BitcoinClient>,
+    ethereum_client: Arc<EthereumClient>,
+}
+
+impl HistoryServiceImpl {
+    pub fn new(index: Option<Arc<HistoryIndex>>, bitcoin_client: Arc<BitcoinClient>, ethereum_client: Arc<EthereumClient>) -> Self {
+        Self {
+            index,
+            bitcoin_client,
+            ethereum_client,
+        }
+    }
+
+    /// Confirmations of transaction are this number minus its block number. Bitcoin node counts the block
+    /// of transaction as a confirmation, ethereum client doesn't
+    fn confirmations_base(&self, currency: Currency) -> Box<Future<Item = u64, Error = Error> + Send> {
+        match currency {
+            Currency::Btc => Box::new(
+                self.bitcoin_client
+                    .get_chain_tip()
+                    .map(|tip| tip.height + 1)
+                    .map_err(ectx!(convert => currency)),
+            ),
+            Currency::Eth | Currency::Stq => Box::new(self.ethereum_client.get_block_number().map_err(ectx!(convert => currency))),
+        }
+    }
+}
+
+impl HistoryService for HistoryServiceImpl {
+    fn get_transactions(
+        &self,
+        currency: Currency,
+        address: String,
+        cursor: Option<String>,
+        limit: Option<usize>,
+    ) -> Box<Future<Item = TransactionsPage, Error = Error> + Send> {
+        let index = match self.index {
+            Some(ref index) => index.clone(),
+            None => {
+                return Box::new(future::err(
+                    ectx!(err ErrorContext::History, ErrorKind::NotFound => currency, address),
+                ))
+            }
+        };
+        let limit = limit.unwrap_or(DEFAULT_LIMIT);
+        let mut errors = ValidationErrors::new();
+        if limit == 0 || limit > MAX_LIMIT {
+            let message = format!("limit must be between 1 and {}", MAX_LIMIT);
+            errors.add("limit", validation_error("invalid_limit", message));
+        }
+        let cursor = match cursor {
+            Some(cursor) => {
+                let parsed = parse_hex(&cursor);
+                if parsed.is_none() {
+                    errors.add(
+                        "cursor",
+                        validation_error("invalid_cursor", format!("{} is not a valid cursor", cursor)),
+                    );
+                }
+                parsed
+            }
+            None => None,
+        };
+        if !errors.is_empty() {
+            return Box::new(future::err(
+                ectx!(err ErrorContext::History, ErrorKind::InvalidInput(errors) => currency, address),
+            ));
+        }
+        let (transactions, next_cursor) = match index
+            .transactions(currency, &address, cursor, limit)
+            .map_err(ectx!(ErrorSource::History, ErrorKind::Internal => currency, address))
+        {
+            Ok(page) => page,
+            Err(e) => return Box::new(future::err(e)),
+        };
+        let next_cursor = next_cursor.map(|cursor| bytes_to_hex(&cursor));
+        if transactions.is_empty() {
+            return Box::new(future::ok(TransactionsPage { transactions, next_cursor }));
+        }
+        // index doesn't store confirmations, as they change with each block
+        Box::new(self.confirmations_base(currency).map(move |base| {
+            TransactionsPage {
+                transactions: transactions
+                    .into_iter()
+                    .map(|tx| {
+                        let confirmations = tx.block_number.map_or(0, |block_number| base.saturating_sub(block_number)) as usize;
+                        BlockchainTransaction { confirmations, ..tx }
+                    })
+                    .collect(),
+                next_cursor,
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use history::SledHistoryIndex;
+    use mocks::{BitcoinClientMock, EthereumClientMock};
+
+    #[test]
+    fn test_confirmations() {
+        let index = SledHistoryIndex::temporary().unwrap();
+        let tx = BlockchainTransaction {
+            hash: "a1".to_string(),
+            from: vec!["aa".to_string()],
+            to: vec![BlockchainTransactionEntry {
+                address: "bb".to_string(),
+                value: Amount::new(1),
+            }],
+            block_number: Some(10),
+            block_hash: None,
+            block_time: None,
+            currency: Currency::Eth,
+            fee: Amount::new(0),
+            confirmations: 0,
+            erc20_operation_kind: None,
+            contract_deployment: false,
+        };
+        index.index(&tx).unwrap();
+        let ethereum_client = EthereumClientMock::new();
+        let service = HistoryServiceImpl::new(
+            Some(Arc::new(index)),
+            Arc::new(BitcoinClientMock::new()),
+            Arc::new(ethereum_client.clone()),
+        );
+
+        ethereum_client.set_block_number(12);
+        let page = service
+            .get_transactions(Currency::Eth, "aa".to_string(), None, None)
+            .wait()
+            .unwrap();
+        assert_eq!(page.transactions[0].confirmations, 2);
+        ethereum_client.set_block_number(15);
+        let page = service
+            .get_transactions(Currency::Eth, "bb".to_string(), None, None)
+            .wait()
+            .unwrap();
+        assert_eq!(page.transactions[0].confirmations, 5);
+    }
+}
@@ -1 +1,16 @@
+use std::collections::BTreeMap;
 
+use super::error::*;
+use super::health::HealthService;
+use models::*;
+use prelude::*;
+
+/// Health service without dependencies, always ready
+#[derive(Clone, Default)]
+pub struct HealthServiceMock;
+
+impl HealthService for HealthServiceMock {
+    fn readiness(&self) -> Box<Future<Item = Readiness, Error = Error> + Send> {
+        Box::new(Ok(Readiness::new(BTreeMap::new())).into_future())
+    }
+}
@@ -1,11 +1,25 @@
+mod backfills;
 mod bitcoin;
+mod bitcoin_transaction;
+mod blocks;
+mod broadcasts;
 mod error;
 mod ethereum;
+mod ethereum_transaction;
+mod health;
+mod history;
 #[cfg(test)]
 mod mocks;
+mod nonces;
 
+pub use self::backfills::*;
 pub use self::bitcoin::*;
+pub use self::blocks::*;
+pub use self::broadcasts::*;
 pub use self::error::*;
 pub use self::ethereum::*;
+pub use self::health::*;
+pub use self::history::*;
 #[cfg(test)]
 pub use self::mocks::*;
+pub use self::nonces::*;
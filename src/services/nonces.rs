@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+
+use chrono::{self, DateTime, Utc};
+
+use super::error::*;
+use client::EthereumClient;
+use config::NonceReservation;
+use models::*;
+use prelude::*;
+
+/// Max number of gaps reported, so that a nonce posted far ahead doesn't produce a huge response
+const MAX_REPORTED_GAPS: usize = 100;
+
+pub trait NonceService: Send + Sync + 'static {
+    /// Hands out the lowest nonce of address above transactions in node's mempool that is not reserved or posted before
+    fn reserve_nonce(&self, address: EthereumAddress) -> Box<Future<Item = u64, Error = Error> + Send>;
+    /// Records nonce of transaction posted through API
+    fn record_posted(&self, address: EthereumAddress, nonce: u64);
+    /// Nonces tracked for address, with gaps that block mining of transactions with higher nonces
+    fn get_nonces(&self, address: EthereumAddress) -> Box<Future<Item = NonceState, Error = Error> + Send>;
+    /// Forgets reserved and posted nonces of address, so that the next nonce is taken from chain state
+    fn reset_nonces(&self, address: EthereumAddress) -> Box<Future<Item = NonceState, Error = Error> + Send>;
+}
+
+#[derive(Clone)]
+pub struct NonceServiceImpl {
+    client: Arc<EthereumClient>,
+    config: NonceReservation,
+    nonces: Arc<Mutex<HashMap<String, AddressNonces>>>,
+}
+
+impl NonceServiceImpl {
+    pub fn new(client: Arc<EthereumClient>, config: NonceReservation) -> Self {
+        Self {
+            client,
+            config,
+            nonces: Default::default(),
+        }
+    }
+
+    /// Syncs nonces of address with chain state and applies `f` to them
+    fn with_chain_state<F, T>(&self, address: EthereumAddress, f: F) -> Box<Future<Item = T, Error = Error> + Send>
+    where
+        F: FnOnce(&mut AddressNonces, &EthereumAddress, u64, u64) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let nonces = self.nonces.clone();
+        let ttl = chrono::Duration::seconds(self.config.ttl_secs as i64);
+        let address_clone = address.clone();
+        Box::new(
+            self.client
+                .get_nonce(address.clone(), BlockTag::Latest)
+                .join(self.client.get_nonce(address.clone(), BlockTag::Pending))
+                .map_err(ectx!(convert => address_clone))
+                .map(move |(latest, pending)| {
+                    let mut nonces = nonces.lock().unwrap();
+                    let entry = nonces.entry(address.to_string()).or_insert_with(Default::default);
+                    entry.sync(latest, pending, Utc::now() - ttl);
+                    f(entry, &address, latest, pending)
+                }),
+        )
+    }
+}
+
+impl NonceService for NonceServiceImpl {
+    fn reserve_nonce(&self, address: EthereumAddress) -> Box<Future<Item = u64, Error = Error> + Send> {
+        self.with_chain_state(address, |nonces, _, _, pending| nonces.reserve(pending, Utc::now()))
+    }
+
+    fn record_posted(&self, address: EthereumAddress, nonce: u64) {
+        let mut nonces = self.nonces.lock().unwrap();
+        nonces
+            .entry(address.to_string())
+            .or_insert_with(Default::default)
+            .record_posted(nonce);
+    }
+
+    fn get_nonces(&self, address: EthereumAddress) -> Box<Future<Item = NonceState, Error = Error> + Send> {
+        self.with_chain_state(address, |nonces, address, latest, pending| nonces.state(address, latest, pending))
+    }
+
+    fn reset_nonces(&self, address: EthereumAddress) -> Box<Future<Item = NonceState, Error = Error> + Send> {
+        self.with_chain_state(address, |nonces, address, latest, pending| {
+            *nonces = AddressNonces::default();
+            nonces.next = pending;
+            nonces.state(address, latest, pending)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct AddressNonces {
+    next: u64,
+    /// Reserved nonces with time of reservation
+    reserved: BTreeMap<u64, DateTime<Utc>>,
+    posted: BTreeSet<u64>,
+}
+
+impl AddressNonces {
+    /// Forgets mined transactions, reservations used outside of gateway and expired reservations
+    fn sync(&mut self, latest: u64, pending: u64, expired_before: DateTime<Utc>) {
+        self.posted = self.posted.split_off(&latest);
+        self.reserved = self.reserved.split_off(&pending);
+        self.reserved.retain(|_, reserved_at| *reserved_at >= expired_before);
+        self.next = self.next.max(pending);
+    }
+
+    /// Hands out the lowest nonce not in mempool, reserved or posted, so that gaps of expired reservations are filled first
+    fn reserve(&mut self, pending: u64, now: DateTime<Utc>) -> u64 {
+        let nonce = (pending..self.next).find(|nonce| self.is_free(*nonce)).unwrap_or(self.next);
+        self.reserved.insert(nonce, now);
+        self.next = self.next.max(nonce + 1);
+        nonce
+    }
+
+    fn is_free(&self, nonce: u64) -> bool {
+        !self.reserved.contains_key(&nonce) && !self.posted.contains(&nonce)
+    }
+
+    fn record_posted(&mut self, nonce: u64) {
+        self.reserved.remove(&nonce);
+        self.posted.insert(nonce);
+        self.next = self.next.max(nonce + 1);
+    }
+
+    fn state(&self, address: &EthereumAddress, latest: u64, pending: u64) -> NonceState {
+        let gaps = (pending..self.next)
+            .filter(|nonce| self.is_free(*nonce))
+            .take(MAX_REPORTED_GAPS)
+            .collect();
+        NonceState {
+            address: address.clone(),
+            latest,
+            pending,
+            next: self.next,
+            reserved: self.reserved.keys().cloned().collect(),
+            posted: self.posted.iter().cloned().collect(),
+            gaps,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_nonces() {
+        let address = EthereumAddress::new("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f".to_string());
+        let now = Utc::now();
+        let mut nonces = AddressNonces::default();
+        nonces.sync(3, 4, now);
+        assert_eq!((nonces.reserve(4, now), nonces.reserve(4, now), nonces.reserve(4, now)), (4, 5, 6));
+        nonces.record_posted(4);
+        nonces.record_posted(6);
+        let state = nonces.state(&address, 3, 4);
+        assert_eq!(
+            (state.next, state.reserved, state.posted, state.gaps),
+            (7, vec![5], vec![4, 6], vec![])
+        );
+
+        // reservation of 5 expired, 4 got to mempool
+        nonces.sync(3, 5, now + chrono::Duration::seconds(1));
+        let state = nonces.state(&address, 3, 5);
+        assert_eq!(
+            (state.next, state.reserved, state.posted, state.gaps),
+            (7, vec![], vec![4, 6], vec![5])
+        );
+        // the gap is handed out before nonces above posted ones
+        assert_eq!((nonces.reserve(5, now), nonces.reserve(5, now)), (5, 7));
+        let state = nonces.state(&address, 3, 5);
+        assert_eq!(
+            (state.next, state.reserved, state.posted, state.gaps),
+            (8, vec![5, 7], vec![4, 6], vec![])
+        );
+
+        // 4 to 9 are mined, e.g. sent bypassing gateway
+        nonces.sync(10, 10, now);
+        let state = nonces.state(&address, 10, 10);
+        assert_eq!((state.next, state.reserved, state.posted, state.gaps), (10, vec![], vec![], vec![]));
+        assert_eq!(nonces.reserve(10, now), 10);
+    }
+}
@@ -0,0 +1,41 @@
+use failure::{Backtrace, Context, Fail};
+use std::fmt;
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub struct Error {
+    inner: Context<ErrorKind>,
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Fail)]
+pub enum ErrorKind {
+    #[fail(display = "storage error - internal error")]
+    Internal,
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Fail)]
+pub enum ErrorSource {
+    #[fail(display = "storage error source - diesel")]
+    Diesel,
+    #[fail(display = "storage error source - r2d2")]
+    R2D2,
+    #[fail(display = "storage error source - serde_json")]
+    SerdeJson,
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Fail)]
+pub enum ErrorContext {
+    #[fail(display = "storage error context - error connecting to database")]
+    Connect,
+    #[fail(display = "storage error context - error running migrations")]
+    Migrations,
+    #[fail(display = "storage error context - error reading database")]
+    Read,
+    #[fail(display = "storage error context - error writing database")]
+    Write,
+}
+
+derive_error_impls!();
@@ -0,0 +1,535 @@
+//! Database of scanned block headers, published transactions, poller cursors and transactions posted through API.
+//!
+//! Postgres is used for `postgres://` urls and SQLite for `sqlite://` ones, migrations of both are embedded
+//! and run on start. Queries are written once with diesel DSL and `with_connection!` type checks them
+//! against both backends. Rows are replaced with update, falling back to insert, as diesel has no upsert
+//! common to both backends.
+
+mod error;
+mod schema;
+
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::sqlite::SqliteConnection;
+use futures::future;
+use futures_cpupool::CpuPool;
+use serde_json;
+
+pub use self::error::*;
+use self::schema::{blocks, broadcasts, poller_cursors, transactions};
+use config::Database;
+use models::*;
+use prelude::*;
+
+mod postgres_migrations {
+    embed_migrations!("migrations/postgres");
+
+    pub use self::embedded_migrations::run;
+}
+
+mod sqlite_migrations {
+    embed_migrations!("migrations/sqlite");
+
+    pub use self::embedded_migrations::run;
+}
+
+pub trait Storage: Send + Sync + 'static {
+    /// Saves published transactions with headers of their blocks. Transactions and blocks saved again,
+    /// e.g. after reorg, replace the previous ones
+    fn save_transactions(&self, txs: Vec<BlockchainTransaction>) -> Box<Future<Item = (), Error = Error> + Send>;
+    /// Header of block at height with transactions published from it, `None` if block is not saved
+    fn get_block(&self, currency: Currency, height: u64) -> Box<Future<Item = Option<ScannedBlock>, Error = Error> + Send>;
+    /// Whether transaction is saved, i.e. it was published from a scanned block
+    fn has_transaction(&self, currency: Currency, hash: String) -> Box<Future<Item = bool, Error = Error> + Send>;
+    /// Number of the latest block scanned by poller of currency
+    fn get_cursor(&self, currency: Currency) -> Box<Future<Item = Option<u64>, Error = Error> + Send>;
+    fn set_cursor(&self, currency: Currency, block_number: u64) -> Box<Future<Item = (), Error = Error> + Send>;
+    /// Saves transaction posted through API with its tracked status, replacing the previously saved one
+    fn save_broadcast(&self, broadcast: Broadcast) -> Box<Future<Item = (), Error = Error> + Send>;
+    /// Transactions posted through API that are pending or reached final status at or after `time`
+    fn get_broadcasts(&self, time: DateTime<Utc>) -> Box<Future<Item = Vec<Broadcast>, Error = Error> + Send>;
+    /// Deletes transactions posted through API that reached final status before `time`
+    fn delete_broadcasts(&self, time: DateTime<Utc>) -> Box<Future<Item = (), Error = Error> + Send>;
+}
+
+#[derive(Clone)]
+enum DbPool {
+    Postgres(Pool<ConnectionManager<PgConnection>>),
+    Sqlite(Pool<ConnectionManager<SqliteConnection>>),
+}
+
+/// Runs `$body` with `$conn` bound to a pooled connection of either backend
+macro_rules! with_connection {
+    ($pool:expr, $conn:ident => $body:expr) => {
+        match $pool {
+            DbPool::Postgres(ref pool) => {
+                let $conn = &*pool.get().map_err(ectx!(try ErrorSource::R2D2, ErrorContext::Connect, ErrorKind::Internal))?;
+                $body
+            }
+            DbPool::Sqlite(ref pool) => {
+                let $conn = &*pool.get().map_err(ectx!(try ErrorSource::R2D2, ErrorContext::Connect, ErrorKind::Internal))?;
+                $body
+            }
+        }
+    };
+}
+
+#[derive(Insertable)]
+#[table_name = "blocks"]
+struct BlockRow {
+    currency: String,
+    height: i64,
+    hash: String,
+    block_time: Option<NaiveDateTime>,
+    scanned_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "transactions"]
+struct TransactionRow {
+    currency: String,
+    hash: String,
+    block_number: Option<i64>,
+    block_hash: Option<String>,
+    payload: String,
+    published_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "poller_cursors"]
+struct CursorRow {
+    currency: String,
+    block_number: i64,
+    updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "broadcasts"]
+struct BroadcastRow {
+    currency: String,
+    hash: String,
+    status: String,
+    payload: String,
+    updated_at: NaiveDateTime,
+}
+
+/// Tracked transaction with the signed one, that is not exposed by API, so that it can be sent again after restart
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BroadcastPayload {
+    hash: TxHash,
+    currency: Currency,
+    status: BroadcastStatus,
+    stuck: bool,
+    rebroadcasts: u32,
+    submitted_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    transaction: BroadcastTransaction,
+}
+
+impl From<Broadcast> for BroadcastPayload {
+    fn from(broadcast: Broadcast) -> Self {
+        BroadcastPayload {
+            hash: broadcast.hash,
+            currency: broadcast.currency,
+            status: broadcast.status,
+            stuck: broadcast.stuck,
+            rebroadcasts: broadcast.rebroadcasts,
+            submitted_at: broadcast.submitted_at,
+            updated_at: broadcast.updated_at,
+            transaction: broadcast.transaction,
+        }
+    }
+}
+
+impl From<BroadcastPayload> for Broadcast {
+    fn from(payload: BroadcastPayload) -> Self {
+        Broadcast {
+            hash: payload.hash,
+            currency: payload.currency,
+            status: payload.status,
+            stuck: payload.stuck,
+            rebroadcasts: payload.rebroadcasts,
+            submitted_at: payload.submitted_at,
+            updated_at: payload.updated_at,
+            transaction: payload.transaction,
+        }
+    }
+}
+
+/// Storage backed by Postgres or SQLite, blocking queries run on a separate thread pool
+#[derive(Clone)]
+pub struct DbStorage {
+    pool: DbPool,
+    cpu_pool: CpuPool,
+}
+
+impl DbStorage {
+    /// Connects to database and runs pending migrations
+    pub fn connect(config: &Database) -> Result<Self, Error> {
+        let size = config.thread_pool_size.max(1);
+        let pool = if config.url.starts_with("postgres://") || config.url.starts_with("postgresql://") {
+            let pool = Pool::builder()
+                .max_size(size as u32)
+                .build(ConnectionManager::<PgConnection>::new(config.url.clone()))
+                .map_err(ectx!(try ErrorSource::R2D2, ErrorContext::Connect, ErrorKind::Internal))?;
+            DbPool::Postgres(pool)
+        } else if config.url.starts_with("sqlite://") {
+            let path = &config.url["sqlite://".len()..];
+            if let Some(dir) = Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir).map_err(ectx!(try ErrorContext::Connect, ErrorKind::Internal => dir.to_path_buf()))?;
+            }
+            // SQLite allows a single writer, concurrent writes would fail with `database is locked`
+            let pool = Pool::builder()
+                .max_size(1)
+                .build(ConnectionManager::<SqliteConnection>::new(path))
+                .map_err(ectx!(try ErrorSource::R2D2, ErrorContext::Connect, ErrorKind::Internal))?;
+            DbPool::Sqlite(pool)
+        } else {
+            return Err(ectx!(err ErrorContext::Connect, ErrorKind::Internal => "database url must start with postgres:// or sqlite://"));
+        };
+        match pool {
+            DbPool::Postgres(ref pool) => {
+                let conn = pool
+                    .get()
+                    .map_err(ectx!(try ErrorSource::R2D2, ErrorContext::Connect, ErrorKind::Internal))?;
+                postgres_migrations::run(&*conn).map_err(ectx!(try ErrorContext::Migrations, ErrorKind::Internal))?;
+            }
+            DbPool::Sqlite(ref pool) => {
+                let conn = pool
+                    .get()
+                    .map_err(ectx!(try ErrorSource::R2D2, ErrorContext::Connect, ErrorKind::Internal))?;
+                sqlite_migrations::run(&*conn).map_err(ectx!(try ErrorContext::Migrations, ErrorKind::Internal))?;
+            }
+        }
+        Ok(Self {
+            pool,
+            cpu_pool: CpuPool::new(size),
+        })
+    }
+
+    fn execute<F, T>(&self, f: F) -> Box<Future<Item = T, Error = Error> + Send>
+    where
+        F: FnOnce(&DbPool) -> Result<T, Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        Box::new(self.cpu_pool.spawn_fn(move || f(&pool)))
+    }
+}
+
+impl Storage for DbStorage {
+    fn save_transactions(&self, txs: Vec<BlockchainTransaction>) -> Box<Future<Item = (), Error = Error> + Send> {
+        let now = Utc::now().naive_utc();
+        let mut block_rows: Vec<BlockRow> = Vec::new();
+        let mut tx_rows = Vec::new();
+        for tx in txs {
+            if let (Some(block_number), Some(block_hash)) = (tx.block_number, tx.block_hash.clone()) {
+                let currency = tx.currency.to_string();
+                if !block_rows.iter().any(|row| row.currency == currency && row.hash == block_hash) {
+                    block_rows.push(BlockRow {
+                        currency,
+                        height: block_number as i64,
+                        hash: block_hash,
+                        block_time: tx.block_time.map(|time| time.naive_utc()),
+                        scanned_at: now,
+                    });
+                }
+            }
+            let payload = match serde_json::to_string(&tx) {
+                Ok(payload) => payload,
+                Err(e) => return Box::new(future::err(ectx!(err e, ErrorSource::SerdeJson, ErrorKind::Internal => tx.hash))),
+            };
+            tx_rows.push(TransactionRow {
+                currency: tx.currency.to_string(),
+                hash: tx.hash,
+                block_number: tx.block_number.map(|number| number as i64),
+                block_hash: tx.block_hash,
+                payload,
+                published_at: now,
+            });
+        }
+        self.execute(move |pool| {
+            with_connection!(pool, conn => conn
+                .transaction::<_, diesel::result::Error, _>(|| {
+                    for row in &block_rows {
+                        let updated = diesel::update(blocks::table.find((&row.currency, row.height)))
+                            .set((
+                                blocks::hash.eq(&row.hash),
+                                blocks::block_time.eq(row.block_time),
+                                blocks::scanned_at.eq(row.scanned_at),
+                            ))
+                            .execute(conn)?;
+                        if updated == 0 {
+                            diesel::insert_into(blocks::table).values(row).execute(conn)?;
+                        }
+                    }
+                    for row in &tx_rows {
+                        let updated = diesel::update(transactions::table.find((&row.currency, &row.hash)))
+                            .set((
+                                transactions::block_number.eq(row.block_number),
+                                transactions::block_hash.eq(&row.block_hash),
+                                transactions::payload.eq(&row.payload),
+                                transactions::published_at.eq(row.published_at),
+                            ))
+                            .execute(conn)?;
+                        if updated == 0 {
+                            diesel::insert_into(transactions::table).values(row).execute(conn)?;
+                        }
+                    }
+                    Ok(())
+                })
+                .map_err(ectx!(ErrorSource::Diesel, ErrorContext::Write, ErrorKind::Internal)))
+        })
+    }
+
+    fn get_block(&self, currency: Currency, height: u64) -> Box<Future<Item = Option<ScannedBlock>, Error = Error> + Send> {
+        self.execute(move |pool| {
+            let currency_label = currency.to_string();
+            let block: Option<(String, Option<NaiveDateTime>)> = with_connection!(pool, conn => blocks::table
+                .find((&currency_label, height as i64))
+                .select((blocks::hash, blocks::block_time))
+                .first(conn)
+                .optional()
+                .map_err(ectx!(try ErrorSource::Diesel, ErrorContext::Read, ErrorKind::Internal => currency, height)))?;
+            let (hash, block_time) = match block {
+                Some(block) => block,
+                None => return Ok(None),
+            };
+            let hash_clone = hash.clone();
+            // transactions of a block replaced by reorg keep hash of their old block
+            let payloads: Vec<String> = with_connection!(pool, conn => transactions::table
+                .filter(transactions::currency.eq(&currency_label))
+                .filter(transactions::block_hash.eq(&hash))
+                .order(transactions::hash.asc())
+                .select(transactions::payload)
+                .load(conn)
+                .map_err(ectx!(try ErrorSource::Diesel, ErrorContext::Read, ErrorKind::Internal => currency, hash_clone)))?;
+            let transactions = payloads
+                .iter()
+                .map(|payload| serde_json::from_str(payload).map_err(ectx!(ErrorSource::SerdeJson, ErrorKind::Internal => payload)))
+                .collect::<Result<Vec<BlockchainTransaction>, Error>>()?;
+            Ok(Some(ScannedBlock {
+                currency,
+                height,
+                hash,
+                time: block_time.map(|time| Utc.from_utc_datetime(&time)),
+                transactions,
+            }))
+        })
+    }
+
+    fn has_transaction(&self, currency: Currency, hash: String) -> Box<Future<Item = bool, Error = Error> + Send> {
+        self.execute(move |pool| {
+            let currency_label = currency.to_string();
+            let saved: Option<String> = with_connection!(pool, conn => transactions::table
+                .find((&currency_label, &hash))
+                .select(transactions::hash)
+                .first(conn)
+                .optional()
+                .map_err(ectx!(try ErrorSource::Diesel, ErrorContext::Read, ErrorKind::Internal => currency, hash)))?;
+            Ok(saved.is_some())
+        })
+    }
+
+    fn get_cursor(&self, currency: Currency) -> Box<Future<Item = Option<u64>, Error = Error> + Send> {
+        self.execute(move |pool| {
+            let currency_label = currency.to_string();
+            let block_number: Option<i64> = with_connection!(pool, conn => poller_cursors::table
+                .find(&currency_label)
+                .select(poller_cursors::block_number)
+                .first(conn)
+                .optional()
+                .map_err(ectx!(try ErrorSource::Diesel, ErrorContext::Read, ErrorKind::Internal => currency)))?;
+            Ok(block_number.map(|number| number as u64))
+        })
+    }
+
+    fn set_cursor(&self, currency: Currency, block_number: u64) -> Box<Future<Item = (), Error = Error> + Send> {
+        let row = CursorRow {
+            currency: currency.to_string(),
+            block_number: block_number as i64,
+            updated_at: Utc::now().naive_utc(),
+        };
+        self.execute(move |pool| {
+            with_connection!(pool, conn => conn
+                .transaction::<_, diesel::result::Error, _>(|| {
+                    let updated = diesel::update(poller_cursors::table.find(&row.currency))
+                        .set((poller_cursors::block_number.eq(row.block_number), poller_cursors::updated_at.eq(row.updated_at)))
+                        .execute(conn)?;
+                    if updated == 0 {
+                        diesel::insert_into(poller_cursors::table).values(&row).execute(conn)?;
+                    }
+                    Ok(())
+                })
+                .map_err(ectx!(ErrorSource::Diesel, ErrorContext::Write, ErrorKind::Internal => currency, block_number)))
+        })
+    }
+
+    fn save_broadcast(&self, broadcast: Broadcast) -> Box<Future<Item = (), Error = Error> + Send> {
+        let hash = broadcast.hash.clone();
+        let currency = broadcast.currency.to_string();
+        let status = broadcast.status.to_string();
+        let updated_at = broadcast.updated_at.naive_utc();
+        let payload = match serde_json::to_string(&BroadcastPayload::from(broadcast)) {
+            Ok(payload) => payload,
+            Err(e) => return Box::new(future::err(ectx!(err e, ErrorSource::SerdeJson, ErrorKind::Internal => hash))),
+        };
+        let row = BroadcastRow {
+            currency,
+            hash: hash.to_string(),
+            status,
+            payload,
+            updated_at,
+        };
+        self.execute(move |pool| {
+            with_connection!(pool, conn => conn
+                .transaction::<_, diesel::result::Error, _>(|| {
+                    let updated = diesel::update(broadcasts::table.find((&row.currency, &row.hash)))
+                        .set((
+                            broadcasts::status.eq(&row.status),
+                            broadcasts::payload.eq(&row.payload),
+                            broadcasts::updated_at.eq(row.updated_at),
+                        ))
+                        .execute(conn)?;
+                    if updated == 0 {
+                        diesel::insert_into(broadcasts::table).values(&row).execute(conn)?;
+                    }
+                    Ok(())
+                })
+                .map_err(ectx!(ErrorSource::Diesel, ErrorContext::Write, ErrorKind::Internal => hash)))
+        })
+    }
+
+    fn get_broadcasts(&self, time: DateTime<Utc>) -> Box<Future<Item = Vec<Broadcast>, Error = Error> + Send> {
+        self.execute(move |pool| {
+            let pending = BroadcastStatus::Pending.to_string();
+            let payloads: Vec<String> = with_connection!(pool, conn => broadcasts::table
+                .filter(broadcasts::status.eq(&pending).or(broadcasts::updated_at.ge(time.naive_utc())))
+                .select(broadcasts::payload)
+                .load(conn)
+                .map_err(ectx!(try ErrorSource::Diesel, ErrorContext::Read, ErrorKind::Internal => time)))?;
+            payloads
+                .iter()
+                .map(|payload| {
+                    serde_json::from_str::<BroadcastPayload>(payload)
+                        .map(Broadcast::from)
+                        .map_err(ectx!(ErrorSource::SerdeJson, ErrorKind::Internal => payload))
+                })
+                .collect()
+        })
+    }
+
+    fn delete_broadcasts(&self, time: DateTime<Utc>) -> Box<Future<Item = (), Error = Error> + Send> {
+        self.execute(move |pool| {
+            let pending = BroadcastStatus::Pending.to_string();
+            with_connection!(pool, conn => diesel::delete(
+                broadcasts::table
+                    .filter(broadcasts::status.ne(&pending))
+                    .filter(broadcasts::updated_at.lt(time.naive_utc())),
+            )
+            .execute(conn)
+            .map(|_| ())
+            .map_err(ectx!(ErrorSource::Diesel, ErrorContext::Write, ErrorKind::Internal => time)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_sqlite_storage() {
+        let storage = DbStorage::connect(&Database {
+            url: "sqlite://:memory:".to_string(),
+            thread_pool_size: 1,
+        })
+        .unwrap();
+        let tx = BlockchainTransaction {
+            hash: "5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060".to_string(),
+            from: vec!["1BoatSLRHtKNngkdXEeobR76b53LETtpyT".to_string()],
+            to: vec![],
+            block_number: Some(100),
+            block_hash: Some("00000000000000000015".to_string()),
+            block_time: Some(Utc.timestamp_opt(1_550_000_000, 0).unwrap()),
+            currency: Currency::Btc,
+            fee: Amount::new(1000),
+            confirmations: 1,
+            erc20_operation_kind: None,
+            contract_deployment: false,
+        };
+        storage.save_transactions(vec![tx.clone()]).wait().unwrap();
+        let block = storage.get_block(Currency::Btc, 100).wait().unwrap().unwrap();
+        assert_eq!((block.hash.as_str(), block.transactions.len()), ("00000000000000000015", 1));
+        assert!(storage.get_block(Currency::Eth, 100).wait().unwrap().is_none());
+        assert!(storage.has_transaction(Currency::Btc, tx.hash.clone()).wait().unwrap());
+        assert!(!storage.has_transaction(Currency::Eth, tx.hash.clone()).wait().unwrap());
+
+        // reorg replaced the block, transaction is saved again with the new one
+        let moved = BlockchainTransaction {
+            block_hash: Some("00000000000000000016".to_string()),
+            ..tx
+        };
+        storage.save_transactions(vec![moved]).wait().unwrap();
+        let block = storage.get_block(Currency::Btc, 100).wait().unwrap().unwrap();
+        assert_eq!((block.hash.as_str(), block.transactions.len()), ("00000000000000000016", 1));
+
+        assert_eq!(storage.get_cursor(Currency::Btc).wait().unwrap(), None);
+        storage.set_cursor(Currency::Btc, 100).wait().unwrap();
+        storage.set_cursor(Currency::Btc, 101).wait().unwrap();
+        assert_eq!(storage.get_cursor(Currency::Btc).wait().unwrap(), Some(101));
+    }
+
+    #[test]
+    fn test_sqlite_broadcasts() {
+        let storage = DbStorage::connect(&Database {
+            url: "sqlite://:memory:".to_string(),
+            thread_pool_size: 1,
+        })
+        .unwrap();
+        let submitted_at = Utc.timestamp_opt(1_550_000_000, 0).unwrap();
+        let mut broadcast = Broadcast {
+            hash: TxHash::new("5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060".to_string()),
+            currency: Currency::Eth,
+            status: BroadcastStatus::Pending,
+            stuck: false,
+            rebroadcasts: 0,
+            submitted_at,
+            updated_at: submitted_at,
+            transaction: BroadcastTransaction::Ethereum {
+                raw: serde_json::from_value(json!("f86b")).unwrap(),
+                from: EthereumAddress::new("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f".to_string()),
+                nonce: 3,
+            },
+        };
+        storage.save_broadcast(broadcast.clone()).wait().unwrap();
+        broadcast.rebroadcasts = 1;
+        storage.save_broadcast(broadcast.clone()).wait().unwrap();
+        let now = submitted_at + Duration::seconds(1000);
+        let saved = storage.get_broadcasts(now).wait().unwrap();
+        assert_eq!(saved.len(), 1, "pending transactions are loaded regardless of time");
+        assert_eq!((saved[0].hash.clone(), saved[0].rebroadcasts), (broadcast.hash.clone(), 1));
+        match saved[0].transaction {
+            BroadcastTransaction::Ethereum { nonce, .. } => assert_eq!(nonce, 3),
+            ref transaction => panic!("unexpected transaction {:?}", transaction),
+        }
+
+        broadcast.status = BroadcastStatus::Mined;
+        broadcast.updated_at = now;
+        storage.save_broadcast(broadcast).wait().unwrap();
+        assert_eq!(storage.get_broadcasts(now).wait().unwrap().len(), 1);
+        assert_eq!(storage.get_broadcasts(now + Duration::seconds(1)).wait().unwrap().len(), 0);
+        storage.delete_broadcasts(now).wait().unwrap();
+        assert_eq!(storage.get_broadcasts(now).wait().unwrap().len(), 1);
+        storage.delete_broadcasts(now + Duration::seconds(1)).wait().unwrap();
+        assert_eq!(storage.get_broadcasts(now).wait().unwrap().len(), 0);
+    }
+}
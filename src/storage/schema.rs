@@ -0,0 +1,38 @@
+table! {
+    blocks (currency, height) {
+        currency -> Text,
+        height -> BigInt,
+        hash -> Text,
+        block_time -> Nullable<Timestamp>,
+        scanned_at -> Timestamp,
+    }
+}
+
+table! {
+    transactions (currency, hash) {
+        currency -> Text,
+        hash -> Text,
+        block_number -> Nullable<BigInt>,
+        block_hash -> Nullable<Text>,
+        payload -> Text,
+        published_at -> Timestamp,
+    }
+}
+
+table! {
+    poller_cursors (currency) {
+        currency -> Text,
+        block_number -> BigInt,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    broadcasts (currency, hash) {
+        currency -> Text,
+        hash -> Text,
+        status -> Text,
+        payload -> Text,
+        updated_at -> Timestamp,
+    }
+}
@@ -52,6 +52,17 @@ pub fn log_warn<E: Fail>(error: &E) {
     warn!("\n{}", format_error(error));
 }
 
+/// Decodes hex string without `0x` prefix, `None` if it's not valid hex
+pub fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
 // Reads body of request in Future format
 pub fn read_body(body: hyper::Body) -> impl Future<Item = Vec<u8>, Error = hyper::Error> {
     body.fold(Vec::new(), |mut acc, chunk| {